@@ -0,0 +1,204 @@
+//! Microbenchmarks of ouch's hot paths, run them with `cargo bench`.
+//!
+//! - `compression/*` and `decompression/*`: throughput of each single file format, in memory.
+//! - `walk/*`: directory walking and archive entry building, without compression.
+//!
+//! Filter benchmarks by name with `cargo bench -- <filter>`, e.g. `cargo bench -- compression/zst`.
+
+use std::{ffi::OsStr, io, path::Path};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use fs_err as fs;
+use ouch::{
+    archive::{self, EntryModes},
+    cli::{Eol, Lz4Format},
+    commands::{compress::chain_writer_encoder, decompress::chain_reader_decoder},
+    extension::{parse_format_flag, CompressionFormat},
+    utils::{logger::spawn_logger_thread, FileVisibilityPolicy},
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// Size of the synthetic input of the single file formats
+const INPUT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Single file formats that support compression
+const FORMATS: &[&str] = &[
+    "gz",
+    "bz2",
+    #[cfg(feature = "bzip3")]
+    "bz3",
+    "lz4",
+    "xz",
+    "sz",
+    "zst",
+    "br",
+];
+
+/// Levels measured for each format, `None` stands for the format's default level
+const LEVELS: &[Option<i16>] = &[Some(1), None];
+
+fn level_name(level: Option<i16>) -> String {
+    level.map_or_else(|| "default".to_string(), |level| format!("level-{level}"))
+}
+
+fn compression_format(format: &str) -> CompressionFormat {
+    parse_format_flag(OsStr::new(format)).unwrap()[0].compression_formats[0]
+}
+
+/// Text-like data, compressible but not trivially so.
+fn synthetic_data(size: usize) -> Vec<u8> {
+    const WORDS: &[&[u8]] = &[
+        b"ouch",
+        b"compress",
+        b"archive",
+        b"lorem",
+        b"ipsum",
+        b"dolor",
+        b"tar",
+        b"zip",
+        b"\n",
+        b"0123456789",
+    ];
+
+    let mut rng = SmallRng::seed_from_u64(0);
+    let mut data = Vec::with_capacity(size + 16);
+    while data.len() < size {
+        data.extend_from_slice(WORDS[rng.gen_range(0..WORDS.len())]);
+        data.push(b' ');
+    }
+    data.truncate(size);
+    data
+}
+
+fn compress(format: CompressionFormat, level: Option<i16>, input: &[u8], output: impl io::Write + Send + 'static) {
+    let mut writer = chain_writer_encoder(&format, Box::new(output), level, Lz4Format::Frame, None).unwrap();
+    writer.write_all(input).unwrap();
+    writer.flush().unwrap();
+    // Encoders finish their streams when dropped
+    drop(writer);
+}
+
+fn compression(c: &mut Criterion) {
+    let input = synthetic_data(INPUT_SIZE);
+
+    let mut group = c.benchmark_group("compression");
+    group.throughput(Throughput::Bytes(INPUT_SIZE as u64));
+    group.sample_size(10);
+
+    for format in FORMATS {
+        for &level in LEVELS {
+            group.bench_with_input(BenchmarkId::new(*format, level_name(level)), &level, |b, &level| {
+                b.iter(|| compress(compression_format(format), level, &input, io::sink()))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn decompression(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let input = synthetic_data(INPUT_SIZE);
+
+    let mut group = c.benchmark_group("decompression");
+    group.throughput(Throughput::Bytes(INPUT_SIZE as u64));
+    group.sample_size(10);
+
+    for format in FORMATS {
+        let compressed_path = dir.path().join(format!("input.{format}"));
+        compress(
+            compression_format(format),
+            None,
+            &input,
+            fs::File::create(&compressed_path).unwrap(),
+        );
+        let compressed = fs::read(&compressed_path).unwrap();
+
+        group.bench_function(*format, |b| {
+            b.iter(|| {
+                let reader = Box::new(io::Cursor::new(compressed.clone()));
+                let mut reader = chain_reader_decoder(&compression_format(format), reader).unwrap();
+                io::copy(&mut reader, &mut io::sink()).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+/// Create `dirs` directories holding `files_per_dir` small files each
+fn create_tree(root: &Path, dirs: usize, files_per_dir: usize) {
+    let mut rng = SmallRng::seed_from_u64(0);
+
+    for dir in 0..dirs {
+        let dir = root.join(format!("dir{dir}"));
+        fs::create_dir_all(&dir).unwrap();
+        for file in 0..files_per_dir {
+            let size = rng.gen_range(0..4096);
+            fs::write(dir.join(format!("file{file}")), synthetic_data(size)).unwrap();
+        }
+    }
+}
+
+fn walk(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("tree");
+    create_tree(&root, 20, 50);
+    let inputs = [root.canonicalize().unwrap()];
+    let output_path = dir.path().join("output");
+
+    let mut group = c.benchmark_group("walk");
+
+    group.bench_function("walker", |b| {
+        b.iter(|| FileVisibilityPolicy::new().build_walker(&root).count())
+    });
+    group.bench_function("tar-entries", |b| {
+        b.iter(|| {
+            archive::tar::build_archive_from_paths(
+                &inputs,
+                &output_path,
+                io::sink(),
+                FileVisibilityPolicy::new(),
+                true,
+                false,
+                None,
+                EntryModes::default(),
+                1,
+                None,
+                Eol::Keep,
+            )
+            .unwrap()
+        })
+    });
+    group.bench_function("zip-entries", |b| {
+        b.iter(|| {
+            archive::zip::build_archive_from_paths(
+                &inputs,
+                &output_path,
+                io::Cursor::new(vec![]),
+                FileVisibilityPolicy::new(),
+                true,
+                false,
+                None,
+                EntryModes::default(),
+                1,
+                None,
+                Eol::Keep,
+            )
+            .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+fn setup(c: &mut Criterion) {
+    // Messages are sent to the logger thread, which must be running
+    spawn_logger_thread();
+    compression(c);
+    decompression(c);
+    walk(c);
+}
+
+criterion_group!(benches, setup);
+criterion_main!(benches);
@@ -0,0 +1,107 @@
+//! Indexes of blocked gzip files, the `.gzi` files written next to them by `bgzip --index`.
+//!
+//! Blocked gzip files are sequences of small gzip members, each one decodes on its own. The index
+//! stores where the members start in the compressed and the decompressed data, so decompression
+//! can start at the member holding an offset instead of at the beginning of the file.
+//!
+//! The index is a little endian u64 count of members, followed by the compressed and decompressed
+//! u64 offsets of each one but the first, which starts at 0 on both sides.
+
+use std::{
+    ffi::OsString,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+
+/// Offsets of the members of a blocked gzip file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzipIndex {
+    /// Compressed and decompressed offsets at which each member starts, in increasing order
+    blocks: Vec<(u64, u64)>,
+}
+
+impl GzipIndex {
+    pub fn read(mut reader: impl Read) -> io::Result<Self> {
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid gzip index: {message}"));
+
+        let count = read_u64()?;
+        let mut blocks = vec![(0, 0)];
+        for _ in 0..count {
+            let block = (read_u64()?, read_u64()?);
+            // Unwrap safety: starts with the first member
+            let previous = *blocks.last().unwrap();
+            if block.0 < previous.0 || block.1 < previous.1 {
+                return Err(invalid("the offsets aren't in increasing order"));
+            }
+            blocks.push(block);
+        }
+        if reader.read(&mut [0])? != 0 {
+            return Err(invalid("there is data after the offsets"));
+        }
+        Ok(Self { blocks })
+    }
+
+    /// Reads the index of `path` from `<path>.gzi`, if there is one
+    pub fn find(path: &Path) -> io::Result<Option<Self>> {
+        let index_path = index_path(path);
+        if !index_path.is_file() {
+            return Ok(None);
+        }
+        let file = fs::File::open(index_path)?;
+        Self::read(io::BufReader::new(file)).map(Some)
+    }
+
+    /// Compressed and decompressed offsets of the last member starting at or before the
+    /// decompressed `offset`
+    pub fn block_before(&self, offset: u64) -> (u64, u64) {
+        let after = self.blocks.partition_point(|&(_, start)| start <= offset);
+        // The first member starts at 0, so at least one is before
+        self.blocks[after - 1]
+    }
+}
+
+/// `file.gz` -> `file.gz.gzi`
+fn index_path(path: &Path) -> PathBuf {
+    let mut index_path = OsString::from(path);
+    index_path.push(".gzi");
+    index_path.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_bytes(blocks: &[(u64, u64)]) -> Vec<u8> {
+        let mut bytes = (blocks.len() as u64).to_le_bytes().to_vec();
+        for (compressed, decompressed) in blocks {
+            bytes.extend(compressed.to_le_bytes());
+            bytes.extend(decompressed.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_gzip_index_block_before() {
+        let index = GzipIndex::read(&index_bytes(&[(30, 100), (70, 200)])[..]).unwrap();
+        assert_eq!(index.block_before(0), (0, 0));
+        assert_eq!(index.block_before(99), (0, 0));
+        assert_eq!(index.block_before(100), (30, 100));
+        assert_eq!(index.block_before(150), (30, 100));
+        assert_eq!(index.block_before(5000), (70, 200));
+
+        let empty = GzipIndex::read(&index_bytes(&[])[..]).unwrap();
+        assert_eq!(empty.block_before(5000), (0, 0));
+
+        assert!(GzipIndex::read(&index_bytes(&[(70, 200), (30, 100)])[..]).is_err());
+        assert!(GzipIndex::read(&index_bytes(&[(30, 100)])[..10]).is_err());
+        assert!(GzipIndex::read(&[index_bytes(&[(30, 100)]), vec![0]].concat()[..]).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! LZ4 legacy format, still the only LZ4 format some tools (like the Linux kernel) can read.
+//!
+//! The stream is made of the magic number followed by blocks, each prefixed by its compressed size
+//! as a little-endian u32 and holding 8 MiB of uncompressed data, except for the last one.
+
+use std::io::{self, Read, Write};
+
+use lz4_flex::block;
+
+/// Magic number at the start of legacy streams
+pub const MAGIC: [u8; 4] = 0x184C2102_u32.to_le_bytes();
+
+/// Uncompressed size of each block
+const BLOCK_SIZE: usize = 8 * 1024 * 1024;
+
+/// Compresses everything written to it in the legacy format, finishing the stream when dropped.
+pub struct Encoder<W: Write> {
+    writer: W,
+    buffer: Vec<u8>,
+    compressed: Vec<u8>,
+    header_written: bool,
+    finished: bool,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            compressed: vec![0; block::get_maximum_output_size(BLOCK_SIZE)],
+            header_written: false,
+            finished: false,
+        }
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            self.writer.write_all(&MAGIC)?;
+            self.header_written = true;
+        }
+
+        if !self.buffer.is_empty() {
+            let size = block::compress_into(&self.buffer, &mut self.compressed).map_err(io::Error::other)?;
+            self.writer.write_all(&(size as u32).to_le_bytes())?;
+            self.writer.write_all(&self.compressed[..size])?;
+            self.buffer.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last block, it's the only one allowed to be smaller than the others.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.write_block()?;
+            self.writer.flush()?;
+            self.finished = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+
+        if self.buffer.len() == BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(len)
+    }
+
+    /// Only flushes the complete blocks, as blocks in the middle of the stream must be full
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        // Same as the `auto_finish` of other encoders, errors can't be reported from here
+        let _ = self.finish();
+    }
+}
+
+/// Decompresses a legacy stream, including multiple concatenated ones.
+pub struct Decoder<R: Read> {
+    reader: R,
+    block: Vec<u8>,
+    position: usize,
+    compressed: Vec<u8>,
+    header_read: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            block: vec![0; BLOCK_SIZE],
+            // Nothing decompressed yet
+            position: BLOCK_SIZE,
+            compressed: Vec::new(),
+            header_read: false,
+        }
+    }
+
+    /// Reads the next little-endian u32, or `None` at the end of the stream
+    fn read_u32(&mut self) -> io::Result<Option<u32>> {
+        let mut bytes = [0; 4];
+        let mut read = 0;
+        while read < bytes.len() {
+            match self.reader.read(&mut bytes[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        Ok(Some(u32::from_le_bytes(bytes)))
+    }
+
+    /// Decompresses the next block, returns `false` at the end of the stream
+    fn read_block(&mut self) -> io::Result<bool> {
+        let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        if !self.header_read {
+            if self.read_u32()? != Some(u32::from_le_bytes(MAGIC)) {
+                return Err(invalid_data("not an LZ4 legacy stream"));
+            }
+            self.header_read = true;
+        }
+
+        let size = loop {
+            match self.read_u32()? {
+                None => return Ok(false),
+                // Another stream concatenated to this one
+                Some(size) if size.to_le_bytes() == MAGIC => continue,
+                Some(size) => break size as usize,
+            }
+        };
+
+        if size > block::get_maximum_output_size(BLOCK_SIZE) {
+            return Err(invalid_data("LZ4 legacy block is too large"));
+        }
+
+        self.compressed.resize(size, 0);
+        self.reader.read_exact(&mut self.compressed)?;
+
+        self.block.resize(BLOCK_SIZE, 0);
+        let len = block::decompress_into(&self.compressed, &mut self.block)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        self.block.truncate(len);
+        self.position = 0;
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.block.len() {
+            if !self.read_block()? {
+                return Ok(0);
+            }
+        }
+
+        let len = buf.len().min(self.block.len() - self.position);
+        buf[..len].copy_from_slice(&self.block[self.position..self.position + len]);
+        self.position += len;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        let mut encoder = Encoder::new(&mut output);
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap();
+        drop(encoder);
+        output
+    }
+
+    fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut output = vec![];
+        Decoder::new(data).read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Spans multiple blocks, the last one being partial
+        let data: Vec<u8> = (0..BLOCK_SIZE * 2 + 1000).map(|i| (i % 251) as u8).collect();
+
+        let compressed = compress(&data);
+        assert!(compressed.starts_with(&MAGIC));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        assert_eq!(compress(b""), MAGIC);
+        assert_eq!(decompress(&MAGIC).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_concatenated_streams() {
+        let mut compressed = compress(b"first ");
+        compressed.extend(compress(b"second"));
+        assert_eq!(decompress(&compressed).unwrap(), b"first second");
+    }
+
+    #[test]
+    fn test_invalid_streams() {
+        assert!(decompress(b"\x04\x22\x4D\x18").is_err());
+        assert!(decompress(&compress(b"truncated")[..10]).is_err());
+    }
+}
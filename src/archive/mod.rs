@@ -2,10 +2,345 @@
 
 #[cfg(not(feature = "bzip3"))]
 pub mod bzip3_stub;
+#[cfg(feature = "gzi")]
+pub mod gzi;
+pub mod lz4_legacy;
 #[cfg(feature = "unrar")]
 pub mod rar;
 #[cfg(not(feature = "unrar"))]
 pub mod rar_stub;
 pub mod sevenz;
 pub mod tar;
+pub mod volumes;
 pub mod zip;
+pub mod zstd_frames;
+
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use fs_err as fs;
+
+use crate::{
+    cli::ResumeMode,
+    error::FinalError,
+    utils::{logger::warning, Bytes, EscapedPathDisplay},
+};
+
+/// Name of the entry written by `--embed-manifest`, stored before the inputs
+pub const MANIFEST_FILE_NAME: &str = ".ouch-manifest.json";
+
+/// The setuid, setgid and sticky bits of a mode, removed on extraction unless `--preserve-special-bits`
+pub const SPECIAL_MODE_BITS: u32 = 0o7000;
+
+/// Permission modes stored for archived entries instead of the ones on disk, set by `--mode`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryModes {
+    pub files: Option<u32>,
+    pub dirs: Option<u32>,
+}
+
+impl EntryModes {
+    /// Parse modes in the `files=644,dirs=755` form, either of them can be omitted
+    pub fn new(text: &str) -> crate::Result<Self> {
+        let error = |detail: String| {
+            FinalError::with_title(format!("Invalid modes '{text}'"))
+                .detail(detail)
+                .hint("Use octal modes like: --mode files=644,dirs=755")
+        };
+
+        let mut modes = Self::default();
+        for pair in text.split(',') {
+            let (kind, mode) = pair
+                .split_once('=')
+                .ok_or_else(|| error(format!("Missing '=' in '{pair}'")))?;
+
+            let mode = u32::from_str_radix(mode, 8)
+                .ok()
+                .filter(|mode| *mode <= 0o7777)
+                .ok_or_else(|| error(format!("'{mode}' is not an octal mode between 0 and 7777")))?;
+
+            match kind {
+                "files" => modes.files = Some(mode),
+                "dirs" => modes.dirs = Some(mode),
+                _ => return Err(error(format!("Unknown entry kind '{kind}', expected 'files' or 'dirs'")).into()),
+            }
+        }
+
+        Ok(modes)
+    }
+}
+
+/// User or group ids stored in archives mapped to the ones set on extraction, for `--owner-map`
+/// and `--group-map`. Ids left out of the mapping are kept as stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdMap(HashMap<u32, u32>);
+
+impl IdMap {
+    /// Parse mappings in the `old:new` form separated by commas, like "1000:1001,1002:0"
+    pub fn new(text: &str) -> crate::Result<Self> {
+        let error = |detail: String| {
+            FinalError::with_title(format!("Invalid id mapping '{text}'"))
+                .detail(detail)
+                .hint("Use numeric ids like: --owner-map 1000:1001,1002:0")
+        };
+
+        let mut map = HashMap::new();
+        for pair in text.split(',') {
+            let (old, new) = pair
+                .split_once(':')
+                .ok_or_else(|| error(format!("Missing ':' in '{pair}'")))?;
+            let parse = |id: &str| {
+                id.parse::<u32>()
+                    .map_err(|_| error(format!("'{id}' is not a numeric id")))
+            };
+
+            if map.insert(parse(old)?, parse(new)?).is_some() {
+                return Err(error(format!("'{old}' is mapped more than once")).into());
+            }
+        }
+
+        Ok(Self(map))
+    }
+
+    /// Id set on extraction for the stored `id`
+    pub fn remap(&self, id: u32) -> u32 {
+        self.0.get(&id).copied().unwrap_or(id)
+    }
+}
+
+/// Owners given to extracted tar entries, the ones stored remapped by `--owner-map` and
+/// `--group-map`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OwnerMaps {
+    pub users: IdMap,
+    pub groups: IdMap,
+}
+
+impl OwnerMaps {
+    /// Parse the mappings, `None` if neither is given or if owners can't be changed, which is only
+    /// allowed to root on Unix.
+    pub fn new(users: Option<&str>, groups: Option<&str>) -> crate::Result<Option<Self>> {
+        if users.is_none() && groups.is_none() {
+            return Ok(None);
+        }
+
+        let maps = Self {
+            users: users.map(IdMap::new).transpose()?.unwrap_or_default(),
+            groups: groups.map(IdMap::new).transpose()?.unwrap_or_default(),
+        };
+
+        // SAFETY: geteuid has no preconditions and never fails
+        #[cfg(unix)]
+        let is_privileged = unsafe { libc::geteuid() } == 0;
+        #[cfg(not(unix))]
+        let is_privileged = false;
+
+        if !is_privileged {
+            warning("Owners can only be changed by root on Unix, ignoring --owner-map and --group-map".to_string());
+            return Ok(None);
+        }
+        Ok(Some(maps))
+    }
+}
+
+/// Gives the entry extracted at `path` the owners stored for it, remapped by `owners`, without
+/// following symlinks
+pub(crate) fn set_owner(path: &Path, owners: &OwnerMaps, uid: u32, gid: u32) -> crate::Result<()> {
+    #[cfg(unix)]
+    std::os::unix::fs::lchown(path, Some(owners.users.remap(uid)), Some(owners.groups.remap(gid)))?;
+    #[cfg(not(unix))]
+    let _ = (path, owners, uid, gid);
+    Ok(())
+}
+
+/// Rounds `mtime` down to a multiple of `granularity` seconds, so it doesn't depend on the
+/// precision of the filesystem it was read from.
+pub fn truncate_mtime(mtime: SystemTime, granularity: u64) -> SystemTime {
+    let since_epoch = mtime.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs() / granularity * granularity;
+    SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)
+}
+
+pub(crate) fn warn_special_bits_removed(path: &Path) {
+    warning(format!(
+        "Removed the setuid, setgid or sticky bit of {}, use --preserve-special-bits to keep them",
+        EscapedPathDisplay::new(path)
+    ));
+}
+
+/// What is known of an entry to tell if it was already extracted by an interrupted run
+pub(crate) struct ResumedEntry {
+    pub size: u64,
+    /// Modification time, in seconds since the Unix epoch
+    pub mtime: Option<i64>,
+    /// Only zip stores the CRC32 of entries
+    pub crc32: Option<u32>,
+}
+
+/// Checks if `path` already holds `entry`, to skip it with `--resume`, otherwise removes what's
+/// there so that it's replaced by the entry.
+///
+/// Files match if their size and modification time do, or their CRC32 with
+/// [`ResumeMode::Checksum`], which reads them whole. Entries without a CRC32 fall back to the
+/// modification time. Fails if `path` leads outside of `output_folder` through a symlink
+/// extracted earlier, instead of touching what's there.
+pub(crate) fn check_resumed_entry(
+    output_folder: &Path,
+    path: &Path,
+    mode: ResumeMode,
+    entry: ResumedEntry,
+) -> crate::Result<bool> {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return Ok(false);
+    };
+
+    let parent = path.parent().unwrap_or(path);
+    let canonical_parent = parent.canonicalize()?;
+    if !canonical_parent.starts_with(output_folder.canonicalize()?) {
+        return Err(FinalError::with_title(format!(
+            "Refusing to resume {} outside of the output directory",
+            EscapedPathDisplay::new(path)
+        ))
+        .detail(format!(
+            "{} leads to {} through a symlink",
+            EscapedPathDisplay::new(parent),
+            EscapedPathDisplay::new(&canonical_parent)
+        ))
+        .into());
+    }
+
+    if metadata.is_file() && metadata.len() == entry.size {
+        let is_extracted = match (mode, entry.crc32) {
+            (ResumeMode::Checksum, Some(crc32)) => file_crc32(path)? == crc32,
+            _ => {
+                let mtime = metadata.modified()?.duration_since(SystemTime::UNIX_EPOCH);
+                matches!((mtime, entry.mtime), (Ok(mtime), Some(entry_mtime)) if mtime.as_secs() as i64 == entry_mtime)
+            }
+        };
+        if is_extracted {
+            return Ok(true);
+        }
+    }
+
+    if !metadata.is_dir() {
+        fs::remove_file(path)?;
+    }
+    Ok(false)
+}
+
+fn file_crc32(path: &Path) -> crate::Result<u32> {
+    let mut file = fs::File::open(path)?;
+    let mut crc = flate2::Crc::new();
+    let mut buffer = vec![0; crate::BUFFER_CAPACITY];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(crc.sum()),
+            read => crc.update(&buffer[..read]),
+        }
+    }
+}
+
+/// Size limit of in-memory decompression, shared by all entries of an archive
+pub(crate) struct MemoryBudget {
+    max_size: u64,
+    remaining: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            remaining: max_size,
+        }
+    }
+
+    /// Reads a decompressed entry into memory, failing if it doesn't fit in what's left
+    pub fn read(&mut self, entry: &mut impl Read) -> crate::Result<Vec<u8>> {
+        let mut content = vec![];
+        // One more byte than allowed tells content that reaches the limit apart from content past it
+        entry
+            .by_ref()
+            .take(self.remaining.saturating_add(1))
+            .read_to_end(&mut content)?;
+
+        self.remaining = self.remaining.checked_sub(content.len() as u64).ok_or_else(|| {
+            FinalError::with_title("Decompressed content is too large to be held in memory")
+                .detail(format!(
+                    "Decompression stopped after reaching the limit of {}",
+                    Bytes::new(self.max_size)
+                ))
+                .hint("Raise the limit or decompress to disk instead")
+        })?;
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_modes() {
+        assert_eq!(
+            EntryModes::new("files=644,dirs=0755").unwrap(),
+            EntryModes {
+                files: Some(0o644),
+                dirs: Some(0o755)
+            }
+        );
+        assert_eq!(EntryModes::new("dirs=700").unwrap().files, None);
+
+        assert!(EntryModes::new("files=648").is_err());
+        assert!(EntryModes::new("files=17777").is_err());
+        assert!(EntryModes::new("links=777").is_err());
+        assert!(EntryModes::new("files").is_err());
+        assert!(EntryModes::new("").is_err());
+    }
+
+    #[test]
+    fn test_id_map() {
+        let map = IdMap::new("1000:1001,1001:1000,0:0,33:0").unwrap();
+        // Swapped, identity and squashed mappings
+        assert_eq!(map.remap(1000), 1001);
+        assert_eq!(map.remap(1001), 1000);
+        assert_eq!(map.remap(0), 0);
+        assert_eq!(map.remap(33), 0);
+        // Unmapped ids are kept
+        assert_eq!(map.remap(1002), 1002);
+        assert_eq!(IdMap::default().remap(1000), 1000);
+
+        assert!(IdMap::new("1000:1001,1000:0").is_err());
+        assert!(IdMap::new("1000=1001").is_err());
+        assert!(IdMap::new("user:1001").is_err());
+        assert!(IdMap::new("1000:-1").is_err());
+        assert!(IdMap::new("").is_err());
+    }
+
+    #[test]
+    fn test_truncate_mtime() {
+        let time = |secs, nanos| SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+
+        assert_eq!(truncate_mtime(time(1000, 999_999_999), 1), time(1000, 0));
+        assert_eq!(truncate_mtime(time(1000, 0), 1), time(1000, 0));
+        assert_eq!(truncate_mtime(time(1001, 500), 2), time(1000, 0));
+        assert_eq!(truncate_mtime(time(1019, 1), 10), time(1010, 0));
+        // Times before the epoch can't be stored anyway
+        assert_eq!(
+            truncate_mtime(SystemTime::UNIX_EPOCH - Duration::from_secs(1), 10),
+            time(0, 0)
+        );
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut budget = MemoryBudget::new(10);
+        assert_eq!(budget.read(&mut &b"hello"[..]).unwrap(), b"hello");
+        assert_eq!(budget.read(&mut &b"world"[..]).unwrap(), b"world");
+        assert_eq!(budget.read(&mut &b""[..]).unwrap(), b"");
+        assert!(budget.read(&mut &b"!"[..]).is_err());
+    }
+}
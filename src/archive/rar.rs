@@ -1,13 +1,21 @@
 //! Contains RAR-specific building and unpacking functions
 
-use std::path::Path;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
-use unrar::Archive;
+use bstr::ByteSlice;
+use unrar::{
+    error::{Code, UnrarError, When},
+    Archive,
+};
 
+use super::volumes::{self, VolumeName};
 use crate::{
     error::{Error, Result},
     list::FileInArchive,
-    utils::{logger::info, Bytes},
+    utils::{self, logger::info, Bytes},
 };
 
 /// Unpacks the archive given by `archive_path` into the folder given by `output_folder`.
@@ -23,11 +31,15 @@ pub fn unpack_archive(
         None => Archive::new(archive_path),
     };
 
-    let mut archive = archive.open_for_processing()?;
+    let volumes = check_volumes(archive_path)?;
+    let error = |err| volume_error(&volumes, err);
+
+    let mut archive = archive.open_for_processing().map_err(error)?;
     let mut unpacked = 0;
 
-    while let Some(header) = archive.read_header()? {
+    while let Some(header) = archive.read_header().map_err(error)? {
         let entry = header.entry();
+        utils::check_entry_path(&entry.filename)?;
         archive = if entry.is_file() {
             if !quiet {
                 info(format!(
@@ -37,9 +49,9 @@ pub fn unpack_archive(
                 ));
             }
             unpacked += 1;
-            header.extract_with_base(output_folder)?
+            header.extract_with_base(output_folder).map_err(error)?
         } else {
-            header.skip()?
+            header.skip().map_err(error)?
         };
     }
 
@@ -56,15 +68,56 @@ pub fn list_archive(
         None => Archive::new(archive_path),
     };
 
-    Ok(archive.open_for_listing()?.map(|item| {
-        let item = item?;
+    let volumes = check_volumes(archive_path)?;
+
+    Ok(archive.open_for_listing()?.map(move |item| {
+        let item = item.map_err(|err| volume_error(&volumes, err))?;
         let is_dir = item.is_directory();
+        let size = item.unpacked_size;
+        // RAR stores the modification time in the MS-DOS format
+        let mtime = zip::DateTime::from_msdos((item.file_time >> 16) as u16, item.file_time as u16)
+            .to_time()
+            .ok()
+            .map(SystemTime::from);
         let path = item.filename;
 
-        Ok(FileInArchive { path, is_dir })
+        Ok(FileInArchive {
+            path,
+            is_dir,
+            size,
+            mtime,
+        })
     }))
 }
 
+/// Checks that no volume is missing if the archive at `archive_path` is the first volume of a
+/// multi-volume archive like "archive.part1.rar". Returns how many were found, along with the
+/// path the next one would have.
+///
+/// The following volumes are opened by unrar itself, as it reaches them.
+fn check_volumes(archive_path: &Path) -> Result<Option<(u64, PathBuf)>> {
+    let Some(volume) = archive_path
+        .file_name()
+        .and_then(<[u8]>::from_os_str)
+        .and_then(VolumeName::rar)
+    else {
+        return Ok(None);
+    };
+    let found = volumes::find_volumes(archive_path, &volume)?.len() as u64;
+    Ok(Some((found, volume.sibling(archive_path, found + 1))))
+}
+
+/// Names the volume unrar couldn't open after the ones found, as the last volumes can't be told
+/// apart from the end of the set by name
+fn volume_error(volumes: &Option<(u64, PathBuf)>, err: UnrarError) -> Error {
+    match volumes {
+        Some((found, next)) if err.code == Code::EOpen && err.when != When::Open => {
+            volumes::missing_volume(next, *found)
+        }
+        _ => err.into(),
+    }
+}
+
 pub fn no_compression() -> Error {
     Error::UnsupportedFormat {
         reason: "Creating RAR archives is not allowed due to licensing restrictions.".into(),
@@ -11,11 +11,12 @@ use fs_err as fs;
 use same_file::Handle;
 use sevenz_rust2::SevenZArchiveEntry;
 
+use super::volumes::{self, VolumeName, VolumeReader};
 use crate::{
     error::{Error, FinalError, Result},
     list::FileInArchive,
     utils::{
-        cd_into_same_dir_as,
+        self, cd_into_same_dir_as,
         logger::{info, warning},
         Bytes, EscapedPathDisplay, FileVisibilityPolicy,
     },
@@ -76,12 +77,12 @@ where
                 }
             };
 
-            let entry_name = path.to_str().ok_or_else(|| {
+            let entry_name = utils::entry_name(path).ok_or_else(|| {
                 FinalError::with_title("7z requires that all entry names are valid UTF-8")
                     .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
             })?;
 
-            let entry = sevenz_rust2::SevenZArchiveEntry::from_path(path, entry_name.to_owned());
+            let entry = sevenz_rust2::SevenZArchiveEntry::from_path(path, entry_name.into_owned());
             let entry_data = if metadata.is_dir() {
                 None
             } else {
@@ -98,20 +99,70 @@ where
     Ok(bytes)
 }
 
+/// Signature starting 7z archives, followed by the version and the start header
+const SIGNATURE: [u8; 6] = *b"7z\xbc\xaf\x27\x1c";
+
+/// Size of the signature header, the offset of the archive headers is counted from its end
+const SIGNATURE_HEADER_SIZE: u64 = 32;
+
+/// Opens all the volumes of the archive at `archive_path` if it's the first volume of a split
+/// archive like "archive.7z.001", `None` for other names.
+pub fn open_volumes(archive_path: &Path) -> Result<Option<VolumeReader>> {
+    let Some(volume) = archive_path
+        .file_name()
+        .and_then(<[u8]>::from_os_str)
+        .and_then(VolumeName::sevenz)
+    else {
+        return Ok(None);
+    };
+    let paths = volumes::find_volumes(archive_path, &volume)?;
+    let mut reader = VolumeReader::open(&paths)?;
+
+    // Missing volumes at the end can't be told apart from the set by name, the headers stored at
+    // the end of the archive are expected past what was found instead
+    let mut header = [0; SIGNATURE_HEADER_SIZE as usize];
+    if reader.read_exact(&mut header).is_ok() && header.starts_with(&SIGNATURE) {
+        let next_header_offset = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let next_header_size = u64::from_le_bytes(header[20..28].try_into().unwrap());
+        let end = SIGNATURE_HEADER_SIZE
+            .saturating_add(next_header_offset)
+            .saturating_add(next_header_size);
+        if end > reader.size() {
+            let last = paths.len() as u64;
+            return Err(volumes::missing_volume(&volume.sibling(archive_path, last + 1), last));
+        }
+    }
+    reader.rewind()?;
+
+    Ok(Some(reader))
+}
+
 pub fn decompress_sevenz<R>(reader: R, output_path: &Path, password: Option<&[u8]>, quiet: bool) -> crate::Result<usize>
 where
     R: Read + Seek,
 {
     let mut count: usize = 0;
+    // Set when an entry is refused, the extraction is stopped as it can't fail with ouch errors
+    let mut refused_entry = None;
 
-    let entry_extract_fn = |entry: &SevenZArchiveEntry, reader: &mut dyn Read, path: &PathBuf| {
+    let entry_extract_fn = |entry: &SevenZArchiveEntry, reader: &mut dyn Read, _: &PathBuf| {
+        if let Err(err) = utils::check_entry_path(Path::new(entry.name())) {
+            refused_entry = Some(err);
+            return Ok(false);
+        }
         count += 1;
         // Manually handle writing all files from 7z archive, due to library exluding empty files
         use std::io::BufWriter;
 
         use filetime_creation as ft;
 
-        let file_path = output_path.join(entry.name());
+        // Entry names are separated by `/`, paths are rebuilt with the separator of the platform.
+        // Absolute ones allowed by `--force-local` are made relative, like in other formats
+        let Some(relative_path) = utils::normalize_entry_path(Path::new(entry.name())) else {
+            return Ok(true);
+        };
+        let path = output_path.join(relative_path);
+        let file_path: PathBuf = output_path.join(entry.name()).components().collect();
 
         if entry.is_directory() {
             if !quiet {
@@ -167,6 +218,9 @@ where
         None => sevenz_rust2::decompress_with_extract_fn(reader, output_path, entry_extract_fn)?,
     }
 
+    if let Some(err) = refused_entry {
+        return Err(err);
+    }
     Ok(count)
 }
 
@@ -181,6 +235,8 @@ where
         files.push(Ok(FileInArchive {
             path: entry.name().into(),
             is_dir: entry.is_directory(),
+            size: entry.size(),
+            mtime: entry.has_last_modified_date.then(|| entry.last_modified_date().into()),
         }));
         Ok(true)
     };
@@ -2,51 +2,178 @@
 
 use std::{
     env,
-    io::prelude::*,
-    path::{Path, PathBuf},
+    io::{self, prelude::*},
+    path::{Component, Path, PathBuf},
     sync::mpsc::{self, Receiver},
     thread,
+    time::{Duration, SystemTime},
 };
 
+use bstr::ByteSlice;
+use filetime_creation::{set_file_atime, set_file_mtime, FileTime};
 use fs_err as fs;
 use same_file::Handle;
 
 use crate::{
+    archive::{
+        check_resumed_entry, set_owner, truncate_mtime, warn_special_bits_removed, EntryModes, MemoryBudget, OwnerMaps,
+        ResumedEntry, MANIFEST_FILE_NAME, SPECIAL_MODE_BITS,
+    },
+    cli::{AbsoluteSymlinks, Eol, ResumeMode},
     error::FinalError,
-    list::FileInArchive,
+    list::{FileInArchive, ListEntry},
     utils::{
         self,
+        io::read_with_line_endings,
         logger::{info, warning},
         Bytes, EscapedPathDisplay, FileVisibilityPolicy,
     },
+    QuestionAction, QuestionPolicy,
 };
 
 /// Unpacks the archive given by `archive` into the folder given by `into`.
 /// Assumes that output_folder is empty
-pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool) -> crate::Result<usize> {
+///
+/// Symlinks with absolute targets are handled according to `absolute_symlinks`, and the special
+/// bits of modes are removed unless `preserve_special_bits` is set. Entry paths lose their last
+/// `strip_trailing_components`, files colliding after that are handled by `question_policy`, and
+/// moved to the trash when replaced if `trash`. With `resume`, the files already extracted are
+/// skipped, compared by size and modification time as tar entries have no checksum. With
+/// `owners`, entries get the stored owners, remapped.
+///
+/// Files get the modification time of their PAX record if any, which keeps sub-second precision,
+/// and their access time too with `preserve_atime`.
+#[allow(clippy::too_many_arguments)]
+pub fn unpack_archive(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    quiet: bool,
+    absolute_symlinks: AbsoluteSymlinks,
+    preserve_special_bits: bool,
+    strip_trailing_components: usize,
+    question_policy: QuestionPolicy,
+    trash: bool,
+    resume: Option<ResumeMode>,
+    owners: Option<&OwnerMaps>,
+    preserve_atime: bool,
+) -> crate::Result<usize> {
     let mut archive = tar::Archive::new(reader);
+    // Without it, modes are limited to the permission bits
+    archive.set_preserve_permissions(preserve_special_bits);
 
     let mut files_unpacked = 0;
     for file in archive.entries()? {
         let mut file = file?;
 
-        match file.header().entry_type() {
+        utils::check_entry_path(&file.path()?)?;
+        // Skip entries like "./", they represent the extraction root itself
+        let Some(relative_path) = utils::normalize_entry_path(&file.path()?) else {
+            continue;
+        };
+        let relative_path = match strip_trailing_components {
+            0 => relative_path,
+            count => match utils::strip_trailing_components(&relative_path, count) {
+                Some(path) => path,
+                None => continue,
+            },
+        };
+        // Skipped like `unpack_in` does, so nothing outside of the output directory is created,
+        // replaced or given times and owners
+        if relative_path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            continue;
+        }
+
+        if let Some(mode) = resume {
+            let entry_type = file.header().entry_type();
+            if entry_type.is_file() || entry_type.is_symlink() {
+                let full_path = output_folder.join(&relative_path);
+                let entry = ResumedEntry {
+                    size: file.size(),
+                    mtime: file.header().mtime().ok().map(|mtime| mtime as i64),
+                    crc32: None,
+                };
+                if check_resumed_entry(output_folder, &full_path, mode, entry)? && entry_type.is_file() {
+                    if !quiet {
+                        info(format!(
+                            "skipped {:?}, already extracted",
+                            utils::strip_cur_dir(&full_path)
+                        ));
+                    }
+                    continue;
+                }
+            }
+        }
+
+        let unpacked_path = match file.header().entry_type() {
             tar::EntryType::Symlink => {
-                let relative_path = file.path()?.to_path_buf();
                 let full_path = output_folder.join(&relative_path);
                 let target = file
                     .link_name()?
                     .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Missing symlink target"))?;
+                let Some(target) = utils::resolve_symlink_target(absolute_symlinks, &relative_path, &target) else {
+                    continue;
+                };
 
                 #[cfg(unix)]
                 std::os::unix::fs::symlink(&target, &full_path)?;
                 #[cfg(windows)]
                 std::os::windows::fs::symlink_file(&target, &full_path)?;
+                full_path
             }
             tar::EntryType::Regular | tar::EntryType::Directory => {
-                file.unpack_in(output_folder)?;
+                if !preserve_special_bits && file.header().mode()? & SPECIAL_MODE_BITS != 0 {
+                    warn_special_bits_removed(&relative_path);
+                }
+
+                if strip_trailing_components == 0 {
+                    // Skipped when it leaves the output directory, then nothing was written there
+                    if !file.unpack_in(output_folder)? {
+                        continue;
+                    }
+                    output_folder.join(&relative_path)
+                } else {
+                    // The entry path doesn't match anymore, so it can't be unpacked by `unpack_in`,
+                    // which checks where the parents lead the same way
+                    let full_path = output_folder.join(&relative_path);
+                    if file.header().entry_type().is_dir() {
+                        create_dirs_inside(output_folder, &relative_path)?;
+                        full_path
+                    } else {
+                        if let Some(parent) = relative_path.parent() {
+                            create_dirs_inside(output_folder, parent)?;
+                        }
+                        let Some(full_path) = utils::resolve_path_conflict(
+                            &full_path,
+                            question_policy,
+                            QuestionAction::Decompression,
+                            trash,
+                        )?
+                        else {
+                            continue;
+                        };
+                        file.unpack(&full_path)?;
+                        full_path
+                    }
+                }
             }
             _ => continue,
+        };
+
+        // tar only restores the whole seconds of the header
+        if file.header().entry_type().is_file() {
+            let (mtime, atime) = entry_times(&mut file, preserve_atime)?;
+            set_file_mtime(&unpacked_path, FileTime::from_system_time(mtime))?;
+            if let Some(atime) = atime {
+                set_file_atime(&unpacked_path, FileTime::from_system_time(atime))?;
+            }
+        }
+
+        if let Some(owners) = owners {
+            let (uid, gid) = (file.header().uid()?, file.header().gid()?);
+            set_owner(&unpacked_path, owners, uid as u32, gid as u32)?;
         }
 
         // This is printed for every file in the archive and has little
@@ -57,7 +184,7 @@ pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool)
             info(format!(
                 "extracted ({}) {:?}",
                 Bytes::new(file.size()),
-                utils::strip_cur_dir(&output_folder.join(file.path()?)),
+                utils::strip_cur_dir(&output_folder.join(&relative_path)),
             ));
         }
         files_unpacked += 1;
@@ -66,13 +193,261 @@ pub fn unpack_archive(reader: Box<dyn Read>, output_folder: &Path, quiet: bool)
     Ok(files_unpacked)
 }
 
-/// List contents of `archive`, returning a vector of archive entries
+/// Creates the missing directories of the relative `path` in `output_folder`, failing if one of
+/// them leads outside of it through a symlink extracted earlier, like `tar::Entry::unpack_in`.
+fn create_dirs_inside(output_folder: &Path, path: &Path) -> crate::Result<()> {
+    let canonical_output = output_folder.canonicalize()?;
+    let mut dir = output_folder.to_path_buf();
+    for component in path.components() {
+        dir.push(component);
+        if !dir.exists() {
+            fs::create_dir(&dir)?;
+        }
+        let canonical_dir = dir.canonicalize()?;
+        if !canonical_dir.starts_with(&canonical_output) {
+            return Err(FinalError::with_title(format!(
+                "Refusing to extract {} outside of the output directory",
+                EscapedPathDisplay::new(&output_folder.join(path))
+            ))
+            .detail(format!(
+                "{} leads to {} through a symlink",
+                EscapedPathDisplay::new(&dir),
+                EscapedPathDisplay::new(&canonical_dir)
+            ))
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Same as [`unpack_archive`], with every entry created relative to a descriptor of
+/// `output_folder`, for `--confined`.
+///
+/// Symlinks leading outside of `output_folder` on the way to an entry are refused instead of
+/// followed, so it can't be written outside of it. Files colliding after stripping components are
+/// handled by `question_policy`. Hard links must point to entries inside of `output_folder` too.
+/// Symlinks keep the owner of the extraction.
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+pub fn unpack_archive_confined(
+    reader: Box<dyn Read>,
+    output_folder: &Path,
+    quiet: bool,
+    absolute_symlinks: AbsoluteSymlinks,
+    preserve_special_bits: bool,
+    strip_trailing_components: usize,
+    question_policy: QuestionPolicy,
+    owners: Option<&OwnerMaps>,
+    preserve_atime: bool,
+) -> crate::Result<usize> {
+    use std::{
+        fs::{FileTimes, Permissions},
+        os::unix::fs::PermissionsExt,
+    };
+
+    use crate::utils::confined::ConfinedDir;
+
+    let destination = ConfinedDir::open(output_folder)?;
+    let mut archive = tar::Archive::new(reader);
+
+    let mut files_unpacked = 0;
+    for file in archive.entries()? {
+        let mut file = file?;
+
+        utils::check_entry_path(&file.path()?)?;
+        let Some(relative_path) = utils::normalize_entry_path(&file.path()?) else {
+            continue;
+        };
+        let relative_path = match strip_trailing_components {
+            0 => relative_path,
+            count => match utils::strip_trailing_components(&relative_path, count) {
+                Some(path) => path,
+                None => continue,
+            },
+        };
+
+        let entry_type = file.header().entry_type();
+        if !matches!(
+            entry_type,
+            tar::EntryType::Symlink
+                | tar::EntryType::Link
+                | tar::EntryType::Directory
+                | tar::EntryType::Regular
+                | tar::EntryType::Continuous
+                | tar::EntryType::GNUSparse
+        ) {
+            continue;
+        }
+
+        // Stripping components can make files collide
+        let relative_path = if strip_trailing_components > 0 && !entry_type.is_dir() {
+            match destination.resolve_conflict(output_folder, &relative_path, question_policy)? {
+                Some(path) => path,
+                None => continue,
+            }
+        } else {
+            relative_path
+        };
+
+        let output = match entry_type {
+            tar::EntryType::Symlink => {
+                let target = file
+                    .link_name()?
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing symlink target"))?;
+                let Some(target) = utils::resolve_symlink_target(absolute_symlinks, &relative_path, &target) else {
+                    continue;
+                };
+                destination.symlink(&target, &relative_path)?;
+                None
+            }
+            // Targets are entry paths, stripped the same way
+            tar::EntryType::Link => {
+                let target = file
+                    .link_name()?
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing hard link target"))?;
+                utils::check_entry_path(&target)?;
+                let target = utils::normalize_entry_path(&target).and_then(|target| match strip_trailing_components {
+                    0 => Some(target),
+                    count => utils::strip_trailing_components(&target, count),
+                });
+                let Some(target) = target else {
+                    continue;
+                };
+                destination.hard_link(&target, &relative_path)?;
+                None
+            }
+            tar::EntryType::Directory => Some(destination.create_dir_all(&relative_path)?),
+            // Holes of sparse files are read as zeros
+            _ => {
+                let mut output = destination.create_file(&relative_path)?;
+                io::copy(&mut file, &mut output)?;
+                Some(output)
+            }
+        };
+
+        // Set through the descriptor, a path could have been swapped for a symlink by now
+        if let Some(output) = output {
+            let mut mode = file.header().mode()?;
+            if !preserve_special_bits && mode & SPECIAL_MODE_BITS != 0 {
+                warn_special_bits_removed(&relative_path);
+                mode &= !SPECIAL_MODE_BITS;
+            }
+            output.set_permissions(Permissions::from_mode(mode & 0o7777))?;
+            let (mtime, atime) = entry_times(&mut file, preserve_atime)?;
+            let times = FileTimes::new().set_modified(mtime);
+            output.set_times(atime.map_or(times, |atime| times.set_accessed(atime)))?;
+            if let Some(owners) = owners {
+                let (uid, gid) = (file.header().uid()? as u32, file.header().gid()? as u32);
+                std::os::unix::fs::fchown(&output, Some(owners.users.remap(uid)), Some(owners.groups.remap(gid)))?;
+            }
+        }
+
+        if !quiet {
+            info(format!(
+                "extracted ({}) {:?}",
+                Bytes::new(file.size()),
+                utils::strip_cur_dir(&output_folder.join(&relative_path)),
+            ));
+        }
+        files_unpacked += 1;
+    }
+
+    Ok(files_unpacked)
+}
+
+/// Reads the regular files of the archive given by `reader` into memory, with their paths.
+///
+/// Entries with `..` components are left out, like the zip ones without an enclosed name, as
+/// nothing stops callers from joining the paths returned to a directory of theirs. Fails once
+/// their content adds up to more than `max_size` bytes.
+pub fn unpack_archive_in_memory(reader: Box<dyn Read>, max_size: u64) -> crate::Result<Vec<(PathBuf, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut budget = MemoryBudget::new(max_size);
+
+    let mut files = vec![];
+    for file in archive.entries()? {
+        let mut file = file?;
+
+        if !file.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(path) = utils::normalize_entry_path(&file.path()?) else {
+            continue;
+        };
+        if path.components().any(|component| component == Component::ParentDir) {
+            continue;
+        }
+
+        let content = budget.read(&mut file)?;
+        files.push((path, content));
+    }
+
+    Ok(files)
+}
+
+/// Modification time of `file`, with the sub-second precision of its PAX record if any, and its
+/// access time from its PAX record or GNU header if `preserve_atime` is set.
+fn entry_times<R: Read>(
+    file: &mut tar::Entry<R>,
+    preserve_atime: bool,
+) -> io::Result<(SystemTime, Option<SystemTime>)> {
+    let (mut mtime, mut atime) = (None, None);
+    if let Some(extensions) = file.pax_extensions()? {
+        for extension in extensions {
+            let extension = extension?;
+            match extension.key() {
+                Ok("mtime") => mtime = parse_pax_time(extension.value_bytes()),
+                Ok("atime") => atime = parse_pax_time(extension.value_bytes()),
+                _ => {}
+            }
+        }
+    }
+
+    let mtime = match mtime {
+        Some(mtime) => mtime,
+        None => SystemTime::UNIX_EPOCH + Duration::from_secs(file.header().mtime()?),
+    };
+    let atime = match atime {
+        Some(atime) => Some(atime),
+        None => file
+            .header()
+            .as_gnu()
+            .and_then(|header| header.atime().ok())
+            .filter(|&atime| atime > 0)
+            .map(|atime| SystemTime::UNIX_EPOCH + Duration::from_secs(atime)),
+    };
+
+    Ok((mtime, atime.filter(|_| preserve_atime)))
+}
+
+/// Parses the time of a PAX record, seconds since the epoch with an optional fraction, like
+/// "1700000000.123456789" or "-1.5"
+fn parse_pax_time(value: &[u8]) -> Option<SystemTime> {
+    let value = std::str::from_utf8(value).ok()?;
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let offset = Duration::new(
+        seconds.trim_start_matches('-').parse().ok()?,
+        // Digits past nanoseconds are dropped
+        format!("{fraction:0<9}")[..9].parse().ok()?,
+    );
+    if seconds.starts_with('-') {
+        SystemTime::UNIX_EPOCH.checked_sub(offset)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_add(offset)
+    }
+}
+
+/// Records of PAX global headers are returned as archive metadata, instead of as files.
 pub fn list_archive(
     mut archive: tar::Archive<impl Read + Send + 'static>,
-) -> impl Iterator<Item = crate::Result<FileInArchive>> {
-    struct Files(Receiver<crate::Result<FileInArchive>>);
+) -> impl Iterator<Item = crate::Result<ListEntry>> {
+    struct Files(Receiver<crate::Result<ListEntry>>);
     impl Iterator for Files {
-        type Item = crate::Result<FileInArchive>;
+        type Item = crate::Result<ListEntry>;
 
         fn next(&mut self) -> Option<Self::Item> {
             self.0.recv().ok()
@@ -82,13 +457,48 @@ pub fn list_archive(
     let (tx, rx) = mpsc::channel();
     thread::spawn(move || {
         for file in archive.entries().expect("entries is only used once") {
-            let file_in_archive = (|| {
-                let file = file?;
+            let entries = (|| {
+                let mut file = file?;
+
+                if file.header().entry_type() == tar::EntryType::XGlobalHeader {
+                    let Some(extensions) = file.pax_extensions()? else {
+                        return Ok(vec![]);
+                    };
+                    return extensions
+                        .map(|extension| {
+                            let extension = extension?;
+                            Ok(ListEntry::Metadata {
+                                key: String::from_utf8_lossy(extension.key_bytes()).into_owned(),
+                                value: String::from_utf8_lossy(extension.value_bytes()).into_owned(),
+                            })
+                        })
+                        .collect();
+                }
+
                 let path = file.path()?.into_owned();
                 let is_dir = file.header().entry_type().is_dir();
-                Ok(FileInArchive { path, is_dir })
+                let size = file.size();
+                let mtime = file
+                    .header()
+                    .mtime()
+                    .ok()
+                    .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime));
+                Ok(vec![ListEntry::File(FileInArchive {
+                    path,
+                    is_dir,
+                    size,
+                    mtime,
+                })])
             })();
-            tx.send(file_in_archive).unwrap();
+
+            // The listing stops reading entries once it printed enough of them
+            let sent = match entries {
+                Ok(entries) => entries.into_iter().all(|entry| tx.send(Ok(entry)).is_ok()),
+                Err(err) => tx.send(Err(err)).is_ok(),
+            };
+            if !sent {
+                break;
+            }
         }
     });
 
@@ -96,6 +506,12 @@ pub fn list_archive(
 }
 
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// If `root_name` is set, entries are stored under it instead of the name of their input, and
+/// `modes` replaces the permissions read from disk. Modification times are rounded down to a
+/// multiple of `mtime_granularity` seconds. A `manifest` is stored as the first entry. The line
+/// endings of text files are converted to `eol`.
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
@@ -103,6 +519,11 @@ pub fn build_archive_from_paths<W>(
     file_visibility_policy: FileVisibilityPolicy,
     quiet: bool,
     follow_symlinks: bool,
+    root_name: Option<&str>,
+    modes: EntryModes,
+    mtime_granularity: u64,
+    manifest: Option<&[u8]>,
+    eol: Eol,
 ) -> crate::Result<W>
 where
     W: Write,
@@ -110,6 +531,20 @@ where
     let mut builder = tar::Builder::new(writer);
     let output_handle = Handle::from_path(output_path);
 
+    if let Some(manifest) = manifest {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+        builder.append_data(&mut header, MANIFEST_FILE_NAME, manifest)?;
+    }
+
     for filename in input_filenames {
         let previous_location = utils::cd_into_same_dir_as(filename)?;
 
@@ -141,8 +576,23 @@ where
                 info(format!("Compressing '{}'", EscapedPathDisplay::new(path)));
             }
 
+            // The name the entry is stored under, `path` keeps pointing at the file on disk
+            let entry_path = match root_name {
+                Some(name) => utils::replace_root_component(path, name),
+                None => path.to_path_buf(),
+            };
+
             if path.is_dir() {
-                builder.append_dir(path, path)?;
+                let metadata = path.metadata()?;
+                append_with_metadata(
+                    &mut builder,
+                    &entry_path,
+                    &metadata,
+                    modes.dirs,
+                    mtime_granularity,
+                    None,
+                    io::empty(),
+                )?;
             } else if path.is_symlink() && !follow_symlinks {
                 let target_path = path.read_link()?;
 
@@ -150,7 +600,18 @@ where
                 header.set_entry_type(tar::EntryType::Symlink);
                 header.set_size(0);
 
-                builder.append_link(&mut header, path, &target_path).map_err(|err| {
+                // Store the target exactly as read, `append_link` would normalize it, but it's
+                // still needed for targets too long to fit in the header
+                let literal_target =
+                    <[u8]>::from_path(&target_path).filter(|target| header.set_link_name_literal(target).is_ok());
+                let result = if literal_target.is_some() {
+                    header.set_cksum();
+                    builder.append_data(&mut header, &entry_path, io::empty())
+                } else {
+                    builder.append_link(&mut header, &entry_path, &target_path)
+                };
+
+                result.map_err(|err| {
                     FinalError::with_title("Could not create archive")
                         .detail("Unexpected error while trying to read link")
                         .detail(format!("Error: {err}."))
@@ -166,7 +627,29 @@ where
                         return Err(e.into());
                     }
                 };
-                builder.append_file(path, file.file_mut()).map_err(|err| {
+                let metadata = file.metadata()?;
+                let result = match read_with_line_endings(file.file_mut(), eol) {
+                    Ok(Some(text)) => append_with_metadata(
+                        &mut builder,
+                        &entry_path,
+                        &metadata,
+                        modes.files,
+                        mtime_granularity,
+                        Some(text.len() as u64),
+                        text.as_slice(),
+                    ),
+                    Ok(None) => append_with_metadata(
+                        &mut builder,
+                        &entry_path,
+                        &metadata,
+                        modes.files,
+                        mtime_granularity,
+                        None,
+                        file.file_mut(),
+                    ),
+                    Err(err) => Err(err),
+                };
+                result.map_err(|err| {
                     FinalError::with_title("Could not create archive")
                         .detail("Unexpected error while trying to read file")
                         .detail(format!("Error: {err}."))
@@ -178,3 +661,35 @@ where
 
     Ok(builder.into_inner()?)
 }
+
+/// Appends an entry like `append_file` or `append_dir` would, but storing `mode` as its permissions
+/// if set, and its modification time rounded down to a multiple of `mtime_granularity` seconds.
+/// `size` replaces the one on disk, for content converted while read
+fn append_with_metadata<W: Write>(
+    builder: &mut tar::Builder<W>,
+    entry_path: &Path,
+    metadata: &std::fs::Metadata,
+    mode: Option<u32>,
+    mtime_granularity: u64,
+    size: Option<u64>,
+    data: impl Read,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(metadata);
+    if let Some(size) = size {
+        header.set_size(size);
+    }
+    if let Some(mode) = mode {
+        header.set_mode(mode);
+    }
+    if let Ok(mtime) = metadata.modified() {
+        let mtime = truncate_mtime(mtime, mtime_granularity);
+        header.set_mtime(
+            mtime
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        );
+    }
+    builder.append_data(&mut header, entry_path, data)
+}
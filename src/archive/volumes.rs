@@ -0,0 +1,233 @@
+//! Archives split in several volumes, like "archive.7z.001" or "archive.part1.rar".
+//!
+//! Only the first volume is given, the others are found next to it. Volume numbers must follow
+//! each other, a missing volume is reported by name before anything is read.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice;
+use fs_err as fs;
+
+use crate::{
+    error::{FinalError, Result},
+    utils::EscapedPathDisplay,
+};
+
+/// Name of a volume, split around its number
+#[derive(Debug, PartialEq, Eq)]
+pub struct VolumeName<'a> {
+    pub prefix: &'a [u8],
+    pub number: u64,
+    /// Digits of the number, which is padded with zeros to this width
+    pub width: usize,
+    pub suffix: &'a [u8],
+}
+
+impl<'a> VolumeName<'a> {
+    /// Parses volumes of split 7z archives, like "archive.7z.001"
+    pub fn sevenz(name: &'a [u8]) -> Option<Self> {
+        let (rest, digits) = name.rsplit_once_str(b".")?;
+        if !rest.ends_with(b".7z") {
+            return None;
+        }
+        Self::new(&name[..rest.len() + 1], digits, b"")
+    }
+
+    /// Parses volumes of multi-volume rar archives, like "archive.part1.rar"
+    pub fn rar(name: &'a [u8]) -> Option<Self> {
+        let rest = name.strip_suffix(b".rar")?;
+        let start = rest.rfind(b".part")? + ".part".len();
+        Self::new(&name[..start], &rest[start..], b".rar")
+    }
+
+    fn new(prefix: &'a [u8], digits: &[u8], suffix: &'a [u8]) -> Option<Self> {
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        Some(Self {
+            prefix,
+            number: digits.to_str().ok()?.parse().ok()?,
+            width: digits.len(),
+            suffix,
+        })
+    }
+
+    /// Number of the volume named `name` if it's part of the same set
+    fn number_of(&self, name: &[u8]) -> Option<u64> {
+        let digits = name.strip_prefix(self.prefix)?.strip_suffix(self.suffix)?;
+        Self::new(self.prefix, digits, self.suffix).map(|volume| volume.number)
+    }
+
+    /// Name of the volume numbered `number` of the same set
+    fn with_number(&self, number: u64) -> Vec<u8> {
+        let mut name = self.prefix.to_vec();
+        name.extend_from_slice(format!("{number:0width$}", width = self.width).as_bytes());
+        name.extend_from_slice(self.suffix);
+        name
+    }
+
+    /// Path of the volume numbered `number`, next to the volume at `path`
+    pub fn sibling(&self, path: &Path, number: u64) -> PathBuf {
+        path.with_file_name(self.with_number(number).to_os_str_lossy())
+    }
+}
+
+/// Paths of the volumes of the set whose first volume is at `path`, in order.
+pub fn find_volumes(path: &Path, volume: &VolumeName) -> Result<Vec<PathBuf>> {
+    if volume.number != 1 {
+        return Err(FinalError::with_title(format!(
+            "{} is not the first volume of the archive",
+            EscapedPathDisplay::new(path)
+        ))
+        .detail("The other volumes are found next to the first one")
+        .hint(format!(
+            "Use {} instead",
+            EscapedPathDisplay::new(&volume.sibling(path, 1))
+        ))
+        .into());
+    }
+
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    // Siblings are listed rather than probed one after another, so gaps can be told apart from the end
+    let mut volumes = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(number) = <[u8]>::from_os_str(&name).and_then(|name| volume.number_of(name)) else {
+            continue;
+        };
+        volumes.push((number, path.with_file_name(&name)));
+    }
+    volumes.sort();
+
+    for (expected, (number, _)) in (1..).zip(&volumes) {
+        if *number != expected {
+            let last = volumes.last().map_or(expected, |(number, _)| *number);
+            return Err(missing_volume(&volume.sibling(path, expected), last));
+        }
+    }
+
+    Ok(volumes.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Error for the volume at `path` that wasn't found, while volumes up to `last` were
+pub fn missing_volume(path: &Path, last: u64) -> crate::Error {
+    FinalError::with_title(format!("Missing volume {}", EscapedPathDisplay::new(path)))
+        .detail(format!(
+            "Found volumes up to number {last}, but the archive can't be read without all of them"
+        ))
+        .hint("Put the missing volume next to the other ones")
+        .into()
+}
+
+/// Reads volumes one after another, as a single file.
+pub struct VolumeReader {
+    files: Vec<fs::File>,
+    /// Offset of the start of each volume, followed by the total size
+    offsets: Vec<u64>,
+    position: u64,
+}
+
+impl VolumeReader {
+    pub fn open(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut files = Vec::with_capacity(paths.len());
+        let mut offsets = vec![0];
+        for path in paths {
+            let file = fs::File::open(path)?;
+            offsets.push(offsets.last().unwrap() + file.metadata()?.len());
+            files.push(file);
+        }
+        Ok(Self {
+            files,
+            offsets,
+            position: 0,
+        })
+    }
+
+    /// Size of all the volumes
+    pub fn size(&self) -> u64 {
+        *self.offsets.last().unwrap()
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Reads stop at the end of each volume, the next read continues with the following one
+        let index = self.offsets.partition_point(|&offset| offset <= self.position);
+        if index == 0 || index > self.files.len() || buf.is_empty() {
+            return Ok(0);
+        }
+        let file = &mut self.files[index - 1];
+        file.seek(SeekFrom::Start(self.position - self.offsets[index - 1]))?;
+
+        let len = buf.len().min((self.offsets[index] - self.position) as usize);
+        let read = file.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position =
+            position.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume_names() {
+        let volume = VolumeName::sevenz(b"archive.7z.001").unwrap();
+        assert_eq!(
+            (volume.prefix, volume.number, volume.suffix),
+            (&b"archive.7z."[..], 1, &b""[..])
+        );
+        assert_eq!(volume.with_number(12), b"archive.7z.012");
+
+        let volume = VolumeName::rar(b"archive.part01.rar").unwrap();
+        assert_eq!(
+            (volume.prefix, volume.number, volume.suffix),
+            (&b"archive.part"[..], 1, &b".rar"[..])
+        );
+        assert_eq!(volume.with_number(2), b"archive.part02.rar");
+
+        assert_eq!(VolumeName::sevenz(b"archive.7z"), None);
+        assert_eq!(VolumeName::sevenz(b"archive.zip.001"), None);
+        assert_eq!(VolumeName::rar(b"archive.rar"), None);
+        assert_eq!(VolumeName::rar(b"archive.partial.rar"), None);
+    }
+
+    #[test]
+    fn test_read_across_volumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = ["a", "b"].iter().map(|name| dir.path().join(name)).collect();
+        fs::write(&paths[0], b"hello ").unwrap();
+        fs::write(&paths[1], b"world").unwrap();
+
+        let mut reader = VolumeReader::open(&paths).unwrap();
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "hello world");
+
+        reader.seek(SeekFrom::End(-8)).unwrap();
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"lo w");
+    }
+}
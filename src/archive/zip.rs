@@ -3,36 +3,65 @@
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::{
-    env,
+    env, fmt,
     io::{self, prelude::*},
-    path::{Path, PathBuf},
-    sync::mpsc,
+    ops::Deref,
+    path::{Component, Path, PathBuf},
+    sync::{mpsc, Arc},
     thread,
+    time::SystemTime,
 };
 
 use filetime_creation::{set_file_mtime, FileTime};
 use fs_err as fs;
 use same_file::Handle;
 use time::OffsetDateTime;
-use zip::{self, read::ZipFile, DateTime, ZipArchive};
+use zip::{self, read::ZipFile, result::ZipError, CompressionMethod, DateTime, ZipArchive};
 
+#[cfg(unix)]
+use crate::archive::{warn_special_bits_removed, SPECIAL_MODE_BITS};
 use crate::{
+    archive::{check_resumed_entry, truncate_mtime, EntryModes, MemoryBudget, ResumedEntry, MANIFEST_FILE_NAME},
+    cli::{Eol, ResumeMode},
     error::FinalError,
     list::FileInArchive,
     utils::{
-        cd_into_same_dir_as, get_invalid_utf8_paths,
+        self, cd_into_same_dir_as, get_invalid_utf8_paths,
+        io::read_with_line_endings,
         logger::{info, info_accessible, warning},
-        pretty_format_list_of_paths, strip_cur_dir, Bytes, EscapedPathDisplay, FileVisibilityPolicy,
+        normalize_entry_path, pretty_format_list_of_paths,
+        progress::{AggregateProgress, ProgressReader},
+        replace_root_component, strip_cur_dir, Bytes, EscapedPathDisplay, FileVisibilityPolicy,
     },
+    QuestionAction, QuestionPolicy,
 };
 
 /// Unpacks the archive given by `archive` into the folder given by `output_folder`.
-/// Assumes that output_folder is empty
+/// Assumes that output_folder is empty, special bits of modes are removed unless
+/// `preserve_special_bits` is set. Entry paths lose their last `strip_trailing_components`, files
+/// colliding after that are handled by `question_policy`, and moved to the trash when replaced if
+/// `trash`. Entries whose content doesn't match their
+/// CRC32 are an error if `check_crc` is set. With `resume`, the files already extracted are
+/// skipped.
+///
+/// Sizes and CRCs are taken from the central directory, so entries of streamed zips, which leave
+/// them out of their local headers for a trailing data descriptor, are read correctly.
+///
+/// Each entry is decoded according to its own compression method, entries of unsupported methods
+/// are skipped with a warning.
+#[allow(clippy::too_many_arguments)]
 pub fn unpack_archive<R>(
     mut archive: ZipArchive<R>,
     output_folder: &Path,
     password: Option<&[u8]>,
     quiet: bool,
+    preserve_special_bits: bool,
+    strip_trailing_components: usize,
+    question_policy: QuestionPolicy,
+    trash: bool,
+    check_crc: bool,
+    resume: Option<ResumeMode>,
+    progress: Option<&Arc<AggregateProgress>>,
 ) -> crate::Result<usize>
 where
     R: Read + Seek,
@@ -40,19 +69,52 @@ where
     let mut unpacked_files = 0;
 
     for idx in 0..archive.len() {
-        let mut file = match password {
-            Some(password) => archive
-                .by_index_decrypt(idx, password)?
-                .map_err(|_| zip::result::ZipError::UnsupportedArchive("Password required to decrypt file"))?,
-            None => archive.by_index(idx)?,
+        let Some(mut file) = open_entry(&mut archive, idx, password)? else {
+            continue;
         };
-        let file_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
+        utils::check_entry_path(Path::new(file.name()))?;
+        // Also skips entries like "./", they represent the extraction root itself
+        let file_path = match extracted_path(&file) {
+            Some(path) => path,
             None => continue,
         };
+        let file_path = match strip_trailing_components {
+            0 => file_path,
+            count => match utils::strip_trailing_components(&file_path, count) {
+                Some(path) => path,
+                None => continue,
+            },
+        };
 
         let file_path = output_folder.join(file_path);
 
+        if let Some(mode) = resume {
+            if !file.is_dir() {
+                let entry = ResumedEntry {
+                    size: file.size(),
+                    mtime: file.last_modified().to_time().ok().map(|time| time.unix_timestamp()),
+                    crc32: Some(file.crc32()),
+                };
+                let is_symlink = file.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+                if check_resumed_entry(output_folder, &file_path, mode, entry)? && !is_symlink {
+                    if !quiet {
+                        info(format!("skipped {:?}, already extracted", strip_cur_dir(&file_path)));
+                    }
+                    continue;
+                }
+            }
+        }
+
+        // Stripping components can make files collide
+        let file_path = if strip_trailing_components > 0 && !file.name().ends_with('/') {
+            match utils::resolve_path_conflict(&file_path, question_policy, QuestionAction::Decompression, trash)? {
+                Some(path) => path,
+                None => continue,
+            }
+        } else {
+            file_path
+        };
+
         display_zip_comment_if_exists(&file);
 
         match file.name().ends_with('/') {
@@ -87,8 +149,10 @@ where
                 let is_symlink = mode.is_some_and(|mode| mode & 0o170000 == 0o120000);
 
                 if is_symlink {
-                    let mut target = String::new();
-                    file.read_to_string(&mut target)?;
+                    let mut target = vec![];
+                    copy_entry_content(&mut file, &mut target, check_crc, progress)?;
+                    let target = String::from_utf8(target)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.utf8_error()))?;
 
                     #[cfg(unix)]
                     std::os::unix::fs::symlink(&target, file_path)?;
@@ -96,7 +160,7 @@ where
                     std::os::windows::fs::symlink_file(&target, file_path)?;
                 } else {
                     let mut output_file = fs::File::create(file_path)?;
-                    io::copy(&mut file, &mut output_file)?;
+                    copy_entry_content(&mut file, &mut output_file, check_crc, progress)?;
                 }
 
                 set_last_modified_time(&file, file_path)?;
@@ -104,7 +168,7 @@ where
         }
 
         #[cfg(unix)]
-        unix_set_permissions(&file_path, &file)?;
+        unix_set_permissions(&file_path, &file, preserve_special_bits)?;
 
         unpacked_files += 1;
     }
@@ -112,6 +176,152 @@ where
     Ok(unpacked_files)
 }
 
+/// Same as [`unpack_archive`], with every entry created relative to a descriptor of
+/// `output_folder`, for `--confined`.
+///
+/// Symlinks leading outside of `output_folder` on the way to an entry are refused instead of
+/// followed, so it can't be written outside of it. Files colliding after stripping components are
+/// handled by `question_policy`.
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+pub fn unpack_archive_confined<R>(
+    mut archive: ZipArchive<R>,
+    output_folder: &Path,
+    password: Option<&[u8]>,
+    quiet: bool,
+    preserve_special_bits: bool,
+    strip_trailing_components: usize,
+    question_policy: QuestionPolicy,
+    check_crc: bool,
+    progress: Option<&Arc<AggregateProgress>>,
+) -> crate::Result<usize>
+where
+    R: Read + Seek,
+{
+    use std::fs::Permissions;
+
+    use crate::utils::confined::ConfinedDir;
+
+    let destination = ConfinedDir::open(output_folder)?;
+
+    let mut unpacked_files = 0;
+    for idx in 0..archive.len() {
+        let Some(mut file) = open_entry(&mut archive, idx, password)? else {
+            continue;
+        };
+        utils::check_entry_path(Path::new(file.name()))?;
+        let Some(relative_path) = extracted_path(&file) else {
+            continue;
+        };
+        let relative_path = match strip_trailing_components {
+            0 => relative_path,
+            count => match utils::strip_trailing_components(&relative_path, count) {
+                Some(path) => path,
+                None => continue,
+            },
+        };
+
+        // Stripping components can make files collide
+        let relative_path = if strip_trailing_components > 0 && !file.name().ends_with('/') {
+            match destination.resolve_conflict(output_folder, &relative_path, question_policy)? {
+                Some(path) => path,
+                None => continue,
+            }
+        } else {
+            relative_path
+        };
+
+        display_zip_comment_if_exists(&file);
+
+        let is_symlink = file.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        let output = if file.name().ends_with('/') {
+            destination.create_dir_all(&relative_path)?
+        } else if is_symlink {
+            let mut target = vec![];
+            copy_entry_content(&mut file, &mut target, check_crc, progress)?;
+            let target = String::from_utf8(target)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.utf8_error()))?;
+            destination.symlink(Path::new(&target), &relative_path)?;
+            unpacked_files += 1;
+            continue;
+        } else {
+            let mut output = destination.create_file(&relative_path)?;
+            copy_entry_content(&mut file, &mut output, check_crc, progress)?;
+            if let Ok(time) = file.last_modified().to_time() {
+                output.set_modified(time.into())?;
+            }
+            output
+        };
+
+        if !quiet {
+            info(format!(
+                "extracted ({}) {:?}",
+                Bytes::new(file.size()),
+                strip_cur_dir(&output_folder.join(&relative_path)),
+            ));
+        }
+
+        // Set through the descriptor, a path could have been swapped for a symlink by now
+        if let Some(mut mode) = file.unix_mode() {
+            if !preserve_special_bits && mode & SPECIAL_MODE_BITS != 0 {
+                warn_special_bits_removed(&relative_path);
+                mode &= !SPECIAL_MODE_BITS;
+            }
+            output.set_permissions(Permissions::from_mode(mode & 0o7777))?;
+        }
+
+        unpacked_files += 1;
+    }
+
+    Ok(unpacked_files)
+}
+
+/// Reads the regular files of `archive` into memory, with their paths.
+///
+/// Fails once their content adds up to more than `max_size` bytes.
+pub fn unpack_archive_in_memory<R>(
+    mut archive: ZipArchive<R>,
+    password: Option<&[u8]>,
+    max_size: u64,
+) -> crate::Result<Vec<(PathBuf, Vec<u8>)>>
+where
+    R: Read + Seek,
+{
+    let mut budget = MemoryBudget::new(max_size);
+
+    let mut files = vec![];
+    for idx in 0..archive.len() {
+        let Some(mut file) = open_entry(&mut archive, idx, password)? else {
+            continue;
+        };
+
+        let is_symlink = file.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000);
+        if file.is_dir() || is_symlink {
+            continue;
+        }
+        let Some(path) = file.enclosed_name().and_then(normalize_entry_path) else {
+            continue;
+        };
+
+        let content = budget.read(&mut entry_content(&mut file, true)?)?;
+        files.push((path, content));
+    }
+
+    Ok(files)
+}
+
+/// Sum of the uncompressed sizes of the entries of `archive`, as stored in its central directory
+pub fn uncompressed_size<R>(archive: &mut ZipArchive<R>) -> crate::Result<u64>
+where
+    R: Read + Seek,
+{
+    let mut size = 0;
+    for idx in 0..archive.len() {
+        size += archive.by_index_raw(idx)?.size();
+    }
+    Ok(size)
+}
+
 /// List contents of `archive`, returning a vector of archive entries
 pub fn list_archive<R>(
     mut archive: ZipArchive<R>,
@@ -135,24 +345,32 @@ where
     thread::spawn(move || {
         for idx in 0..archive.len() {
             let file_in_archive = (|| {
-                let zip_result = match password.clone() {
+                // Only the metadata is needed, which entries of unsupported methods still have
+                let method = archive.by_index_raw(idx)?.compression();
+                let file = match password.clone() {
+                    _ if !is_supported(method) => archive.by_index_raw(idx)?,
                     Some(password) => archive
                         .by_index_decrypt(idx, &password)?
-                        .map_err(|_| zip::result::ZipError::UnsupportedArchive("Password required to decrypt file")),
-                    None => archive.by_index(idx),
-                };
-
-                let file = match zip_result {
-                    Ok(f) => f,
-                    Err(e) => return Err(e.into()),
+                        .map_err(|_| wrong_password())?,
+                    None => archive.by_index(idx)?,
                 };
 
                 let path = file.enclosed_name().unwrap_or(&*file.mangled_name()).to_owned();
                 let is_dir = file.is_dir();
-
-                Ok(FileInArchive { path, is_dir })
+                let size = file.size();
+                let mtime = file.last_modified().to_time().ok().map(SystemTime::from);
+
+                Ok(FileInArchive {
+                    path,
+                    is_dir,
+                    size,
+                    mtime,
+                })
             })();
-            tx.send(file_in_archive).unwrap();
+            // The listing stops reading entries once it printed enough of them
+            if tx.send(file_in_archive).is_err() {
+                break;
+            }
         }
     });
 
@@ -160,6 +378,11 @@ where
 }
 
 /// Compresses the archives given by `input_filenames` into the file given previously to `writer`.
+///
+/// If `root_name` is set, entries are stored under it instead of the name of their input, and
+/// `modes` replaces the permissions read from disk. Modification times are rounded down to a
+/// multiple of `mtime_granularity` seconds. A `manifest` is stored as the first entry.
+#[allow(clippy::too_many_arguments)]
 pub fn build_archive_from_paths<W>(
     input_filenames: &[PathBuf],
     output_path: &Path,
@@ -167,6 +390,11 @@ pub fn build_archive_from_paths<W>(
     file_visibility_policy: FileVisibilityPolicy,
     quiet: bool,
     follow_symlinks: bool,
+    root_name: Option<&str>,
+    modes: EntryModes,
+    mtime_granularity: u64,
+    manifest: Option<&[u8]>,
+    eol: Eol,
 ) -> crate::Result<W>
 where
     W: Write + Seek,
@@ -180,6 +408,11 @@ where
     #[cfg(not(unix))]
     let executable = options.unix_permissions(0o755);
 
+    if let Some(manifest) = manifest {
+        writer.start_file(MANIFEST_FILE_NAME, options.unix_permissions(0o644))?;
+        writer.write_all(manifest)?;
+    }
+
     // Vec of any filename that failed the UTF-8 check
     let invalid_unicode_filenames = get_invalid_utf8_paths(input_filenames);
 
@@ -237,16 +470,26 @@ where
             #[cfg(unix)]
             let mode = metadata.permissions().mode();
 
-            let entry_name = path.to_str().ok_or_else(|| {
+            // The name the entry is stored under, `path` keeps pointing at the file on disk
+            let entry_path = match root_name {
+                Some(name) => replace_root_component(path, name),
+                None => path.to_path_buf(),
+            };
+            let entry_name = utils::entry_name(&entry_path).ok_or_else(|| {
                 FinalError::with_title("Zip requires that all directories names are valid UTF-8")
                     .detail(format!("File at '{path:?}' has a non-UTF-8 name"))
             })?;
 
             if metadata.is_dir() {
-                writer.add_directory(entry_name, options)?;
+                let options = match modes.dirs {
+                    Some(mode) => options.unix_permissions(mode),
+                    None => options,
+                };
+                let options = options.last_modified_time(get_last_modified_time(&metadata, mtime_granularity));
+                writer.add_directory(&*entry_name, options)?;
             } else if path.is_symlink() && !follow_symlinks {
                 let target_path = path.read_link()?;
-                let target_name = target_path.to_str().ok_or_else(|| {
+                let target_name = utils::entry_name(&target_path).ok_or_else(|| {
                     FinalError::with_title("Zip requires that all directories names are valid UTF-8")
                         .detail(format!("File at '{target_path:?}' has a non-UTF-8 name"))
                 })?;
@@ -258,7 +501,7 @@ where
                 #[cfg(windows)]
                 let symlink_options = options.unix_permissions(0o120777);
 
-                writer.add_symlink(entry_name, target_name, symlink_options)?;
+                writer.add_symlink(&*entry_name, &*target_name, symlink_options)?;
             } else {
                 #[cfg(not(unix))]
                 let options = if is_executable::is_executable(path) {
@@ -271,11 +514,21 @@ where
 
                 #[cfg(unix)]
                 let options = options.unix_permissions(mode);
+                let options = match modes.files {
+                    Some(mode) => options.unix_permissions(mode),
+                    None => options,
+                };
                 // Updated last modified time
-                let last_modified_time = options.last_modified_time(get_last_modified_time(&file));
-
-                writer.start_file(entry_name, last_modified_time)?;
-                io::copy(&mut file, &mut writer)?;
+                let last_modified_time =
+                    options.last_modified_time(get_last_modified_time(&metadata, mtime_granularity));
+
+                writer.start_file(&*entry_name, last_modified_time)?;
+                match read_with_line_endings(&mut file, eol)? {
+                    Some(text) => writer.write_all(&text)?,
+                    None => {
+                        io::copy(&mut file, &mut writer)?;
+                    }
+                }
             }
         }
 
@@ -286,6 +539,224 @@ where
     Ok(bytes)
 }
 
+/// Normalized path `file` is extracted to, `None` if it goes outside of the output directory.
+///
+/// Absolute paths only get there with `--force-local`, they're made relative like in tar archives.
+fn extracted_path(file: &ZipFile) -> Option<PathBuf> {
+    match file.enclosed_name() {
+        Some(path) => normalize_entry_path(path),
+        None if Path::new(file.name()).has_root() => {
+            let path = normalize_entry_path(Path::new(file.name()))?;
+            (!path.components().any(|component| component == Component::ParentDir)).then_some(path)
+        }
+        None => None,
+    }
+}
+
+/// Error for entries that don't decrypt with the given password
+fn wrong_password() -> crate::Error {
+    crate::Error::InvalidPassword {
+        reason: "The password doesn't decrypt the zip archive".to_owned(),
+    }
+}
+
+/// Entry opened by [`open_entry`], its content is read with [`entry_content`]
+pub(crate) struct ZipEntry<'a> {
+    file: ZipFile<'a>,
+    /// Opened without decoding its content, as ouch decodes it. Encrypted entries are decoded by
+    /// the zip crate instead, as it decrypts them
+    raw: bool,
+}
+
+impl<'a> Deref for ZipEntry<'a> {
+    type Target = ZipFile<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+/// Opens the entry numbered `idx` of `archive`, or warns and returns `None` if its compression
+/// method isn't supported.
+///
+/// Methods differ from one entry to another, so this is checked for each of them. Entries that
+/// aren't encrypted are opened raw and decoded by [`entry_content`], which computes their CRC32
+/// only if it's checked, while the zip crate always does.
+pub(crate) fn open_entry<'a, R>(
+    archive: &'a mut ZipArchive<R>,
+    idx: usize,
+    password: Option<&[u8]>,
+) -> crate::Result<Option<ZipEntry<'a>>>
+where
+    R: Read + Seek,
+{
+    let (method, name) = {
+        let file = archive.by_index_raw(idx)?;
+        (file.compression(), file.name().to_owned())
+    };
+
+    // Opening it normally checks the password before the method
+    let encrypted = matches!(
+        archive.by_index(idx),
+        Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+    );
+    if !encrypted && decodes_raw(method) {
+        let file = archive.by_index_raw(idx)?;
+        return Ok(Some(ZipEntry { file, raw: true }));
+    }
+    if encrypted && is_supported(method) {
+        let file = match password {
+            Some(password) => archive.by_index_decrypt(idx, password)?.map_err(|_| wrong_password())?,
+            None => archive.by_index(idx)?,
+        };
+        return Ok(Some(ZipEntry { file, raw: false }));
+    }
+
+    let method = match method {
+        CompressionMethod::ZSTD => "zstd",
+        CompressionMethod::DEFLATE => "deflate",
+        CompressionMethod::DEFLATE64 => "deflate64",
+        CompressionMethod::LZMA => "LZMA",
+        CompressionMethod::XZ => "xz",
+        CompressionMethod::PPMD => "PPMd",
+        _ => "an unknown method",
+    };
+    let reason = if encrypted { " along with encryption" } else { "" };
+    warning(format!(
+        "Skipping {name:?}, it's compressed with {method}{reason}, which isn't supported"
+    ));
+    Ok(None)
+}
+
+/// Whether the zip crate decodes entries compressed with `method`, methods of the features it's
+/// built without aren't
+#[allow(deprecated)] // Constants can't tell them apart
+fn is_supported(method: CompressionMethod) -> bool {
+    !matches!(method, CompressionMethod::Unsupported(_))
+}
+
+/// Whether [`entry_content`] decodes the raw content of entries compressed with `method`
+fn decodes_raw(method: CompressionMethod) -> bool {
+    [
+        CompressionMethod::STORE,
+        CompressionMethod::DEFLATE,
+        CompressionMethod::BZIP2,
+        CompressionMethod::ZSTD,
+    ]
+    .contains(&method)
+}
+
+/// Reader of the decompressed content of an entry opened by [`open_entry`], failing at the end
+/// with a [`CrcMismatch`] if `check_crc` is set and the content doesn't match its CRC32.
+pub(crate) fn entry_content<'a>(entry: &'a mut ZipEntry<'_>, check_crc: bool) -> io::Result<Box<dyn Read + 'a>> {
+    let crc32 = entry.file.crc32();
+    let size = entry.file.size();
+    let checked_by_zip = !entry.raw;
+    let method = entry.file.compression();
+    let file = &mut entry.file;
+
+    let inner: Box<dyn Read + 'a> = if checked_by_zip || method == CompressionMethod::STORE {
+        Box::new(file)
+    } else if method == CompressionMethod::DEFLATE {
+        Box::new(flate2::read::DeflateDecoder::new(file))
+    } else if method == CompressionMethod::BZIP2 {
+        Box::new(bzip2::read::BzDecoder::new(file))
+    } else {
+        Box::new(zstd::stream::read::Decoder::new(file)?)
+    };
+
+    Ok(Box::new(CrcReader {
+        inner,
+        crc: (check_crc || checked_by_zip).then(flate2::Crc::new),
+        check_crc,
+        crc32,
+        remaining: size,
+        checked_by_zip,
+    }))
+}
+
+/// Error of entries whose content doesn't match their CRC32
+#[derive(Debug)]
+pub(crate) struct CrcMismatch;
+
+impl fmt::Display for CrcMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The content of the zip entry doesn't match its CRC32")
+    }
+}
+
+impl std::error::Error for CrcMismatch {}
+
+/// Computes the CRC32 of the content of an entry as it's read, and compares it to the stored one
+/// at the end.
+struct CrcReader<R: Read> {
+    inner: R,
+    /// CRC32 of the content read so far, if it's computed
+    crc: Option<flate2::Crc>,
+    check_crc: bool,
+    /// CRC32 the entry is expected to have
+    crc32: u32,
+    /// Bytes of the content left to read
+    remaining: u64,
+    /// Whether `inner` is decoded by the zip crate, which fails on mismatches by itself with an
+    /// error only its message tells apart. The CRC32 is then always computed, to recognize it.
+    checked_by_zip: bool,
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mismatch = |crc: &Option<flate2::Crc>| crc.as_ref().is_some_and(|crc| crc.sum() != self.crc32);
+
+        match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() && !self.checked_by_zip && self.check_crc && mismatch(&self.crc) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, CrcMismatch))
+            }
+            Ok(read) => {
+                if let Some(crc) = &mut self.crc {
+                    crc.update(&buf[..read]);
+                }
+                self.remaining = self.remaining.saturating_sub(read as u64);
+                Ok(read)
+            }
+            Err(_) if self.checked_by_zip && self.remaining == 0 && mismatch(&self.crc) => {
+                if self.check_crc {
+                    Err(io::Error::new(io::ErrorKind::InvalidData, CrcMismatch))
+                } else {
+                    Ok(0)
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Copies the decompressed content of `file` into `writer`, counting it in `progress`.
+///
+/// A content that doesn't match its CRC32 is reported with the name of the entry, unless
+/// `check_crc` isn't set.
+fn copy_entry_content(
+    file: &mut ZipEntry,
+    writer: &mut impl Write,
+    check_crc: bool,
+    progress: Option<&Arc<AggregateProgress>>,
+) -> crate::Result<()> {
+    let size = file.size();
+    let name = file.name().to_owned();
+    let result = io::copy(
+        &mut ProgressReader::new(entry_content(file, check_crc)?, progress.cloned(), Some(size)),
+        writer,
+    );
+    match result {
+        Err(err) if err.get_ref().is_some_and(|err| err.is::<CrcMismatch>()) => {
+            Err(FinalError::with_title("Corrupted zip entry")
+                .detail(format!("The content of '{name}' doesn't match its CRC32"))
+                .hint("Use --no-crc-check to extract it anyway")
+                .into())
+        }
+        result => result.map(drop).map_err(Into::into),
+    }
+}
+
 fn display_zip_comment_if_exists(file: &ZipFile) {
     let comment = file.comment();
     if !comment.is_empty() {
@@ -303,10 +774,11 @@ fn display_zip_comment_if_exists(file: &ZipFile) {
     }
 }
 
-fn get_last_modified_time(file: &fs::File) -> DateTime {
-    file.metadata()
-        .and_then(|metadata| metadata.modified())
+fn get_last_modified_time(metadata: &std::fs::Metadata, mtime_granularity: u64) -> DateTime {
+    metadata
+        .modified()
         .ok()
+        .map(|time| truncate_mtime(time, mtime_granularity))
         .and_then(|time| DateTime::try_from(OffsetDateTime::from(time)).ok())
         .unwrap_or_default()
 }
@@ -327,12 +799,59 @@ fn set_last_modified_time(zip_file: &ZipFile, path: &Path) -> crate::Result<()>
 }
 
 #[cfg(unix)]
-fn unix_set_permissions(file_path: &Path, file: &ZipFile) -> crate::Result<()> {
+fn unix_set_permissions(file_path: &Path, file: &ZipFile, preserve_special_bits: bool) -> crate::Result<()> {
     use std::fs::Permissions;
 
-    if let Some(mode) = file.unix_mode() {
+    if let Some(mut mode) = file.unix_mode() {
+        if !preserve_special_bits && mode & SPECIAL_MODE_BITS != 0 {
+            warn_special_bits_removed(strip_cur_dir(file_path));
+            mode &= !SPECIAL_MODE_BITS;
+        }
         fs::set_permissions(file_path, Permissions::from_mode(mode))?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    #[test]
+    fn test_progress_of_uncompressed_size() {
+        let mut writer = ZipWriter::new(io::Cursor::new(vec![]));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        writer.start_file("a", options).unwrap();
+        writer.write_all(&[b'a'; 3000]).unwrap();
+        writer.add_directory("dir", options).unwrap();
+        writer.start_file("dir/b", options).unwrap();
+        writer.write_all(&[b'b'; 1000]).unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        // The archive is much smaller than what it holds, its size would make a wrong total
+        let size = data.len() as u64;
+        let progress = Arc::new(AggregateProgress::new([Some(size)]));
+        let mut archive = ZipArchive::new(io::Cursor::new(data)).unwrap();
+        progress.replace_item_size(Some(size), uncompressed_size(&mut archive).unwrap());
+        assert_eq!(progress.total_bytes(), Some(4000));
+
+        let output = tempfile::tempdir().unwrap();
+        unpack_archive(
+            archive,
+            output.path(),
+            None,
+            true,
+            false,
+            0,
+            QuestionPolicy::AlwaysYes,
+            false,
+            true,
+            None,
+            Some(&progress),
+        )
+        .unwrap();
+        assert_eq!(progress.status(), "100% total");
+    }
+}
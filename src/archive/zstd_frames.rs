@@ -0,0 +1,116 @@
+//! Zstd streams made of frames of a fixed uncompressed size, for `--frame-size`.
+//!
+//! Frames are independent of each other, so tools processing the stream frame by frame can seek to
+//! one of them or decode several in parallel. The price is a worse ratio, as data can't be matched
+//! against the previous frames, the smaller the frames the bigger the loss.
+
+use std::io::{self, Write};
+
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+/// Compresses everything written to it, starting a new frame every `frame_size` bytes of input and
+/// finishing the last one when dropped.
+pub struct Encoder<W: Write> {
+    /// Encoder of the current frame, only missing once finished
+    encoder: Option<ZstdEncoder<'static, W>>,
+    level: i32,
+    threads: u32,
+    frame_size: u64,
+    /// Uncompressed bytes written to the current frame
+    frame_written: u64,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, level: i32, threads: u32, frame_size: u64) -> io::Result<Self> {
+        Ok(Self {
+            encoder: Some(new_frame_encoder(writer, level, threads)?),
+            level,
+            threads,
+            frame_size,
+            frame_written: 0,
+        })
+    }
+
+    fn encoder(&mut self) -> io::Result<&mut ZstdEncoder<'static, W>> {
+        self.encoder
+            .as_mut()
+            .ok_or_else(|| io::Error::other("the zstd stream is already finished"))
+    }
+
+    /// Ends the current frame and starts the next one
+    fn next_frame(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let writer = encoder.finish()?;
+            self.encoder = Some(new_frame_encoder(writer, self.level, self.threads)?);
+        }
+        self.frame_written = 0;
+        Ok(())
+    }
+
+    /// Ends the last frame, it's the only one allowed to be smaller than the others.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.finish()?.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn new_frame_encoder<W: Write>(writer: W, level: i32, threads: u32) -> io::Result<ZstdEncoder<'static, W>> {
+    let mut encoder = ZstdEncoder::new(writer, level)?;
+    encoder.multithread(threads)?;
+    Ok(encoder)
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Started lazily, so the stream doesn't end with an empty frame
+        if self.frame_written == self.frame_size {
+            self.next_frame()?;
+        }
+
+        let len = buf.len().min((self.frame_size - self.frame_written) as usize);
+        let written = self.encoder()?.write(&buf[..len])?;
+        self.frame_written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder()?.flush()
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        // Same as the `auto_finish` of other encoders, errors can't be reported from here
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_of_fixed_size() {
+        let data: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+
+        let mut output = vec![];
+        let mut encoder = Encoder::new(&mut output, 3, 0, 4096).unwrap();
+        encoder.write_all(&data).unwrap();
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        // Frames of 4096 bytes, then what's left
+        let mut sizes = vec![];
+        let mut frames = output.as_slice();
+        while !frames.is_empty() {
+            let len = zstd::zstd_safe::find_frame_compressed_size(frames).unwrap();
+            sizes.push(zstd::decode_all(&frames[..len]).unwrap().len());
+            frames = &frames[len..];
+        }
+        assert_eq!(sizes, [4096, 4096, 1808]);
+
+        assert_eq!(zstd::decode_all(output.as_slice()).unwrap(), data);
+    }
+}
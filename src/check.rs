@@ -10,12 +10,12 @@ use std::{
 
 use crate::{
     error::FinalError,
-    extension::{build_archive_file_suggestion, Extension},
+    extension::{build_archive_file_suggestion, CompressionFormat, Extension},
     utils::{
         logger::{info_accessible, warning},
         pretty_format_list_of_paths, try_infer_extension, user_wants_to_continue, EscapedPathDisplay,
     },
-    QuestionAction, QuestionPolicy, Result,
+    Error, QuestionAction, QuestionPolicy, Result,
 };
 
 /// Check if the mime type matches the detected extensions.
@@ -99,31 +99,6 @@ pub fn check_for_non_archive_formats(files: &[PathBuf], formats: &[Vec<Extension
     Ok(())
 }
 
-/// Show error if archive format is not the first format in the chain.
-pub fn check_archive_formats_position(formats: &[Extension], output_path: &Path) -> Result<()> {
-    if let Some(format) = formats.iter().skip(1).find(|format| format.is_archive()) {
-        let error = FinalError::with_title(format!(
-            "Cannot compress to '{}'.",
-            EscapedPathDisplay::new(output_path)
-        ))
-        .detail(format!("Found the format '{format}' in an incorrect position."))
-        .detail(format!(
-            "'{format}' can only be used at the start of the file extension."
-        ))
-        .hint(format!(
-            "If you wish to compress multiple files, start the extension with '{format}'."
-        ))
-        .hint(format!(
-            "Otherwise, remove the last '{}' from '{}'.",
-            format,
-            EscapedPathDisplay::new(output_path)
-        ));
-
-        return Err(error.into());
-    }
-    Ok(())
-}
-
 /// Check if all provided files have formats to decompress.
 pub fn check_missing_formats_when_decompressing(files: &[PathBuf], formats: &[Vec<Extension>]) -> Result<()> {
     let files_with_broken_extension: Vec<&PathBuf> = files
@@ -137,42 +112,9 @@ pub fn check_missing_formats_when_decompressing(files: &[PathBuf], formats: &[Ve
         return Ok(());
     }
 
-    let (files_with_unsupported_extensions, files_missing_extension): (Vec<&PathBuf>, Vec<&PathBuf>) =
-        files_with_broken_extension
-            .iter()
-            .partition(|path| path.extension().is_some());
-
-    let mut error = FinalError::with_title("Cannot decompress files");
-
-    if !files_with_unsupported_extensions.is_empty() {
-        error = error.detail(format!(
-            "Files with unsupported extensions: {}",
-            pretty_format_list_of_paths(&files_with_unsupported_extensions)
-        ));
-    }
-
-    if !files_missing_extension.is_empty() {
-        error = error.detail(format!(
-            "Files with missing extensions: {}",
-            pretty_format_list_of_paths(&files_missing_extension)
-        ));
-    }
-
-    error = error.detail("Decompression formats are detected automatically from file extension");
-    error = error.hint_all_supported_formats();
-
-    // If there's exactly one file, give a suggestion to use `--format`
-    if let &[path] = files_with_broken_extension.as_slice() {
-        error = error
-            .hint("")
-            .hint("Alternatively, you can pass an extension to the '--format' flag:")
-            .hint(format!(
-                "  ouch decompress {} --format tar.gz",
-                EscapedPathDisplay::new(path),
-            ));
-    }
-
-    Err(error.into())
+    Err(Error::UnknownFormat {
+        paths: files_with_broken_extension.into_iter().cloned().collect(),
+    })
 }
 
 /// Check if there is a first format when compressing, and returns it.
@@ -250,3 +192,66 @@ pub fn check_invalid_compression_with_non_archive_format(
 
     Err(error.into())
 }
+
+/// Check if `--name` can be used to store the input of a compression.
+///
+/// The name replaces the one of a single input, so it must be a plain file name.
+pub fn check_stored_name(name: &str, files: &[PathBuf]) -> Result<()> {
+    if files.len() > 1 {
+        return Err(FinalError::with_title("Cannot use --name with multiple input files")
+            .detail("The name replaces the one of a single input file or directory")
+            .into());
+    }
+
+    if name.is_empty() || name == "." || name == ".." || name.contains(std::path::is_separator) {
+        return Err(FinalError::with_title(format!("Invalid name '{name}'"))
+            .detail("The name must be a file name, not a path")
+            .hint("Remove the path separators from the name")
+            .into());
+    }
+
+    Ok(())
+}
+
+/// Check if the name given by `--stdin-name` is a file name telling the formats of stdin.
+pub fn check_stdin_name(name: &str, formats: &[Extension]) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(std::path::is_separator) {
+        return Err(FinalError::with_title(format!("Invalid stdin name '{name}'"))
+            .detail("The name must be a file name, not a path")
+            .into());
+    }
+
+    if formats.is_empty() {
+        return Err(
+            FinalError::with_title(format!("Cannot detect the format of stdin from '{name}'"))
+                .detail("The name has no extension of a supported format")
+                .hint("Use a name ending with the extensions of the formats, like 'data.tar.gz'")
+                .hint("Or pass --format to specify them")
+                .into(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Check if the archive format can hold the entry written by `--embed-manifest`.
+pub fn check_manifest_support(formats: &[Extension]) -> Result<()> {
+    match formats.first().map(|format| format.compression_formats[0]) {
+        Some(CompressionFormat::Tar | CompressionFormat::Zip) => Ok(()),
+        _ => Err(FinalError::with_title("Cannot embed a manifest")
+            .detail("The manifest is stored as an archive entry, only tar and zip archives support it")
+            .hint("Compress into a .tar or .zip archive, optionally followed by other formats")
+            .into()),
+    }
+}
+
+/// Check if the archive format can be split in volumes with `--split-by-entry`.
+pub fn check_split_by_entry_support(formats: &[Extension]) -> Result<()> {
+    match formats.first().map(|format| format.compression_formats[0]) {
+        Some(CompressionFormat::Tar | CompressionFormat::Zip) => Ok(()),
+        _ => Err(FinalError::with_title("Cannot split in volumes")
+            .detail("Each volume is an archive of some of the entries, only tar and zip archives support it")
+            .hint("Compress into a .tar or .zip archive, optionally followed by other formats")
+            .into()),
+    }
+}
@@ -41,6 +41,10 @@ pub struct CliArgs {
     #[arg(short, long, global = true)]
     pub format: Option<OsString>,
 
+    /// File name of the data read from stdin, its extensions tell the formats instead of `--format`
+    #[arg(long, global = true, value_name = "NAME")]
+    pub stdin_name: Option<String>,
+
     /// Decompress or list with password
     #[arg(short, long = "password", aliases = ["pass", "pw"], global = true)]
     pub password: Option<OsString>,
@@ -49,6 +53,22 @@ pub struct CliArgs {
     #[arg(short = 'c', long, global = true)]
     pub threads: Option<usize>,
 
+    /// Where to print messages, stdout is kept for data by default
+    #[arg(long, value_enum, default_value_t, value_name = "STREAM", global = true)]
+    pub messages: MessageStream,
+
+    /// Format of messages, structured formats carry their level for log collectors like journald
+    #[arg(long, value_enum, default_value_t, value_name = "FORMAT", global = true)]
+    pub log_format: LogFormat,
+
+    /// Layout of the progress status line, with tokens like {file}, {percent} and {throughput}
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    pub progress_template: Option<String>,
+
+    /// Exit with an error if any warning was emitted, after completing the operation
+    #[arg(long, global = true)]
+    pub fail_on_warning: bool,
+
     // Ouch and claps subcommands
     #[command(subcommand)]
     pub cmd: Subcommand,
@@ -58,13 +78,16 @@ pub struct CliArgs {
 #[allow(rustdoc::bare_urls)]
 pub enum Subcommand {
     /// Compress one or more files into one output file
-    #[command(visible_alias = "c")]
+    #[command(visible_alias = "c", allow_missing_positional = true)]
     Compress {
-        /// Files to be compressed
-        #[arg(required = true, value_hint = ValueHint::FilePath)]
+        /// Files to be compressed, "-" alone compresses the data read from stdin, or reads the
+        /// paths of the files with `--read0`
+        #[arg(value_hint = ValueHint::FilePath)]
         files: Vec<PathBuf>,
 
-        /// The resulting file. Its extensions can be used to specify the compression formats
+        /// The resulting file. Its extensions can be used to specify the compression formats.
+        /// If it's the only path, it's compressed instead, into a file named after it with the
+        /// extensions of `--format`
         #[arg(required = true, value_hint = ValueHint::FilePath)]
         output: PathBuf,
 
@@ -82,9 +105,93 @@ pub enum Subcommand {
         #[arg(long, group = "compression-level")]
         slow: bool,
 
+        /// Set of options for a common workflow: "backup", "web", "fast", or one defined in the
+        /// config file. Options passed explicitly take precedence
+        #[arg(long, value_name = "NAME")]
+        preset: Option<String>,
+
         /// Archive target files instead of storing symlinks (supported by `tar` and `zip`)
         #[arg(long, short = 'S')]
         follow_symlinks: bool,
+
+        /// Also compress the NUL-separated paths read from stdin, like the output of `find -print0`
+        #[arg(long, short = '0')]
+        read0: bool,
+
+        /// Name stored for the input file, in archive entries or the gzip header
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Octal permissions stored for all files and/or directories, e.g. "files=644,dirs=755"
+        #[arg(long, value_name = "files=MODE,dirs=MODE")]
+        mode: Option<String>,
+
+        /// Store a .ouch-manifest.json entry describing the archive (tar and zip)
+        #[arg(long)]
+        embed_manifest: bool,
+
+        /// Format of the .lz4 files created
+        #[arg(long, value_enum, default_value_t, value_name = "FORMAT")]
+        lz4_format: Lz4Format,
+
+        /// Round stored modification times down to a multiple of this many seconds (tar and zip)
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..), value_name = "SECONDS")]
+        mtime_granularity: u64,
+
+        /// Split the archive in volumes of at most SIZE (e.g. "100MB"), each one a complete archive
+        /// made of whole entries (tar and zip)
+        #[arg(long, value_name = "SIZE")]
+        split_by_entry: Option<String>,
+
+        /// Start a new zstd frame every SIZE of input (e.g. "4MiB"), so frames can be sought to and
+        /// decoded in parallel. The smaller the frames, the worse the ratio, as they can't refer to
+        /// each other
+        #[arg(long, value_name = "SIZE")]
+        frame_size: Option<String>,
+
+        /// Order in which directories are walked: depth-first, or breadth-first to have the
+        /// shallow entries first, for consumers streaming the archive that want an overview early
+        #[arg(long, value_enum, default_value_t, value_name = "ORDER")]
+        traversal: Traversal,
+
+        /// Create the archive even if no entries would be in it, which is an error by default as
+        /// it's most likely the inputs or the filters matched nothing, like --exclude-from leaving
+        /// nothing of the input directories
+        #[arg(long)]
+        allow_empty: bool,
+
+        /// Skip the paths matching the globs of FILE, one per line, lines starting with '#' are
+        /// comments. Globs are matched against the paths of entries and their names, can be repeated
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        exclude_from: Vec<PathBuf>,
+
+        /// Convert the line endings of text files, detected as having no NUL byte in their first
+        /// 8000 bytes. This changes their content, so their checksums won't match the originals
+        #[arg(long, value_enum, default_value_t)]
+        eol: Eol,
+
+        /// Remove the output and fail if its compression ratio, the size of the inputs over its
+        /// own, is below RATIO, as the data is barely compressible
+        #[arg(long, value_name = "RATIO")]
+        min_ratio: Option<String>,
+
+        /// What to do with outputs below --min-ratio
+        #[arg(long, value_enum, default_value_t, requires = "min_ratio", value_name = "FALLBACK")]
+        min_ratio_fallback: RatioFallback,
+
+        /// Remove the input files after successful compression
+        #[arg(short = 'r', long)]
+        remove: bool,
+
+        /// Write statistics of the compression as a JSON object to FILE, or to stdout for "-":
+        /// sizes, ratio, duration, throughput, number of entries and parameters of the formats
+        #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+        stats_json: Option<PathBuf>,
+
+        /// Read the output back once written and compare its entries to the inputs, failing on
+        /// missing, extra or different files. MODE is "size", or "content" to also compare contents
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "size", value_name = "MODE", conflicts_with = "eol")]
+        verify_against_source: Option<SourceCheck>,
     },
     /// Decompresses one or more files, optionally into another folder
     #[command(visible_alias = "d")]
@@ -104,6 +211,92 @@ pub enum Subcommand {
         /// Disable Smart Unpack
         #[arg(long)]
         no_smart_unpack: bool,
+
+        /// Keep decompressing the other files after one fails, always the case with multiple files
+        #[arg(long = "continue")]
+        continue_on_error: bool,
+
+        /// Policy for symlinks with absolute targets in tar archives
+        #[arg(long, value_enum, default_value_t, value_name = "POLICY")]
+        absolute_symlinks: AbsoluteSymlinks,
+
+        /// Output file name, only valid with a single input file
+        #[arg(short = 'o', long, conflicts_with = "replace_suffix", value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+
+        /// Replace a suffix of the output file names, e.g. ".min.js=.js" for "data.min.js.gz" -> "data.js"
+        #[arg(long, value_name = "FROM=TO")]
+        replace_suffix: Option<String>,
+
+        /// Keep the setuid, setgid and sticky bits of extracted files, only for archives you trust, asks first
+        /// unless --yes is given
+        #[arg(long)]
+        preserve_special_bits: bool,
+
+        /// Remove the last N components of entry paths, e.g. 1 extracts "dir/sub/file" to "dir/sub"
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        strip_trailing_components: usize,
+
+        /// Extract the single top-level entry of archives under NAME, e.g. "project-1.2.0/" as
+        /// "project/". Archives with several entries at the top level, or none, fail
+        #[arg(long, value_name = "NAME", conflicts_with = "resume")]
+        rename_root: Option<String>,
+
+        /// Don't check the content of zip entries against their stored CRC32, which is faster, but
+        /// for encrypted entries whose CRC32 is always computed
+        #[arg(long)]
+        no_crc_check: bool,
+
+        /// Move the files replaced when overwriting to the trash, instead of deleting them. Fails if
+        /// they can't be moved, like when their filesystem has no trash
+        #[arg(long, conflicts_with = "confined")]
+        trash: bool,
+
+        /// Continue an interrupted extraction into the existing output, skipping the files already
+        /// extracted and replacing the others (tar and zip)
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "size-mtime", value_name = "MODE")]
+        resume: Option<ResumeMode>,
+
+        /// Create every entry relative to the opened output directory, refusing the symlinks that
+        /// lead outside of it, so untrusted archives can't write there (Unix, tar and zip)
+        #[arg(long, conflicts_with = "resume")]
+        confined: bool,
+
+        /// Extract entries whose path is absolute or looks like a remote location like "host:path",
+        /// relative to the output directory, instead of refusing them
+        #[arg(long)]
+        force_local: bool,
+
+        /// Restore the owners of tar entries, changing the stored user ids given like
+        /// "1000:1001,1002:0" (root only, Unix)
+        #[arg(long, value_name = "OLD:NEW,...")]
+        owner_map: Option<String>,
+
+        /// Restore the groups of tar entries, changing the stored group ids given like
+        /// "1000:1001,1002:0" (root only, Unix)
+        #[arg(long, value_name = "OLD:NEW,...")]
+        group_map: Option<String>,
+
+        /// Restore the access times of tar entries, from their PAX records or GNU headers
+        #[arg(long)]
+        preserve_atime: bool,
+
+        /// Don't check inputs against the checksum of a sibling .sha256, .sha512 or .blake3 file,
+        /// which is otherwise verified before extracting
+        #[arg(long)]
+        no_verify: bool,
+
+        /// Set the modification time of every extracted file to DATE instead of the stored one, for
+        /// deterministic outputs. Given like "2024-01-31", "2024-01-31T12:00:00Z" or "@1706702400".
+        /// Can't be used with --resume, as the files already extracted would get it too
+        #[arg(long, value_name = "DATE", conflicts_with = "resume")]
+        set_mtime: Option<String>,
+
+        /// Skip the first BYTES of the decompressed data of single file formats. Gzip files with a
+        /// .gzi index next to them, like the one of `bgzip --index`, are read from the member
+        /// holding the offset instead of from the start
+        #[arg(long, value_name = "BYTES")]
+        start_at: Option<u64>,
     },
     /// List contents of an archive
     #[command(visible_aliases = ["l", "ls"])]
@@ -115,9 +308,144 @@ pub enum Subcommand {
         /// Show archive contents as a tree
         #[arg(short, long)]
         tree: bool,
+
+        /// Show the size and modification time of each entry
+        #[arg(short, long, conflicts_with = "tree")]
+        long: bool,
+
+        /// strftime pattern used for modification times in the long listing
+        #[arg(long, requires = "long", value_name = "PATTERN")]
+        date_format: Option<String>,
+
+        /// Report the entry paths found more than once, like in concatenated tar archives, whose
+        /// extraction depends on the order of the entries
+        #[arg(long)]
+        list_duplicates: bool,
+
+        /// Cache the entries in a sidecar "<archive>.ouchidx", read by the next listings instead of
+        /// the archive until it changes
+        #[arg(long, overrides_with = "no_cache")]
+        cache: bool,
+
+        /// Neither read nor write the cache of entries, the default unless `--cache` comes after
+        #[arg(long, overrides_with = "cache")]
+        no_cache: bool,
+
+        /// Skip the first N entries, to page through large archives along with `--limit`
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        offset: usize,
+
+        /// List at most M entries, reading of the archive stops after them
+        #[arg(long, value_name = "M")]
+        limit: Option<usize>,
+    },
+    /// Rebuild the central directory of a damaged zip archive from its entries
+    Repair {
+        /// Zip archive to repair
+        #[arg(value_hint = ValueHint::FilePath)]
+        archive: PathBuf,
+
+        /// Repaired archive, defaults to the name of the archive ending with ".repaired.zip"
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
     },
 }
 
+/// Format of the .lz4 files created
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Lz4Format {
+    /// Modern frame format, understood by most tools
+    #[default]
+    Frame,
+    /// Legacy format, required by some tools like the Linux kernel
+    Legacy,
+}
+
+/// Order in which the inputs of an archive are walked
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Traversal {
+    /// Depth-first, each directory is followed by its whole content
+    #[default]
+    Dfs,
+    /// Breadth-first, all the entries of a depth come before the ones deeper
+    Bfs,
+}
+
+/// Line endings text files are converted to with `--eol`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Eol {
+    /// Unix line endings, "\n"
+    Lf,
+    /// Windows line endings, "\r\n"
+    Crlf,
+    /// Store the content as is
+    #[default]
+    Keep,
+}
+
+/// What `--verify-against-source` compares, besides the paths and types of entries
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceCheck {
+    /// Sizes of files
+    Size,
+    /// Sizes and contents of files
+    Content,
+}
+
+/// What's done with outputs compressed worse than `--min-ratio`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RatioFallback {
+    /// Remove the output and fail
+    #[default]
+    Fail,
+    /// Replace the output by the data without its compression formats, like "file.tar.zst" by
+    /// "file.tar", or by a copy of the input for single file formats
+    Store,
+}
+
+/// Output stream of informational messages and warnings
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageStream {
+    /// Mixed with the data, like the decompressed bytes or the listed entries
+    Stdout,
+    /// Kept apart from the data, so it can be piped
+    #[default]
+    Stderr,
+}
+
+/// Format of informational messages, warnings and errors
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Readable text, prefixed by the level like "[WARNING]"
+    #[default]
+    Human,
+    /// One JSON object per line, with "level" and "msg" fields
+    Json,
+    /// One logfmt record per line, like `level=warning msg="..."`
+    Logfmt,
+}
+
+/// How `--resume` tells that a file was already extracted
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResumeMode {
+    /// Same size and modification time as the entry
+    SizeMtime,
+    /// Same CRC32 as the entry for zip, which reads the files whole, tar falls back to size-mtime
+    Checksum,
+}
+
+/// What to do with the absolute targets of extracted symlinks
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AbsoluteSymlinks {
+    /// Recreate the symlinks with their targets as-is
+    Allow,
+    /// Skip the symlinks, with a warning
+    #[default]
+    Skip,
+    /// Rewrite the targets relative to the extraction root
+    Relativize,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -152,15 +480,38 @@ mod tests {
             quiet: false,
             gitignore: false,
             format: None,
+            stdin_name: None,
             // This is usually replaced in assertion tests
             password: None,
             threads: None,
+            messages: MessageStream::Stderr,
+            log_format: LogFormat::Human,
+            progress_template: None,
+            fail_on_warning: false,
             cmd: Subcommand::Decompress {
                 // Put a crazy value here so no test can assert it unintentionally
                 files: vec!["\x00\x11\x22".into()],
                 output_dir: None,
                 remove: false,
                 no_smart_unpack: false,
+                continue_on_error: false,
+                absolute_symlinks: AbsoluteSymlinks::Skip,
+                preserve_special_bits: false,
+                strip_trailing_components: 0,
+                rename_root: None,
+                no_crc_check: false,
+                trash: false,
+                resume: None,
+                confined: false,
+                owner_map: None,
+                group_map: None,
+                preserve_atime: false,
+                no_verify: false,
+                set_mtime: None,
+                start_at: None,
+                force_local: false,
+                output: None,
+                replace_suffix: None,
             },
         }
     }
@@ -175,6 +526,24 @@ mod tests {
                     output_dir: None,
                     remove: false,
                     no_smart_unpack: false,
+                    continue_on_error: false,
+                    absolute_symlinks: AbsoluteSymlinks::Skip,
+                    preserve_special_bits: false,
+                    strip_trailing_components: 0,
+                    rename_root: None,
+                    no_crc_check: false,
+                    trash: false,
+                    resume: None,
+                    confined: false,
+                    owner_map: None,
+                    group_map: None,
+                    preserve_atime: false,
+                    no_verify: false,
+                    set_mtime: None,
+                    start_at: None,
+                    force_local: false,
+                    output: None,
+                    replace_suffix: None,
                 },
                 ..mock_cli_args()
             }
@@ -187,6 +556,24 @@ mod tests {
                     output_dir: None,
                     remove: false,
                     no_smart_unpack: false,
+                    continue_on_error: false,
+                    absolute_symlinks: AbsoluteSymlinks::Skip,
+                    preserve_special_bits: false,
+                    strip_trailing_components: 0,
+                    rename_root: None,
+                    no_crc_check: false,
+                    trash: false,
+                    resume: None,
+                    confined: false,
+                    owner_map: None,
+                    group_map: None,
+                    preserve_atime: false,
+                    no_verify: false,
+                    set_mtime: None,
+                    start_at: None,
+                    force_local: false,
+                    output: None,
+                    replace_suffix: None,
                 },
                 ..mock_cli_args()
             }
@@ -199,6 +586,24 @@ mod tests {
                     output_dir: None,
                     remove: false,
                     no_smart_unpack: false,
+                    continue_on_error: false,
+                    absolute_symlinks: AbsoluteSymlinks::Skip,
+                    preserve_special_bits: false,
+                    strip_trailing_components: 0,
+                    rename_root: None,
+                    no_crc_check: false,
+                    trash: false,
+                    resume: None,
+                    confined: false,
+                    owner_map: None,
+                    group_map: None,
+                    preserve_atime: false,
+                    no_verify: false,
+                    set_mtime: None,
+                    start_at: None,
+                    force_local: false,
+                    output: None,
+                    replace_suffix: None,
                 },
                 ..mock_cli_args()
             }
@@ -213,7 +618,25 @@ mod tests {
                     level: None,
                     fast: false,
                     slow: false,
+                    preset: None,
                     follow_symlinks: false,
+                    read0: false,
+                    name: None,
+                    mode: None,
+                    embed_manifest: false,
+                    lz4_format: Lz4Format::Frame,
+                    mtime_granularity: 1,
+                    split_by_entry: None,
+                    frame_size: None,
+                    traversal: Traversal::Dfs,
+                    allow_empty: false,
+                    exclude_from: vec![],
+                    eol: Eol::Keep,
+                    min_ratio: None,
+                    min_ratio_fallback: RatioFallback::Fail,
+                    remove: false,
+                    stats_json: None,
+                    verify_against_source: None,
                 },
                 ..mock_cli_args()
             }
@@ -227,7 +650,25 @@ mod tests {
                     level: None,
                     fast: false,
                     slow: false,
+                    preset: None,
                     follow_symlinks: false,
+                    read0: false,
+                    name: None,
+                    mode: None,
+                    embed_manifest: false,
+                    lz4_format: Lz4Format::Frame,
+                    mtime_granularity: 1,
+                    split_by_entry: None,
+                    frame_size: None,
+                    traversal: Traversal::Dfs,
+                    allow_empty: false,
+                    exclude_from: vec![],
+                    eol: Eol::Keep,
+                    min_ratio: None,
+                    min_ratio_fallback: RatioFallback::Fail,
+                    remove: false,
+                    stats_json: None,
+                    verify_against_source: None,
                 },
                 ..mock_cli_args()
             }
@@ -241,7 +682,25 @@ mod tests {
                     level: None,
                     fast: false,
                     slow: false,
+                    preset: None,
                     follow_symlinks: false,
+                    read0: false,
+                    name: None,
+                    mode: None,
+                    embed_manifest: false,
+                    lz4_format: Lz4Format::Frame,
+                    mtime_granularity: 1,
+                    split_by_entry: None,
+                    frame_size: None,
+                    traversal: Traversal::Dfs,
+                    allow_empty: false,
+                    exclude_from: vec![],
+                    eol: Eol::Keep,
+                    min_ratio: None,
+                    min_ratio_fallback: RatioFallback::Fail,
+                    remove: false,
+                    stats_json: None,
+                    verify_against_source: None,
                 },
                 ..mock_cli_args()
             }
@@ -266,7 +725,25 @@ mod tests {
                         level: None,
                         fast: false,
                         slow: false,
+                        preset: None,
                         follow_symlinks: false,
+                        read0: false,
+                        name: None,
+                        mode: None,
+                        embed_manifest: false,
+                        lz4_format: Lz4Format::Frame,
+                        mtime_granularity: 1,
+                        split_by_entry: None,
+                        frame_size: None,
+                        traversal: Traversal::Dfs,
+                        allow_empty: false,
+                        exclude_from: vec![],
+                        eol: Eol::Keep,
+                        min_ratio: None,
+                        min_ratio_fallback: RatioFallback::Fail,
+                        remove: false,
+                        stats_json: None,
+                        verify_against_source: None,
                     },
                     format: Some("tar.gz".into()),
                     ..mock_cli_args()
@@ -278,7 +755,6 @@ mod tests {
     #[test]
     fn test_clap_cli_err() {
         assert!(CliArgs::try_parse_from(args_splitter("ouch c")).is_err());
-        assert!(CliArgs::try_parse_from(args_splitter("ouch c input")).is_err());
         assert!(CliArgs::try_parse_from(args_splitter("ouch d")).is_err());
         assert!(CliArgs::try_parse_from(args_splitter("ouch l")).is_err());
     }
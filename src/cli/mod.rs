@@ -1,19 +1,33 @@
 //! CLI related functions, uses the clap argparsing definitions from `args.rs`.
 
 mod args;
+mod preset;
 
 use std::{
-    io,
+    io::{self, Read},
     path::{Path, PathBuf},
 };
 
+use bstr::ByteSlice;
 use clap::Parser;
 use fs_err as fs;
 
-pub use self::args::{CliArgs, Subcommand};
+pub use self::{
+    args::{
+        AbsoluteSymlinks, CliArgs, Eol, LogFormat, Lz4Format, MessageStream, RatioFallback, ResumeMode, SourceCheck,
+        Subcommand, Traversal,
+    },
+    preset::Preset,
+};
 use crate::{
     accessible::set_accessible,
-    utils::{is_path_stdin, FileVisibilityPolicy},
+    commands::compress::derive_output_path,
+    error::FinalError,
+    utils::{
+        colors, is_path_stdin, logger, output,
+        progress::{self, ProgressTemplate},
+        EscapedPathDisplay, FileVisibilityPolicy,
+    },
     QuestionPolicy,
 };
 
@@ -21,17 +35,67 @@ impl CliArgs {
     /// A helper method that calls `clap::Parser::parse`.
     ///
     /// And:
-    ///   1. Make paths absolute.
-    ///   2. Checks the QuestionPolicy.
+    ///   1. Resolve `--preset` into the options it stands for.
+    ///   2. Name the output after the input, or after `--stdin-name` for stdin, if only one path was
+    ///      given to compress. Refuse an existing last path as the output when the formats aren't
+    ///      taken from it.
+    ///   3. Read the extra input paths from stdin, if `--read0` was passed.
+    ///   4. Make paths absolute.
+    ///   5. Checks the QuestionPolicy.
     pub fn parse_and_validate_args() -> crate::Result<(Self, QuestionPolicy, FileVisibilityPolicy)> {
         let mut args = Self::parse();
 
         set_accessible(args.accessible);
+        output::set_message_stream(args.messages);
+        logger::set_log_format(args.log_format);
+        colors::set_colored_text(colors::is_colored_text_supported());
+        if let Some(template) = &args.progress_template {
+            progress::set_progress_template(ProgressTemplate::parse(template)?);
+        }
+
+        args.apply_preset()?;
+
+        if let Subcommand::Compress {
+            files,
+            output,
+            read0: false,
+            ..
+        } = &mut args.cmd
+        {
+            // A single path is the input, the output is named after it
+            if files.is_empty() {
+                // Data from stdin is compressed into the file it's named after
+                let derived_output = match &args.stdin_name {
+                    Some(stdin_name) if is_path_stdin(output) => PathBuf::from(stdin_name),
+                    _ => derive_output_path(output, args.format.as_deref())?,
+                };
+                files.push(std::mem::replace(output, derived_output));
+            } else if args.format.is_some() && output.symlink_metadata().is_ok() {
+                // The formats don't come from the last path, which may be one more input to
+                // compress rather than the output, don't overwrite it
+                return Err(FinalError::with_title("Missing the output path")
+                    .detail(format!(
+                        "{} already exists, it may be one of the files to compress",
+                        EscapedPathDisplay::new(output)
+                    ))
+                    .hint("Pass a new output file as the last path when compressing multiple files")
+                    .hint("Or leave --format out to take the formats from the extensions of the output")
+                    .into());
+            }
+        }
+
+        if let Subcommand::Compress { files, read0: true, .. } = &mut args.cmd {
+            // "-" is only a placeholder for the paths read from stdin
+            files.retain(|path| !is_path_stdin(path));
+            files.extend(read_nul_separated_paths(io::stdin().lock())?);
+        }
 
-        let (Subcommand::Compress { files, .. }
+        if let Subcommand::Compress { files, .. }
         | Subcommand::Decompress { files, .. }
-        | Subcommand::List { archives: files, .. }) = &mut args.cmd;
-        *files = canonicalize_files(files)?;
+        | Subcommand::List { archives: files, .. } = &mut args.cmd
+        {
+            *files = canonicalize_files(files)?;
+        }
 
         let skip_questions_positively = match (args.yes, args.no) {
             (false, false) => QuestionPolicy::Ask,
@@ -50,6 +114,26 @@ impl CliArgs {
     }
 }
 
+/// Read a list of paths separated by NUL bytes, as printed by `find -print0`.
+///
+/// Unlike newline-separated lists, this supports every possible filename.
+fn read_nul_separated_paths(mut reader: impl Read) -> crate::Result<Vec<PathBuf>> {
+    let mut buffer = vec![];
+    reader.read_to_end(&mut buffer)?;
+
+    buffer
+        .split(|&byte| byte == b'\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| {
+            path.to_path().map(Path::to_path_buf).map_err(|_| {
+                FinalError::with_title("Failed to read paths from stdin")
+                    .detail(format!("Path '{}' is not valid UTF-8", path.as_bstr()))
+                    .into()
+            })
+        })
+        .collect()
+}
+
 fn canonicalize_files(files: &[impl AsRef<Path>]) -> io::Result<Vec<PathBuf>> {
     files
         .iter()
@@ -0,0 +1,322 @@
+//! Named sets of compression options for `--preset`, built in or defined in the config file.
+//!
+//! The config file is a JSON object read from `$OUCH_CONFIG`, or else `ouch/config.json` in the
+//! config directory of the platform, like `~/.config` on Linux. Presets are defined under
+//! "presets" with the fields of [`Preset`], e.g.:
+//!
+//! ```json
+//! { "presets": { "photos": { "format": "zip", "level": 3, "exclude": ["*.tmp"] } } }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use serde::Deserialize;
+
+use crate::{
+    cli::{CliArgs, Subcommand},
+    error::FinalError,
+    extension,
+    utils::EscapedPathDisplay,
+};
+
+/// Names of the built-in presets, they can't be redefined in the config file
+const BUILT_IN_PRESETS: [&str; 3] = ["backup", "web", "fast"];
+
+/// Directories of version control systems, skipped by the backup preset
+const VCS_DIRECTORIES: [&str; 6] = [".git", ".hg", ".svn", ".bzr", "_darcs", "CVS"];
+
+/// Options set by a preset, options passed explicitly take precedence over them
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Preset {
+    /// Formats of the output like "tar.zst", in place of `--format` when the output has no
+    /// extension, or when only the input is given
+    pub format: Option<String>,
+    /// Compression level, unless `--level`, `--fast` or `--slow` is passed
+    pub level: Option<i16>,
+    // Set the flags of the same name
+    pub fast: bool,
+    pub slow: bool,
+    pub gitignore: bool,
+    pub hidden: bool,
+    pub follow_symlinks: bool,
+    /// Globs of the entries to skip, like the lines of `--exclude-from` files
+    pub exclude: Vec<String>,
+}
+
+/// Contents of the config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    presets: BTreeMap<String, Preset>,
+}
+
+impl Preset {
+    /// The built-in preset named `name`:
+    /// - `backup`: `--format tar.zst --level 19`, skipping the directories of version control
+    ///   systems like `.git`. Permissions, modification times and symlinks are stored as always
+    /// - `web`: `--format tar.br --slow`. Media files are compressed like the others, as a brotli
+    ///   stream can't store some of its entries as they are
+    /// - `fast`: `--format tar.lz4 --fast`. Nothing is left out of the entries to save time, as
+    ///   storing their metadata costs next to nothing
+    fn built_in(name: &str) -> Option<Self> {
+        let preset = match name {
+            "backup" => Self {
+                format: Some("tar.zst".to_string()),
+                level: Some(19),
+                exclude: VCS_DIRECTORIES.map(String::from).to_vec(),
+                ..Self::default()
+            },
+            "web" => Self {
+                format: Some("tar.br".to_string()),
+                slow: true,
+                ..Self::default()
+            },
+            "fast" => Self {
+                format: Some("tar.lz4".to_string()),
+                fast: true,
+                ..Self::default()
+            },
+            _ => return None,
+        };
+        Some(preset)
+    }
+
+    /// The preset named `name`, built in or defined in the config file
+    pub fn named(name: &str) -> crate::Result<Self> {
+        Self::find(name, config_path().as_deref())
+    }
+
+    /// The preset named `name`, built in or defined in the config file at `config_path`
+    fn find(name: &str, config_path: Option<&Path>) -> crate::Result<Self> {
+        if let Some(preset) = Self::built_in(name) {
+            return Ok(preset);
+        }
+
+        let config = match config_path {
+            Some(path) if path.exists() => read_config(path)?,
+            _ => Config::default(),
+        };
+        let mut presets = config.presets;
+        if let Some(preset) = presets.remove(name) {
+            return Ok(preset);
+        }
+
+        let mut error = FinalError::with_title(format!("Unknown preset '{name}'"))
+            .detail(format!("Built-in presets: {}", BUILT_IN_PRESETS.join(", ")));
+        if !presets.is_empty() {
+            let defined: Vec<_> = presets.keys().map(String::as_str).collect();
+            error = error.detail(format!("Presets of the config file: {}", defined.join(", ")));
+        }
+        if let Some(path) = config_path {
+            error = error.hint(format!("Presets can be defined in {}", EscapedPathDisplay::new(path)));
+        }
+        Err(error.into())
+    }
+}
+
+/// Path of the config file, `$OUCH_CONFIG` or `ouch/config.json` in the config directory
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("OUCH_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| Path::new(dir).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("ouch/config.json"))
+}
+
+fn read_config(path: &Path) -> crate::Result<Config> {
+    let invalid_config = |detail: String| {
+        FinalError::with_title(format!("Invalid config file {}", EscapedPathDisplay::new(path))).detail(detail)
+    };
+
+    let config: Config =
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(|err| invalid_config(err.to_string()))?;
+    if let Some(name) = config
+        .presets
+        .keys()
+        .find(|name| BUILT_IN_PRESETS.contains(&name.as_str()))
+    {
+        return Err(
+            invalid_config(format!("'{name}' is a built-in preset, it can't be redefined"))
+                .hint("Give the preset another name")
+                .into(),
+        );
+    }
+    Ok(config)
+}
+
+impl CliArgs {
+    /// Resolve `--preset` into the options it stands for, without overriding the ones passed
+    /// explicitly, see [`Preset`].
+    ///
+    /// Done before the output is named after the input, so the format of the preset can be used.
+    pub(super) fn apply_preset(&mut self) -> crate::Result<()> {
+        let Subcommand::Compress { preset: Some(name), .. } = &self.cmd else {
+            return Ok(());
+        };
+
+        let preset = Preset::named(name)?;
+        self.apply_preset_options(preset);
+        Ok(())
+    }
+
+    fn apply_preset_options(&mut self, preset: Preset) {
+        let Subcommand::Compress {
+            files,
+            output,
+            read0,
+            level,
+            fast,
+            slow,
+            follow_symlinks,
+            ..
+        } = &mut self.cmd
+        else {
+            return;
+        };
+
+        // The output is the input when it's the only path, it then gets the extensions of the
+        // format. Otherwise its own extensions take precedence.
+        let output_is_input = files.is_empty() && !*read0;
+        let output_has_extensions = extension::extensions_from_path(output).is_ok_and(|formats| !formats.is_empty());
+        if self.format.is_none() && (output_is_input || !output_has_extensions) {
+            self.format = preset.format.map(Into::into);
+        }
+
+        // The level is only taken from the preset if none of its flags were passed
+        if level.is_none() && !*fast && !*slow {
+            *level = preset.level;
+            *fast = preset.fast;
+            *slow = preset.slow;
+        }
+
+        self.gitignore |= preset.gitignore;
+        self.hidden |= preset.hidden;
+        *follow_symlinks |= preset.follow_symlinks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    fn parse_with_preset(args: &str) -> CliArgs {
+        let mut args = CliArgs::try_parse_from(args.split_whitespace()).unwrap();
+        let Subcommand::Compress { preset: Some(name), .. } = &args.cmd else {
+            panic!("expected a compress command with a preset");
+        };
+        let preset = Preset::built_in(name).unwrap();
+        args.apply_preset_options(preset);
+        args
+    }
+
+    #[test]
+    fn test_apply_preset() {
+        let args = parse_with_preset("ouch compress --preset backup dir");
+        assert_eq!(args.format.as_deref(), Some("tar.zst".as_ref()));
+        assert!(!args.gitignore);
+        let Subcommand::Compress {
+            level: Some(19),
+            fast: false,
+            slow: false,
+            follow_symlinks: false,
+            ..
+        } = args.cmd
+        else {
+            panic!("unexpected options for backup: {:?}", args.cmd);
+        };
+        assert_eq!(Preset::built_in("backup").unwrap().exclude, VCS_DIRECTORIES);
+
+        // Explicit options take precedence
+        let args = parse_with_preset("ouch compress --preset backup --level 3 a.txt a.tar.gz");
+        assert_eq!(args.format, None);
+        assert!(matches!(args.cmd, Subcommand::Compress { level: Some(3), .. }));
+        let args = parse_with_preset("ouch --format zip compress --preset backup a.txt archive");
+        assert_eq!(args.format.as_deref(), Some("zip".as_ref()));
+
+        // Outputs without extensions get the ones of the preset
+        let args = parse_with_preset("ouch compress --preset web a.txt b.txt site");
+        assert_eq!(args.format.as_deref(), Some("tar.br".as_ref()));
+        assert!(matches!(
+            args.cmd,
+            Subcommand::Compress {
+                level: None,
+                fast: false,
+                slow: true,
+                ..
+            }
+        ));
+
+        let args = parse_with_preset("ouch compress --preset fast dir");
+        assert_eq!(args.format.as_deref(), Some("tar.lz4".as_ref()));
+        assert!(matches!(
+            args.cmd,
+            Subcommand::Compress {
+                level: None,
+                fast: true,
+                slow: false,
+                ..
+            }
+        ));
+
+        let args = parse_with_preset("ouch compress --preset fast --slow a.txt a.tar.lz4");
+        assert!(matches!(
+            args.cmd,
+            Subcommand::Compress {
+                fast: false,
+                slow: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_preset_from_config_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config_path = temp_dir.path().join("config.json");
+        fs::write(
+            &config_path,
+            r#"{ "presets": { "photos": { "format": "zip", "level": 3, "hidden": true, "exclude": ["*.tmp"] } } }"#,
+        )
+        .unwrap();
+
+        let preset = Preset::find("photos", Some(&config_path)).unwrap();
+        assert_eq!(
+            preset,
+            Preset {
+                format: Some("zip".to_string()),
+                level: Some(3),
+                hidden: true,
+                exclude: vec!["*.tmp".to_string()],
+                ..Preset::default()
+            }
+        );
+        // Built-in presets don't need the config file
+        assert_eq!(Preset::find("fast", None).unwrap(), Preset::built_in("fast").unwrap());
+        assert!(Preset::find("missing", Some(&config_path)).is_err());
+        assert!(Preset::find("photos", Some(&temp_dir.path().join("missing.json"))).is_err());
+
+        // Typos in the config aren't silently ignored
+        fs::write(&config_path, r#"{ "presets": { "photos": { "levle": 3 } } }"#).unwrap();
+        assert!(Preset::find("photos", Some(&config_path)).is_err());
+        fs::write(&config_path, r#"{ "presets": { "backup": {} } }"#).unwrap();
+        assert!(Preset::find("other", Some(&config_path)).is_err());
+    }
+}
@@ -1,25 +1,407 @@
 use std::{
-    io::{self, BufWriter, Cursor, Seek, Write},
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    io::{self, BufReader, BufWriter, Cursor, Read, Seek, Write},
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 
 use fs_err as fs;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use tempfile::TempDir;
 
 use super::warn_user_about_loading_sevenz_in_memory;
 use crate::{
-    archive,
-    commands::warn_user_about_loading_zip_in_memory,
-    extension::{split_first_compression_format, CompressionFormat::*, Extension},
-    utils::{io::lock_and_flush_output_stdio, user_wants_to_continue, FileVisibilityPolicy},
+    archive::{self, EntryModes},
+    cli::{Eol, Lz4Format, RatioFallback},
+    commands::{decompress::chain_reader_decoder, warn_user_about_loading_zip_in_memory},
+    error::{Error, FinalError},
+    extension::{
+        flatten_compression_formats, parse_format_flag, separate_known_extensions_from_name,
+        split_first_compression_format,
+        CompressionFormat::{self, *},
+        Extension,
+    },
+    utils::{
+        self, cd_into_same_dir_as, content_size,
+        io::{lock_and_flush_output_stdio, read_with_line_endings},
+        is_path_stdin,
+        logger::{info, info_accessible, warning},
+        progress::{input_size, AggregateProgress, ProgressReader},
+        user_wants_to_continue, EscapedPathDisplay, FileVisibilityPolicy,
+    },
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
+/// Output path used when only the input is given, named after it with the extensions of
+/// `--format`, like "dir/" with "tar.zst" -> "dir.tar.zst", in the current directory.
+pub fn derive_output_path(input: &Path, format: Option<&OsStr>) -> crate::Result<PathBuf> {
+    let Some(format) = format else {
+        return Err(FinalError::with_title("Missing the output path")
+            .detail("The compression formats can't be guessed without it")
+            .hint("Pass the output file as the last path, like 'archive.tar.gz'")
+            .hint("Or pass --format to name the output after the input")
+            .into());
+    };
+    let extensions = parse_format_flag(format)?;
+
+    // Paths like "." have no name of their own
+    let input = input.canonicalize().unwrap_or_else(|_| input.to_path_buf());
+    let mut name = input.file_name().unwrap_or(OsStr::new("archive")).to_owned();
+    for extension in &extensions {
+        name.push(".");
+        name.push(extension.to_string());
+    }
+
+    Ok(PathBuf::from(name))
+}
+
+/// Replaces the "-" input by a temporary copy of the data read from stdin, returned to be kept
+/// until compression is done, as archives need the size of their entries up front.
+///
+/// Unless `name` is given, the copy is stored under `stdin_name` without its compression
+/// extensions, like "data.tar.gz" -> "data", or as "stdin".
+pub fn copy_stdin_input(
+    files: &mut [PathBuf],
+    name: &mut Option<String>,
+    stdin_name: Option<&str>,
+) -> crate::Result<Option<TempDir>> {
+    let Some(index) = files.iter().position(|path| is_path_stdin(path)) else {
+        return Ok(None);
+    };
+    if files.len() > 1 {
+        return Err(FinalError::with_title("Cannot compress stdin along with other files")
+            .detail("The data read from stdin is compressed on its own")
+            .hint("Pass \"-\" as the only input, or use --read0 to read the paths of the files from stdin")
+            .into());
+    }
+
+    if name.is_none() {
+        let entry_name = match stdin_name {
+            Some(stdin_name) => separate_known_extensions_from_name(Path::new(stdin_name))?.0,
+            None => Path::new("stdin"),
+        };
+        *name = Some(entry_name.to_string_lossy().into_owned());
+    }
+
+    let temp_dir = tempfile::tempdir()?;
+    let copy_path = temp_dir.path().join("stdin");
+    io::copy(&mut io::stdin().lock(), &mut fs::File::create(&copy_path)?)?;
+    files[index] = copy_path;
+
+    Ok(Some(temp_dir))
+}
+
+/// Bytes added to volumes for each entry, about the size of a tar header and its padding, or of
+/// the local and central directory headers of zip
+const VOLUME_ENTRY_OVERHEAD: u64 = 1024;
+
+/// Splits the entries of `files` in volumes of at most `max_size` bytes, going to the next volume
+/// before an entry that would go over it.
+///
+/// Sizes are the ones on disk, so compressed volumes end up smaller. An entry larger than
+/// `max_size` is alone in its volume. Returns the absolute paths of the entries of each
+/// volume, to be given to [`FileVisibilityPolicy::only_paths`].
+pub fn plan_volumes(
+    files: &[PathBuf],
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+    max_size: u64,
+) -> crate::Result<Vec<HashSet<PathBuf>>> {
+    let mut volumes = vec![HashSet::new()];
+    let mut volume_size = 0;
+
+    for file in files {
+        let previous_location = cd_into_same_dir_as(file)?;
+        let current_dir = env::current_dir()?;
+
+        // Unwrap safety:
+        //   paths should be canonicalized by now, and the root directory rejected.
+        let filename = file.file_name().unwrap();
+
+        for entry in file_visibility_policy.build_walker(filename) {
+            let path = entry?.into_path();
+            let metadata = if follow_symlinks {
+                path.metadata()
+            } else {
+                path.symlink_metadata()
+            };
+            // Broken symlinks are skipped by the archive builders
+            let size = metadata.map_or(0, |metadata| if metadata.is_file() { metadata.len() } else { 0 });
+            let size = size + VOLUME_ENTRY_OVERHEAD;
+
+            let volume = volumes.last().unwrap();
+            if !volume.is_empty() && volume_size + size > max_size {
+                volumes.push(HashSet::new());
+                volume_size = 0;
+            }
+            volume_size += size;
+            volumes.last_mut().unwrap().insert(current_dir.join(path));
+        }
+
+        env::set_current_dir(previous_location)?;
+    }
+
+    Ok(volumes)
+}
+
+/// Reads the globs of the `--exclude-from` files, one per line, skipping empty lines and comments
+/// starting with '#', along with the `globs` of `--preset`.
+pub fn read_exclude_patterns(files: &[PathBuf], globs: &[String]) -> crate::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let glob = Glob::new(glob).map_err(|err| {
+            FinalError::with_title(format!("Invalid pattern '{glob}' in the preset")).detail(err.kind().to_string())
+        })?;
+        builder.add(glob);
+    }
+    for file in files {
+        let patterns = fs::read_to_string(file)?;
+        for (index, line) in patterns.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let glob = Glob::new(line).map_err(|err| {
+                FinalError::with_title(format!(
+                    "Invalid pattern at line {} of {}",
+                    index + 1,
+                    EscapedPathDisplay::new(file)
+                ))
+                .detail(err.kind().to_string())
+            })?;
+            builder.add(glob);
+        }
+    }
+
+    builder.build().map_err(|err| {
+        FinalError::with_title("Invalid exclusion patterns")
+            .detail(err.to_string())
+            .into()
+    })
+}
+
+/// Parses the ratio of `--min-ratio`, like "1.5" for outputs at most two thirds of their inputs
+pub fn parse_ratio(ratio: &str) -> crate::Result<f64> {
+    match ratio.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(FinalError::with_title(format!("Invalid compression ratio '{ratio}'"))
+            .detail("It must be a positive number, the size of the inputs over the one of the output")
+            .hint("For outputs at most half the size of their inputs: --min-ratio 2")
+            .into()),
+    }
+}
+
+/// What replaced an output removed by `--min-ratio`, with `--min-ratio-fallback store`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredOutput {
+    /// The data without compression formats, written at this path
+    Stored(PathBuf),
+    /// The input at this path, which is already the data without compression formats
+    KeptInput(PathBuf),
+}
+
+impl StoredOutput {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Stored(path) | Self::KeptInput(path) => path,
+        }
+    }
+}
+
+/// Checks the output of a finished compression against `--min-ratio`, whose ratio is the size of
+/// the files of the inputs over the one of the output.
+///
+/// A worse output is removed, then either an error is returned or it's replaced by the data
+/// without its compression formats, as told by `fallback`. Empty inputs are never checked.
+/// Returns what replaced the output, `None` if it was kept.
+#[allow(clippy::too_many_arguments)]
+pub fn enforce_min_ratio(
+    files: &[PathBuf],
+    extensions: &[Extension],
+    output_path: &Path,
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+    min_ratio: f64,
+    fallback: RatioFallback,
+    question_policy: QuestionPolicy,
+) -> crate::Result<Option<StoredOutput>> {
+    let (input_size, _) = measure_inputs(files, file_visibility_policy, follow_symlinks)?;
+    let output_size = fs::metadata(output_path)?.len();
+    if input_size == 0 {
+        return Ok(None);
+    }
+    let ratio = input_size as f64 / output_size.max(1) as f64;
+    if ratio >= min_ratio {
+        return Ok(None);
+    }
+
+    let stored = match fallback {
+        RatioFallback::Fail => Ok(None),
+        RatioFallback::Store => store_uncompressed(files, extensions, output_path, question_policy),
+    };
+    utils::remove_file_or_dir(output_path)?;
+    if let Some(stored) = stored? {
+        return Ok(Some(stored));
+    }
+
+    Err(Error::PoorCompressionRatio {
+        path: output_path.to_path_buf(),
+        ratio,
+        min_ratio,
+    })
+}
+
+/// Total size of the files of the inputs and number of entries, as walked by the archive builders
+pub fn measure_inputs(
+    files: &[PathBuf],
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+) -> crate::Result<(u64, u64)> {
+    let mut size = 0;
+    let mut entries = 0;
+    for file in files {
+        if !file.is_dir() {
+            size += content_size(file).unwrap_or(0);
+            entries += 1;
+            continue;
+        }
+
+        let previous_location = cd_into_same_dir_as(file)?;
+        // Unwrap safety:
+        //   paths should be canonicalized by now, and the root directory rejected.
+        for entry in file_visibility_policy.build_walker(file.file_name().unwrap()) {
+            let path = entry?.into_path();
+            entries += 1;
+            let metadata = if follow_symlinks {
+                path.metadata()
+            } else {
+                path.symlink_metadata()
+            };
+            size += metadata.map_or(0, |metadata| if metadata.is_file() { metadata.len() } else { 0 });
+        }
+        env::set_current_dir(previous_location)?;
+    }
+    Ok((size, entries))
+}
+
+/// Writes the data compressed at `output_path` without its compression formats, next to it, like
+/// "file.tar.zst" decoded to "file.tar". For single file formats that's the input, which is kept
+/// as is if it's already there.
+///
+/// Returns `None` for archives that have no compression format to remove, like zip ones, or if
+/// the user didn't want to replace what's at the path of the stored data.
+fn store_uncompressed(
+    files: &[PathBuf],
+    extensions: &[Extension],
+    output_path: &Path,
+    question_policy: QuestionPolicy,
+) -> crate::Result<Option<StoredOutput>> {
+    let (first_format, formats) = split_first_compression_format(extensions);
+    let (archive_extension, decoded) = match first_format {
+        Tar => (Some("tar"), formats),
+        Zip | SevenZip | Rar if formats.is_empty() => {
+            warning(format!(
+                "{first_format:?} archives compress their own entries, {} can't be stored without compression",
+                EscapedPathDisplay::new(output_path)
+            ));
+            return Ok(None);
+        }
+        Zip => (Some("zip"), formats),
+        SevenZip => (Some("7z"), formats),
+        Rar => (Some("rar"), formats),
+        _ => (None, flatten_compression_formats(extensions)),
+    };
+
+    // Named after the output without its extensions, unless they were given with `--format`
+    let name = match separate_known_extensions_from_name(output_path) {
+        Ok((name, output_extensions))
+            if !output_extensions.is_empty()
+                && flatten_compression_formats(&output_extensions) == flatten_compression_formats(extensions) =>
+        {
+            output_path.with_file_name(name).into_os_string()
+        }
+        _ => output_path.as_os_str().to_owned(),
+    };
+    let mut stored_path = name;
+    if let Some(extension) = archive_extension {
+        stored_path.push(".");
+        stored_path.push(extension);
+    }
+    let stored_path = PathBuf::from(stored_path);
+
+    if files
+        .iter()
+        .any(|file| same_file::is_same_file(file, &stored_path).unwrap_or(false))
+    {
+        info_accessible(format!(
+            "Keeping {} uncompressed, as the compression ratio is below the minimum",
+            EscapedPathDisplay::new(&stored_path)
+        ));
+        return Ok(Some(StoredOutput::KeptInput(stored_path)));
+    }
+
+    let mut reader: Box<dyn Read> = Box::new(BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(output_path)?));
+    for format in decoded.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+    let Some(mut writer) =
+        utils::ask_to_create_file(&stored_path, question_policy, QuestionAction::Compression, false)?
+    else {
+        return Ok(None);
+    };
+    io::copy(&mut reader, &mut writer)?;
+
+    info_accessible(format!(
+        "Stored {} without compression, as the compression ratio is below the minimum",
+        EscapedPathDisplay::new(&stored_path)
+    ));
+    Ok(Some(StoredOutput::Stored(stored_path)))
+}
+
+/// Parses the sizes of `--split-by-entry` and `--frame-size`, like "100MB" or "1GiB", `subject`
+/// names what it's the size of in errors.
+pub fn parse_size(size: &str, subject: &str) -> crate::Result<u64> {
+    match size.parse::<bytesize::ByteSize>() {
+        Ok(size) if size.as_u64() > 0 => Ok(size.as_u64()),
+        Ok(_) => Err(FinalError::with_title(format!("Invalid {subject} size"))
+            .detail(format!("The size of a {subject} must be more than zero"))
+            .into()),
+        Err(err) => Err(FinalError::with_title(format!("Invalid {subject} size"))
+            .detail(err)
+            .hint("Use a size like \"100MB\" or \"1GiB\"")
+            .into()),
+    }
+}
+
+/// Path of the volume numbered `index`, like "archive.tar.gz" -> "archive.002.tar.gz".
+pub fn volume_path(output_path: &Path, index: usize) -> crate::Result<PathBuf> {
+    let (base, extensions) = separate_known_extensions_from_name(output_path)?;
+
+    let mut name = base.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".{index:03}"));
+    for extension in &extensions {
+        name.push(".");
+        name.push(extension.to_string());
+    }
+
+    Ok(output_path.with_file_name(name))
+}
+
 /// Compress files into `output_file`.
 ///
 /// # Arguments:
 /// - `files`: is the list of paths to be compressed: ["dir/file1.txt", "dir/file2.txt"]
 /// - `extensions`: is a list of compression formats for compressing, example: [Tar, Gz] (in compression order)
 /// - `output_file` is the resulting compressed file name, example: "archive.tar.gz"
+/// - `name`: is the name stored for the single input, in the archive entries or the gzip header
+/// - `modes`: are the permissions stored for the archive entries instead of the ones on disk
+/// - `embed_manifest`: stores a manifest describing the archive as its first entry
+/// - `lz4_format`: is the format used for the lz4 compression
+/// - `mtime_granularity`: is the number of seconds stored modification times are rounded down to
+/// - `zstd_frame_size`: is the uncompressed size of zstd frames, a single frame is written if `None`
 ///
 /// # Return value
 /// - Returns `Ok(true)` if compressed all files normally.
@@ -35,94 +417,62 @@ pub fn compress_files(
     question_policy: QuestionPolicy,
     file_visibility_policy: FileVisibilityPolicy,
     level: Option<i16>,
+    name: Option<&str>,
+    modes: EntryModes,
+    embed_manifest: bool,
+    lz4_format: Lz4Format,
+    mtime_granularity: u64,
+    zstd_frame_size: Option<u64>,
+    eol: Eol,
 ) -> crate::Result<bool> {
+    let manifest = embed_manifest.then(|| build_manifest(&files, &extensions, name));
+
     // If the input files contain a directory, then the total size will be underestimated
     let file_writer = BufWriter::with_capacity(BUFFER_CAPACITY, output_file);
 
     let mut writer: Box<dyn Send + Write> = Box::new(file_writer);
 
-    // Grab previous encoder and wrap it inside of a new one
-    let chain_writer_encoder = |format: &_, encoder| -> crate::Result<_> {
-        let encoder: Box<dyn Send + Write> = match format {
-            Gzip => Box::new(
-                // by default, ParCompress uses a default compression level of 3
-                // instead of the regular default that flate2 uses
-                gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
-                    .compression_level(
-                        level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
-                    )
-                    .from_writer(encoder),
-            ),
-            Bzip => Box::new(bzip2::write::BzEncoder::new(
-                encoder,
-                level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
-            )),
-            Bzip3 => {
-                #[cfg(not(feature = "bzip3"))]
-                return Err(archive::bzip3_stub::no_support());
-
-                #[cfg(feature = "bzip3")]
-                Box::new(
-                    // Use block size of 16 MiB
-                    bzip3::write::Bz3Encoder::new(encoder, 16 * 2_usize.pow(20))?,
-                )
-            }
-            Lz4 => Box::new(lz4_flex::frame::FrameEncoder::new(encoder).auto_finish()),
-            Lzma => {
-                return Err(crate::Error::UnsupportedFormat {
-                    reason: "LZMA1 compression is not supported in ouch, use .xz instead.".to_string(),
-                })
-            }
-            Xz => Box::new(liblzma::write::XzEncoder::new(
-                encoder,
-                level.map_or(6, |l| (l as u32).clamp(0, 9)),
-            )),
-            Lzip => {
-                return Err(crate::Error::UnsupportedFormat {
-                    reason: "Lzip compression is not supported in ouch.".to_string(),
-                })
-            }
-            Snappy => Box::new(
-                gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
-                    .compression_level(gzp::par::compress::Compression::new(
-                        level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
-                    ))
-                    .from_writer(encoder),
-            ),
-            Zstd => {
-                let mut zstd_encoder = zstd::stream::write::Encoder::new(
-                    encoder,
-                    level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
-                        (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
-                    }),
-                )?;
-                // Use all available PHYSICAL cores for compression
-                zstd_encoder.multithread(num_cpus::get_physical() as u32)?;
-                Box::new(zstd_encoder.auto_finish())
-            }
-            Brotli => {
-                let default_level = 11; // Same as brotli CLI, default to highest compression
-                let level = level.unwrap_or(default_level).clamp(0, 11) as u32;
-                let win_size = 22; // default to 2^22 = 4 MiB window size
-                Box::new(brotli::CompressorWriter::new(encoder, BUFFER_CAPACITY, level, win_size))
-            }
-            Tar | Zip | Rar | SevenZip => unreachable!(),
-        };
-        Ok(encoder)
-    };
-
     let (first_format, formats) = split_first_compression_format(&extensions);
 
     for format in formats.iter().rev() {
-        writer = chain_writer_encoder(format, writer)?;
+        writer = chain_writer_encoder(format, writer, level, lz4_format, zstd_frame_size)?;
     }
 
     match first_format {
         Gzip | Bzip | Bzip3 | Lz4 | Lzma | Xz | Lzip | Snappy | Zstd | Brotli => {
-            writer = chain_writer_encoder(&first_format, writer)?;
-            let mut reader = fs::File::open(&files[0])?;
-
-            io::copy(&mut reader, &mut writer)?;
+            writer = match (first_format, name) {
+                (Gzip, Some(name)) => Box::new(flate2::GzBuilder::new().filename(name).write(
+                    writer,
+                    level.map_or_else(Default::default, |l| flate2::Compression::new((l as u32).clamp(0, 9))),
+                )),
+                (_, Some(_)) => {
+                    warning(format!(
+                        "{first_format:?} can't store the name of its input, ignoring --name"
+                    ));
+                    chain_writer_encoder(&first_format, writer, level, lz4_format, zstd_frame_size)?
+                }
+                (_, None) => chain_writer_encoder(&first_format, writer, level, lz4_format, zstd_frame_size)?,
+            };
+            // Read in full like any other file, only telling their size needs the platform's help
+            #[cfg(all(unix, not(target_os = "linux")))]
+            if crate::utils::is_block_device(&files[0]) {
+                warning(format!(
+                    "Querying the size of block devices isn't supported on this platform, the progress of {} won't have a total",
+                    crate::utils::EscapedPathDisplay::new(&files[0])
+                ));
+            }
+            let size = input_size(&files[0]);
+            let progress = AggregateProgress::is_supported(quiet).then(|| Arc::new(AggregateProgress::new([size])));
+            let mut file = fs::File::open(&files[0])?;
+            match read_with_line_endings(&mut file, eol)? {
+                Some(text) => writer.write_all(&text)?,
+                None => {
+                    io::copy(&mut ProgressReader::new(file, progress.clone(), size), &mut writer)?;
+                }
+            }
+            if let Some(progress) = progress {
+                progress.finish();
+            }
         }
         Tar => {
             archive::tar::build_archive_from_paths(
@@ -132,6 +482,11 @@ pub fn compress_files(
                 file_visibility_policy,
                 quiet,
                 follow_symlinks,
+                name,
+                modes,
+                mtime_granularity,
+                manifest.as_deref(),
+                eol,
             )?;
             writer.flush()?;
         }
@@ -156,6 +511,11 @@ pub fn compress_files(
                 file_visibility_policy,
                 quiet,
                 follow_symlinks,
+                name,
+                modes,
+                mtime_granularity,
+                manifest.as_deref(),
+                eol,
             )?;
             vec_buffer.rewind()?;
             io::copy(&mut vec_buffer, &mut writer)?;
@@ -179,6 +539,22 @@ pub fn compress_files(
                 }
             }
 
+            if name.is_some() {
+                warning("7z archives can't be stored under another name, ignoring --name".to_string());
+            }
+            if modes != EntryModes::default() {
+                warning("7z archives don't store permissions, ignoring --mode".to_string());
+            }
+            if mtime_granularity != 1 {
+                warning(
+                    "7z archives are built without rounding modification times, ignoring --mtime-granularity"
+                        .to_string(),
+                );
+            }
+            if eol != Eol::Keep {
+                warning("7z archives are built without converting line endings, ignoring --eol".to_string());
+            }
+
             let mut vec_buffer = Cursor::new(vec![]);
             archive::sevenz::compress_sevenz(&files, output_path, &mut vec_buffer, file_visibility_policy, quiet)?;
             vec_buffer.rewind()?;
@@ -188,3 +564,323 @@ pub fn compress_files(
 
     Ok(true)
 }
+
+/// Contents of the manifest stored by `--embed-manifest`, so archives can be traced back to how
+/// they were built.
+fn build_manifest(files: &[PathBuf], extensions: &[Extension], name: Option<&str>) -> Vec<u8> {
+    #[derive(serde::Serialize)]
+    struct Manifest {
+        ouch_version: &'static str,
+        formats: Vec<String>,
+        created: String,
+        inputs: Vec<String>,
+    }
+
+    // Record the inputs as stored in the archive, their absolute paths are specific to this machine
+    let inputs = match name {
+        Some(name) => vec![name.to_string()],
+        None => files
+            .iter()
+            .filter_map(|file| file.file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect(),
+    };
+
+    let manifest = Manifest {
+        ouch_version: env!("CARGO_PKG_VERSION"),
+        formats: extensions.iter().map(ToString::to_string).collect(),
+        created: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        inputs,
+    };
+
+    serde_json::to_vec_pretty(&manifest).expect("manifest serialization can't fail")
+}
+
+/// Removes the `inputs` compressed into outputs in `output_dir`, for `--remove`.
+///
+/// Inputs holding `output_dir` are kept, removing them would remove the output along with them,
+/// and so are the `kept_inputs`, which replaced their output. Symlinks are removed, not what they
+/// point to.
+pub fn remove_inputs(inputs: &[PathBuf], output_dir: &Path, kept_inputs: &[PathBuf]) -> crate::Result<()> {
+    let output_dir = fs::canonicalize(output_dir)?;
+    for input in inputs {
+        if kept_inputs
+            .iter()
+            .any(|kept| same_file::is_same_file(input, kept).unwrap_or(false))
+        {
+            warning(format!(
+                "Not removing {}, it was kept as the output",
+                EscapedPathDisplay::new(input)
+            ));
+            continue;
+        }
+
+        let metadata = fs::symlink_metadata(input)?;
+        if metadata.is_dir() && output_dir.starts_with(input) {
+            warning(format!(
+                "Not removing {}, the output was written inside of it",
+                EscapedPathDisplay::new(input)
+            ));
+            continue;
+        }
+
+        if metadata.is_dir() {
+            fs::remove_dir_all(input)?;
+        } else {
+            fs::remove_file(input)?;
+        }
+        info(format!("Removed input {}", EscapedPathDisplay::new(input)));
+    }
+    Ok(())
+}
+
+/// Statistics of a finished compression, written as JSON by `--stats-json`, like:
+///
+/// ```json
+/// {
+///   "outputs": ["archive.tar.gz"],
+///   "formats": ["tar", "gz"],
+///   "input_size": 1048576,
+///   "output_size": 262144,
+///   "ratio": 4.0,
+///   "duration_secs": 0.25,
+///   "throughput": 4194304.0,
+///   "entries": 12,
+///   "parameters": { "level": null }
+/// }
+/// ```
+///
+/// Sizes are in bytes, `input_size` is the one of the files of the inputs and `output_size` the
+/// one of all the volumes of `--split-by-entry`. The ratio is the one of `--min-ratio`, the
+/// throughput is the number of input bytes compressed per second.
+#[derive(Debug, serde::Serialize)]
+pub struct CompressionStats {
+    /// Paths that aren't valid UTF-8 have their invalid bytes replaced, as JSON can't hold them
+    pub outputs: Vec<String>,
+    pub formats: Vec<String>,
+    pub input_size: u64,
+    pub output_size: u64,
+    pub ratio: f64,
+    pub duration_secs: f64,
+    pub throughput: f64,
+    /// Files, directories and symlinks walked in the inputs
+    pub entries: u64,
+    pub parameters: CompressionParameters,
+}
+
+/// Parameters the formats were compressed with, the ones of formats that aren't used are left out
+#[derive(Debug, serde::Serialize)]
+pub struct CompressionParameters {
+    /// `null` for the default level of each format
+    pub level: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lz4_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zstd_frame_size: Option<u64>,
+}
+
+impl CompressionParameters {
+    pub fn new(
+        extensions: &[Extension],
+        level: Option<i16>,
+        lz4_format: Lz4Format,
+        zstd_frame_size: Option<u64>,
+    ) -> Self {
+        use clap::ValueEnum;
+
+        let formats = flatten_compression_formats(extensions);
+        Self {
+            level,
+            lz4_format: formats
+                .contains(&Lz4)
+                .then(|| lz4_format.to_possible_value().unwrap().get_name().to_owned()),
+            zstd_frame_size: zstd_frame_size.filter(|_| formats.contains(&Zstd)),
+        }
+    }
+}
+
+impl CompressionStats {
+    /// Measures the inputs and the `outputs` they were compressed to in `duration`.
+    pub fn measure(
+        files: &[PathBuf],
+        extensions: &[Extension],
+        outputs: Vec<PathBuf>,
+        file_visibility_policy: &FileVisibilityPolicy,
+        follow_symlinks: bool,
+        duration: Duration,
+        parameters: CompressionParameters,
+    ) -> crate::Result<Self> {
+        let (input_size, entries) = measure_inputs(files, file_visibility_policy, follow_symlinks)?;
+        let mut output_size = 0;
+        for output in &outputs {
+            output_size += fs::metadata(output)?.len();
+        }
+        let duration_secs = duration.as_secs_f64();
+
+        Ok(Self {
+            outputs: outputs
+                .iter()
+                .map(|output| output.to_string_lossy().into_owned())
+                .collect(),
+            formats: extensions.iter().map(ToString::to_string).collect(),
+            input_size,
+            output_size,
+            ratio: input_size as f64 / output_size.max(1) as f64,
+            duration_secs,
+            throughput: if duration_secs > 0.0 {
+                input_size as f64 / duration_secs
+            } else {
+                0.0
+            },
+            entries,
+            parameters,
+        })
+    }
+
+    /// Writes the statistics to the file at `path`, or to stdout for "-".
+    pub fn write(&self, path: &Path) -> crate::Result<()> {
+        let mut json = serde_json::to_vec_pretty(self).expect("stats serialization can't fail");
+        json.push(b'\n');
+        if is_path_stdin(path) {
+            io::stdout().lock().write_all(&json)?;
+        } else {
+            fs::write(path, json)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps `encoder` in the encoder of `format`, so everything written to the returned writer gets
+/// compressed before reaching `encoder`.
+///
+/// Archive formats are not streams, so they are not accepted here, callers build them directly.
+pub fn chain_writer_encoder(
+    format: &CompressionFormat,
+    encoder: Box<dyn Send + Write>,
+    level: Option<i16>,
+    lz4_format: Lz4Format,
+    zstd_frame_size: Option<u64>,
+) -> crate::Result<Box<dyn Send + Write>> {
+    let encoder: Box<dyn Send + Write> = match format {
+        Gzip => Box::new(
+            // by default, ParCompress uses a default compression level of 3
+            // instead of the regular default that flate2 uses
+            gzp::par::compress::ParCompress::<gzp::deflate::Gzip>::builder()
+                .compression_level(
+                    level.map_or_else(Default::default, |l| gzp::Compression::new((l as u32).clamp(0, 9))),
+                )
+                .from_writer(encoder),
+        ),
+        Bzip => Box::new(bzip2::write::BzEncoder::new(
+            encoder,
+            level.map_or_else(Default::default, |l| bzip2::Compression::new((l as u32).clamp(1, 9))),
+        )),
+        Bzip3 => {
+            #[cfg(not(feature = "bzip3"))]
+            return Err(archive::bzip3_stub::no_support());
+
+            #[cfg(feature = "bzip3")]
+            Box::new(
+                // Use block size of 16 MiB
+                bzip3::write::Bz3Encoder::new(encoder, 16 * 2_usize.pow(20))?,
+            )
+        }
+        Lz4 => match lz4_format {
+            Lz4Format::Frame => Box::new(lz4_flex::frame::FrameEncoder::new(encoder).auto_finish()),
+            Lz4Format::Legacy => Box::new(archive::lz4_legacy::Encoder::new(encoder)),
+        },
+        Lzma => {
+            return Err(crate::Error::UnsupportedFormat {
+                reason: "LZMA1 compression is not supported in ouch, use .xz instead.".to_string(),
+            })
+        }
+        Xz => Box::new(liblzma::write::XzEncoder::new(
+            encoder,
+            level.map_or(6, |l| (l as u32).clamp(0, 9)),
+        )),
+        Lzip => {
+            return Err(crate::Error::UnsupportedFormat {
+                reason: "Lzip compression is not supported in ouch.".to_string(),
+            })
+        }
+        Snappy => Box::new(
+            gzp::par::compress::ParCompress::<gzp::snap::Snap>::builder()
+                .compression_level(gzp::par::compress::Compression::new(
+                    level.map_or_else(Default::default, |l| (l as u32).clamp(0, 9)),
+                ))
+                .from_writer(encoder),
+        ),
+        Zstd => {
+            let level = level.map_or(zstd::DEFAULT_COMPRESSION_LEVEL, |l| {
+                (l as i32).clamp(zstd::zstd_safe::min_c_level(), zstd::zstd_safe::max_c_level())
+            });
+            // Use all available PHYSICAL cores for compression
+            let threads = num_cpus::get_physical() as u32;
+
+            match zstd_frame_size {
+                Some(frame_size) => Box::new(archive::zstd_frames::Encoder::new(encoder, level, threads, frame_size)?),
+                None => {
+                    let mut zstd_encoder = zstd::stream::write::Encoder::new(encoder, level)?;
+                    zstd_encoder.multithread(threads)?;
+                    Box::new(zstd_encoder.auto_finish())
+                }
+            }
+        }
+        Brotli => {
+            let default_level = 11; // Same as brotli CLI, default to highest compression
+            let level = level.unwrap_or(default_level).clamp(0, 11) as u32;
+            let win_size = 22; // default to 2^22 = 4 MiB window size
+            Box::new(brotli::CompressorWriter::new(encoder, BUFFER_CAPACITY, level, win_size))
+        }
+        Tar | Zip | Rar | SevenZip => unreachable!(),
+    };
+    Ok(encoder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derive_output_path() {
+        let format = Some(OsStr::new("tar.zst"));
+        assert_eq!(
+            derive_output_path(Path::new("dir/"), format).unwrap(),
+            PathBuf::from("dir.tar.zst")
+        );
+        assert_eq!(
+            derive_output_path(Path::new("some/file.txt"), Some(OsStr::new(".gz"))).unwrap(),
+            PathBuf::from("file.txt.gz")
+        );
+        assert_eq!(
+            derive_output_path(Path::new("/"), format).unwrap(),
+            PathBuf::from("archive.tar.zst")
+        );
+
+        assert!(derive_output_path(Path::new("dir/"), None).is_err());
+        assert!(derive_output_path(Path::new("dir/"), Some(OsStr::new("nope"))).is_err());
+    }
+
+    #[test]
+    fn test_volume_path() {
+        assert_eq!(
+            volume_path(Path::new("out/archive.tar.gz"), 2).unwrap(),
+            PathBuf::from("out/archive.002.tar.gz")
+        );
+        assert_eq!(
+            volume_path(Path::new("archive.zip"), 12).unwrap(),
+            PathBuf::from("archive.012.zip")
+        );
+    }
+
+    #[test]
+    fn test_parse_ratio() {
+        assert_eq!(parse_ratio("1.5").unwrap(), 1.5);
+        assert_eq!(parse_ratio("2").unwrap(), 2.0);
+
+        assert!(parse_ratio("0").is_err());
+        assert!(parse_ratio("-1").is_err());
+        assert!(parse_ratio("inf").is_err());
+        assert!(parse_ratio("half").is_err());
+    }
+}
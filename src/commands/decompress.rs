@@ -1,17 +1,25 @@
 use std::{
-    io::{self, BufReader, Read},
+    ffi::OsStr,
+    io::{self, BufReader, Read, Write},
     ops::ControlFlow,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
 };
 
+use filetime_creation::{set_file_mtime, set_symlink_file_times, FileTime};
 use fs_err as fs;
+use sha2::{Digest, Sha256, Sha512};
 
 #[cfg(not(feature = "bzip3"))]
 use crate::archive;
 use crate::{
+    archive::{lz4_legacy, MemoryBudget, OwnerMaps},
+    cli::{AbsoluteSymlinks, ResumeMode},
     commands::{warn_user_about_loading_sevenz_in_memory, warn_user_about_loading_zip_in_memory},
+    error::{Error, FinalError},
     extension::{
-        split_first_compression_format,
+        separate_known_extensions_from_name, split_first_compression_format,
         CompressionFormat::{self, *},
         Extension,
     },
@@ -19,8 +27,10 @@ use crate::{
         self,
         io::lock_and_flush_output_stdio,
         is_path_stdin,
-        logger::{info, info_accessible},
-        nice_directory_display, user_wants_to_continue,
+        logger::{info, info_accessible, warning},
+        nice_directory_display, pretty_format_list_of_paths,
+        progress::{input_size, AggregateProgress, ProgressReader},
+        user_wants_to_continue, EscapedPathDisplay,
     },
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
@@ -28,6 +38,192 @@ use crate::{
 trait ReadSeek: Read + io::Seek {}
 impl<T: Read + io::Seek> ReadSeek for T {}
 
+/// Rule of `--replace-suffix`, replaces the suffix `from` of a decompressed file name by `to`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceSuffix {
+    from: String,
+    to: String,
+}
+
+impl ReplaceSuffix {
+    /// Parse a rule in the `from=to` form, `to` can be empty to just strip the suffix
+    pub fn new(rule: &str) -> crate::Result<Self> {
+        let error = |detail: &str| {
+            FinalError::with_title(format!("Invalid suffix replacement rule '{rule}'"))
+                .detail(detail.to_string())
+                .hint("Use the 'from=to' form, for example: --replace-suffix .min.js=.js")
+        };
+
+        let (from, to) = rule.split_once('=').ok_or_else(|| error("Missing '=' separator"))?;
+
+        if from.is_empty() {
+            return Err(error("The suffix to replace is empty").into());
+        }
+        if [from, to].iter().any(|suffix| suffix.contains(std::path::is_separator)) {
+            return Err(error("Suffixes cannot contain path separators").into());
+        }
+
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+    }
+
+    /// Apply the rule to `file_name`, returns `None` if it doesn't end with the suffix.
+    ///
+    /// A name that would be left empty also counts as not matching.
+    pub fn apply(&self, file_name: &Path) -> Option<PathBuf> {
+        let stem = file_name.to_str()?.strip_suffix(&self.from)?;
+        let replaced = format!("{stem}{}", self.to);
+        (!replaced.is_empty()).then(|| PathBuf::from(replaced))
+    }
+}
+
+/// Checks the name given to `--rename-root`, which must be a single path component
+pub fn check_root_name(name: &str) -> crate::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(std::path::is_separator) {
+        return Err(FinalError::with_title(format!("Invalid root name '{name}'"))
+            .detail("The root is renamed in place, its new name can't be a path")
+            .hint("Use --dir to choose where it's extracted")
+            .into());
+    }
+    Ok(())
+}
+
+/// Extensions of the checksum files looked for next to inputs, like "file.tar.gz.sha256"
+const CHECKSUM_EXTENSIONS: [&str; 3] = ["sha256", "sha512", "blake3"];
+
+/// Checks `path` against the digest of its sibling checksum files, if any.
+///
+/// These files hold lines like `sha256sum` writes them, "<digest>  <name>", or in the BSD style,
+/// "SHA256 (<name>) = <digest>". A file holding several digests must have one for the input.
+fn verify_checksum_files(path: &Path) -> crate::Result<()> {
+    for extension in CHECKSUM_EXTENSIONS {
+        let mut checksum_file = path.as_os_str().to_owned();
+        checksum_file.push(".");
+        checksum_file.push(extension);
+        let checksum_file = PathBuf::from(checksum_file);
+        if !checksum_file.is_file() {
+            continue;
+        }
+
+        let file_name = path.file_name().unwrap_or_default();
+        let contents = fs::read_to_string(&checksum_file)?;
+        let Some(expected) = find_digest(&contents, file_name) else {
+            warning(format!(
+                "{} has no digest for {}, skipping its verification",
+                EscapedPathDisplay::new(&checksum_file),
+                EscapedPathDisplay::new(Path::new(file_name))
+            ));
+            continue;
+        };
+
+        let (algorithm, actual) = match extension {
+            "sha256" => ("SHA-256", hex_digest::<Sha256>(path)?),
+            "sha512" => ("SHA-512", hex_digest::<Sha512>(path)?),
+            "blake3" => ("BLAKE3", blake3_hex_digest(path)?),
+            _ => unreachable!("every checksum extension has its algorithm"),
+        };
+        if !actual.eq_ignore_ascii_case(expected) {
+            return Err(Error::ChecksumMismatch {
+                path: path.to_path_buf(),
+                checksum_file,
+                algorithm,
+                expected: expected.to_ascii_lowercase(),
+                actual,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Finds the digest of `file_name` in the contents of a checksum file, a lone digest without a
+/// name counts as the one of the input.
+fn find_digest<'a>(contents: &'a str, file_name: &OsStr) -> Option<&'a str> {
+    contents.lines().find_map(|line| {
+        let line = line.trim();
+        let (digest, name) = if let Some((name, digest)) = line.split_once(" = ") {
+            // BSD style, "SHA256 (name) = digest"
+            let name = name.split_once(" (")?.1.strip_suffix(')')?;
+            (digest, Some(name))
+        } else {
+            match line.split_once(char::is_whitespace) {
+                // The '*' marks digests computed in binary mode
+                Some((digest, name)) => (digest, Some(name.trim_start().trim_start_matches('*'))),
+                None => (line, None),
+            }
+        };
+
+        let is_hex = !digest.is_empty() && digest.bytes().all(|byte| byte.is_ascii_hexdigit());
+        let is_input = name.is_none_or(|name| Path::new(name).file_name() == Some(file_name));
+        (is_hex && is_input).then_some(digest)
+    })
+}
+
+fn hex_digest<D: Digest + io::Write>(path: &Path) -> crate::Result<String> {
+    let mut hasher = D::new();
+    io::copy(
+        &mut BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(path)?),
+        &mut hasher,
+    )?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn blake3_hex_digest(path: &Path) -> crate::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(
+        &mut BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(path)?),
+        &mut hasher,
+    )?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Parses the date of `--set-mtime`, either a day like "2024-01-31" at midnight UTC, an RFC 3339
+/// date and time like "2024-01-31T12:00:00+01:00", or a Unix timestamp like "@1706702400".
+pub fn parse_mtime(date: &str) -> crate::Result<SystemTime> {
+    let parsed = if let Some(timestamp) = date.strip_prefix('@') {
+        timestamp
+            .parse()
+            .ok()
+            .and_then(|seconds| chrono::DateTime::from_timestamp(seconds, 0))
+    } else if let Ok(day) = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        day.and_hms_opt(0, 0, 0).map(|midnight| midnight.and_utc())
+    } else {
+        chrono::DateTime::parse_from_rfc3339(date)
+            .ok()
+            .map(|date| date.to_utc())
+    };
+
+    parsed.map(SystemTime::from).ok_or_else(|| {
+        FinalError::with_title(format!("Invalid date '{date}'"))
+            .hint("Use a day, a date and time or a Unix timestamp, like:")
+            .hint("  --set-mtime 2024-01-31")
+            .hint("  --set-mtime 2024-01-31T12:00:00Z")
+            .hint("  --set-mtime @1706702400")
+            .into()
+    })
+}
+
+/// Sets the modification time of everything inside of `dir` to `mtime`, without following symlinks
+fn set_mtimes(dir: &Path, mtime: FileTime) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            let atime = FileTime::from_last_access_time(&metadata);
+            let ctime = FileTime::from_creation_time(&metadata).unwrap_or(atime);
+            set_symlink_file_times(&path, atime, mtime, ctime)?;
+            continue;
+        }
+        // Children first, even though setting their times doesn't change the one of their parent
+        if metadata.is_dir() {
+            set_mtimes(&path, mtime)?;
+        }
+        set_file_mtime(&path, mtime)?;
+    }
+    Ok(())
+}
+
 pub struct DecompressOptions<'a> {
     pub input_file_path: &'a Path,
     pub formats: Vec<Extension>,
@@ -36,9 +232,25 @@ pub struct DecompressOptions<'a> {
     pub is_output_dir_provided: bool,
     pub is_smart_unpack: bool,
     pub question_policy: QuestionPolicy,
+    /// Move the files replaced on conflicts to the trash instead of deleting them
+    pub trash: bool,
     pub quiet: bool,
     pub password: Option<&'a [u8]>,
     pub remove: bool,
+    pub absolute_symlinks: AbsoluteSymlinks,
+    pub preserve_special_bits: bool,
+    pub strip_trailing_components: usize,
+    pub rename_root: Option<&'a str>,
+    pub check_crc: bool,
+    pub resume: Option<ResumeMode>,
+    pub confined: bool,
+    pub owners: Option<&'a OwnerMaps>,
+    pub preserve_atime: bool,
+    pub verify: bool,
+    pub set_mtime: Option<SystemTime>,
+    /// Decompressed bytes skipped at the start of single file formats
+    pub start_at: u64,
+    pub progress: Option<Arc<AggregateProgress>>,
 }
 
 /// Decompress a file
@@ -51,6 +263,17 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
     assert!(options.output_dir.exists());
     let input_is_stdin = is_path_stdin(options.input_file_path);
 
+    if options.verify && !input_is_stdin {
+        verify_checksum_files(options.input_file_path)?;
+    }
+
+    #[cfg(not(unix))]
+    if options.confined {
+        return Err(FinalError::with_title("Confined extraction is only supported on Unix")
+            .hint("Extract without --confined")
+            .into());
+    }
+
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
     // from decoder chaining.
     //
@@ -66,19 +289,64 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
         let mut vec = vec![];
         let reader: Box<dyn ReadSeek> = if input_is_stdin {
             warn_user_about_loading_zip_in_memory();
-            io::copy(&mut io::stdin(), &mut vec)?;
+            io::copy(
+                &mut ProgressReader::new(io::stdin(), options.progress.clone(), None),
+                &mut vec,
+            )?;
             Box::new(io::Cursor::new(vec))
         } else {
+            // Counted as entries are extracted instead, see below
             Box::new(fs::File::open(options.input_file_path)?)
         };
-        let zip_archive = zip::ZipArchive::new(reader)?;
+        let mut zip_archive = zip::ZipArchive::new(reader)?;
+
+        // The central directory tells how much there is to extract, which is what the progress
+        // follows, unlike other formats whose total is only the size of the input
+        let progress = options.progress.as_ref().filter(|_| !input_is_stdin);
+        if let Some(progress) = progress {
+            let size = crate::archive::zip::uncompressed_size(&mut zip_archive)?;
+            progress.replace_item_size(input_size(options.input_file_path), size);
+        }
+
         let files_unpacked = if let ControlFlow::Continue(files) = execute_decompression(
-            |output_dir| crate::archive::zip::unpack_archive(zip_archive, output_dir, options.password, options.quiet),
+            |output_dir| {
+                #[cfg(unix)]
+                if options.confined {
+                    return crate::archive::zip::unpack_archive_confined(
+                        zip_archive,
+                        output_dir,
+                        options.password,
+                        options.quiet,
+                        options.preserve_special_bits,
+                        options.strip_trailing_components,
+                        options.question_policy,
+                        options.check_crc,
+                        progress,
+                    );
+                }
+                crate::archive::zip::unpack_archive(
+                    zip_archive,
+                    output_dir,
+                    options.password,
+                    options.quiet,
+                    options.preserve_special_bits,
+                    options.strip_trailing_components,
+                    options.question_policy,
+                    options.trash,
+                    options.check_crc,
+                    options.resume,
+                    progress,
+                )
+            },
             options.output_dir,
             &options.output_file_path,
             options.question_policy,
+            options.trash,
             options.is_output_dir_provided,
             options.is_smart_unpack,
+            options.resume.is_some(),
+            options.set_mtime,
+            options.rename_root,
         )? {
             files
         } else {
@@ -106,45 +374,26 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
         return Ok(());
     }
 
+    // Decompressed bytes still to skip once decoding
+    let mut start_at = options.start_at;
+
     // Will be used in decoder chaining
     let reader: Box<dyn Read> = if input_is_stdin {
-        Box::new(io::stdin())
+        Box::new(ProgressReader::new(io::stdin(), options.progress.clone(), None))
     } else {
-        Box::new(fs::File::open(options.input_file_path)?)
+        let mut file = fs::File::open(options.input_file_path)?;
+        let mut size = input_size(options.input_file_path);
+        let (skipped, block_start) = seek_to_gzip_block(&mut file, &options)?;
+        start_at -= block_start;
+        if let (Some(progress), Some(size)) = (&options.progress, &mut size) {
+            progress.replace_item_size(Some(*size), *size - skipped);
+            *size -= skipped;
+        }
+        Box::new(ProgressReader::new(file, options.progress.clone(), size))
     };
     let reader = BufReader::with_capacity(BUFFER_CAPACITY, reader);
     let mut reader: Box<dyn Read> = Box::new(reader);
 
-    // Grab previous decoder and wrap it inside of a new one
-    let chain_reader_decoder = |format: &CompressionFormat, decoder: Box<dyn Read>| -> crate::Result<Box<dyn Read>> {
-        let decoder: Box<dyn Read> = match format {
-            Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
-            Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
-            Bzip3 => {
-                #[cfg(not(feature = "bzip3"))]
-                return Err(archive::bzip3_stub::no_support());
-
-                #[cfg(feature = "bzip3")]
-                Box::new(bzip3::read::Bz3Decoder::new(decoder)?)
-            }
-            Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(decoder)),
-            Lzma => Box::new(liblzma::read::XzDecoder::new_stream(
-                decoder,
-                liblzma::stream::Stream::new_lzma_decoder(u64::MAX).unwrap(),
-            )),
-            Xz => Box::new(liblzma::read::XzDecoder::new(decoder)),
-            Lzip => Box::new(liblzma::read::XzDecoder::new_stream(
-                decoder,
-                liblzma::stream::Stream::new_lzip_decoder(u64::MAX, 0).unwrap(),
-            )),
-            Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
-            Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
-            Brotli => Box::new(brotli::Decompressor::new(decoder, BUFFER_CAPACITY)),
-            Tar | Zip | Rar | SevenZip => decoder,
-        };
-        Ok(decoder)
-    };
-
     let (first_extension, extensions) = split_first_compression_format(&options.formats);
 
     for format in extensions.iter().rev() {
@@ -154,28 +403,71 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
     let files_unpacked = match first_extension {
         Gzip | Bzip | Bzip3 | Lz4 | Lzma | Xz | Lzip | Snappy | Zstd | Brotli => {
             reader = chain_reader_decoder(&first_extension, reader)?;
+            io::copy(&mut reader.by_ref().take(start_at), &mut io::sink())?;
 
-            let mut writer = match utils::ask_to_create_file(
-                &options.output_file_path,
-                options.question_policy,
-                QuestionAction::Decompression,
-            )? {
-                Some(file) => file,
-                None => return Ok(()),
-            };
+            // Pipes and devices are written to as they are, they can't be replaced or truncated
+            if let Some(mut writer) = open_stream_target(&options.output_file_path)? {
+                io::copy(&mut reader, &mut writer)?;
+                writer.flush()?;
+            } else {
+                let mut writer = match utils::ask_to_create_file(
+                    &options.output_file_path,
+                    options.question_policy,
+                    QuestionAction::Decompression,
+                    options.trash,
+                )? {
+                    Some(file) => file,
+                    None => return Ok(()),
+                };
 
-            io::copy(&mut reader, &mut writer)?;
+                io::copy(&mut reader, &mut writer)?;
+                if let Some(mtime) = options.set_mtime {
+                    writer.file().set_modified(mtime)?;
+                }
+            }
 
             1
         }
         Tar => {
             if let ControlFlow::Continue(files) = execute_decompression(
-                |output_dir| crate::archive::tar::unpack_archive(reader, output_dir, options.quiet),
+                |output_dir| {
+                    #[cfg(unix)]
+                    if options.confined {
+                        return crate::archive::tar::unpack_archive_confined(
+                            reader,
+                            output_dir,
+                            options.quiet,
+                            options.absolute_symlinks,
+                            options.preserve_special_bits,
+                            options.strip_trailing_components,
+                            options.question_policy,
+                            options.owners,
+                            options.preserve_atime,
+                        );
+                    }
+                    crate::archive::tar::unpack_archive(
+                        reader,
+                        output_dir,
+                        options.quiet,
+                        options.absolute_symlinks,
+                        options.preserve_special_bits,
+                        options.strip_trailing_components,
+                        options.question_policy,
+                        options.trash,
+                        options.resume,
+                        options.owners,
+                        options.preserve_atime,
+                    )
+                },
                 options.output_dir,
                 &options.output_file_path,
                 options.question_policy,
+                options.trash,
                 options.is_output_dir_provided,
                 options.is_smart_unpack,
+                options.resume.is_some(),
+                options.set_mtime,
+                options.rename_root,
             )? {
                 files
             } else {
@@ -204,13 +496,43 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
 
             if let ControlFlow::Continue(files) = execute_decompression(
                 |output_dir| {
-                    crate::archive::zip::unpack_archive(zip_archive, output_dir, options.password, options.quiet)
+                    #[cfg(unix)]
+                    if options.confined {
+                        return crate::archive::zip::unpack_archive_confined(
+                            zip_archive,
+                            output_dir,
+                            options.password,
+                            options.quiet,
+                            options.preserve_special_bits,
+                            options.strip_trailing_components,
+                            options.question_policy,
+                            options.check_crc,
+                            None,
+                        );
+                    }
+                    crate::archive::zip::unpack_archive(
+                        zip_archive,
+                        output_dir,
+                        options.password,
+                        options.quiet,
+                        options.preserve_special_bits,
+                        options.strip_trailing_components,
+                        options.question_policy,
+                        options.trash,
+                        options.check_crc,
+                        options.resume,
+                        None,
+                    )
                 },
                 options.output_dir,
                 &options.output_file_path,
                 options.question_policy,
+                options.trash,
                 options.is_output_dir_provided,
                 options.is_smart_unpack,
+                options.resume.is_some(),
+                options.set_mtime,
+                options.rename_root,
             )? {
                 files
             } else {
@@ -219,6 +541,18 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
         }
         #[cfg(feature = "unrar")]
         Rar => {
+            if options.strip_trailing_components > 0 {
+                warning(
+                    "Entry paths of rar archives can't be changed, ignoring --strip-trailing-components".to_string(),
+                );
+            }
+            if options.confined {
+                return Err(confined_not_supported("rar"));
+            }
+            if options.resume.is_some() {
+                warning("Rar archives can't be resumed, ignoring --resume".to_string());
+            }
+
             type UnpackResult = crate::Result<usize>;
             let unpack_fn: Box<dyn FnOnce(&Path) -> UnpackResult> = if options.formats.len() > 1 || input_is_stdin {
                 let mut temp_file = tempfile::NamedTempFile::new()?;
@@ -242,8 +576,12 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
                 options.output_dir,
                 &options.output_file_path,
                 options.question_policy,
+                options.trash,
                 options.is_output_dir_provided,
                 options.is_smart_unpack,
+                false,
+                options.set_mtime,
+                options.rename_root,
             )? {
                 files
             } else {
@@ -255,7 +593,26 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
             return Err(crate::archive::rar_stub::no_support());
         }
         SevenZip => {
-            if options.formats.len() > 1 {
+            if options.strip_trailing_components > 0 {
+                warning(
+                    "Entry paths of 7z archives can't be changed, ignoring --strip-trailing-components".to_string(),
+                );
+            }
+            if options.confined {
+                return Err(confined_not_supported("7z"));
+            }
+            if options.resume.is_some() {
+                warning("7z archives can't be resumed, ignoring --resume".to_string());
+            }
+
+            // The volumes of split archives are read where they are
+            let volumes = if input_is_stdin || options.formats.len() > 1 {
+                None
+            } else {
+                crate::archive::sevenz::open_volumes(options.input_file_path)?
+            };
+
+            if volumes.is_none() && options.formats.len() > 1 {
                 // Locking necessary to guarantee that warning and question
                 // messages stay adjacent
                 let _locks = lock_and_flush_output_stdio();
@@ -270,23 +627,28 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
                 }
             }
 
-            let mut vec = vec![];
-            io::copy(&mut reader, &mut vec)?;
+            let reader: Box<dyn ReadSeek> = match volumes {
+                Some(volumes) => Box::new(volumes),
+                None => {
+                    let mut vec = vec![];
+                    io::copy(&mut reader, &mut vec)?;
+                    Box::new(io::Cursor::new(vec))
+                }
+            };
 
             if let ControlFlow::Continue(files) = execute_decompression(
                 |output_dir| {
-                    crate::archive::sevenz::decompress_sevenz(
-                        io::Cursor::new(vec),
-                        output_dir,
-                        options.password,
-                        options.quiet,
-                    )
+                    crate::archive::sevenz::decompress_sevenz(reader, output_dir, options.password, options.quiet)
                 },
                 options.output_dir,
                 &options.output_file_path,
                 options.question_policy,
+                options.trash,
                 options.is_output_dir_provided,
                 options.is_smart_unpack,
+                false,
+                options.set_mtime,
+                options.rename_root,
             )? {
                 files
             } else {
@@ -316,16 +678,203 @@ pub fn decompress_file(options: DecompressOptions) -> crate::Result<()> {
     Ok(())
 }
 
+/// Opens `path` for writing if it's a FIFO or a character device, like a named pipe or
+/// "/dev/stdout" when it's one, returns `None` for regular files or if there's nothing there.
+fn open_stream_target(path: &Path) -> io::Result<Option<fs::File>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        let Ok(metadata) = fs::metadata(path) else {
+            return Ok(None);
+        };
+        let file_type = metadata.file_type();
+        if file_type.is_fifo() || file_type.is_char_device() {
+            return fs::OpenOptions::new().write(true).open(path).map(Some);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(None)
+}
+
+/// Seeks `file` to the gzip member holding `options.start_at` when indexed by a .gzi file,
+/// returning the compressed and decompressed offsets it starts at. Other files are read from the
+/// start.
+#[cfg(feature = "gzi")]
+fn seek_to_gzip_block(file: &mut fs::File, options: &DecompressOptions) -> crate::Result<(u64, u64)> {
+    use std::io::{Seek, SeekFrom};
+
+    if options.start_at == 0 || crate::extension::flatten_compression_formats(&options.formats) != [Gzip] {
+        return Ok((0, 0));
+    }
+    let Some(index) = crate::archive::gzi::GzipIndex::find(options.input_file_path)? else {
+        return Ok((0, 0));
+    };
+
+    let (compressed, decompressed) = index.block_before(options.start_at);
+    file.seek(SeekFrom::Start(compressed))?;
+    Ok((compressed, decompressed))
+}
+
+#[cfg(not(feature = "gzi"))]
+fn seek_to_gzip_block(_file: &mut fs::File, _options: &DecompressOptions) -> crate::Result<(u64, u64)> {
+    Ok((0, 0))
+}
+
+/// Error of `--confined` on archive formats whose extraction can't be confined
+fn confined_not_supported(format: &str) -> Error {
+    FinalError::with_title(format!("Confined extraction isn't supported for {format} archives"))
+        .detail("Only tar and zip archives can be extracted with --confined")
+        .into()
+}
+
+/// Decompress the archive at `archive_path` in memory, without writing anything to disk.
+///
+/// Returns the regular files of the archive with their paths inside of it, directories and symlinks
+/// are left out, along with entries whose path leaves the archive with `..`. Single file formats
+/// return one entry named after the input without its extensions. Formats are detected from the
+/// extensions of `archive_path`.
+///
+/// Everything is buffered, so memory usage grows with the decompressed size, plus the whole
+/// decompressed archive for a `.zip` that is itself compressed. Decompression fails once the
+/// entries add up to more than `max_size` bytes, pick it according to the memory you can spare.
+pub fn decompress_to_memory(
+    archive_path: &Path,
+    password: Option<&[u8]>,
+    max_size: u64,
+) -> crate::Result<Vec<(PathBuf, Vec<u8>)>> {
+    let (name, formats) = separate_known_extensions_from_name(archive_path)?;
+    if formats.is_empty() {
+        return Err(Error::UnknownFormat {
+            paths: vec![archive_path.to_path_buf()],
+        });
+    }
+
+    if let [Extension {
+        compression_formats: [Zip],
+        ..
+    }] = formats.as_slice()
+    {
+        let zip_archive = zip::ZipArchive::new(fs::File::open(archive_path)?)?;
+        return crate::archive::zip::unpack_archive_in_memory(zip_archive, password, max_size);
+    }
+
+    let reader = BufReader::with_capacity(BUFFER_CAPACITY, fs::File::open(archive_path)?);
+    let mut reader: Box<dyn Read> = Box::new(reader);
+
+    let (first_extension, extensions) = split_first_compression_format(&formats);
+    for format in extensions.iter().rev() {
+        reader = chain_reader_decoder(format, reader)?;
+    }
+
+    match first_extension {
+        Gzip | Bzip | Bzip3 | Lz4 | Lzma | Xz | Lzip | Snappy | Zstd | Brotli => {
+            let mut reader = chain_reader_decoder(&first_extension, reader)?;
+            let content = MemoryBudget::new(max_size).read(&mut reader)?;
+            let name = name.file_name().map_or_else(|| name.to_path_buf(), PathBuf::from);
+            Ok(vec![(name, content)])
+        }
+        Tar => crate::archive::tar::unpack_archive_in_memory(reader, max_size),
+        Zip => {
+            let mut vec = vec![];
+            io::copy(&mut reader, &mut vec)?;
+            let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
+            crate::archive::zip::unpack_archive_in_memory(zip_archive, password, max_size)
+        }
+        Rar | SevenZip => Err(
+            FinalError::with_title("In-memory decompression is not supported for this format")
+                .detail(format!("File: {}", EscapedPathDisplay::new(archive_path)))
+                .hint("Only tar, zip and single file formats can be decompressed in memory")
+                .into(),
+        ),
+    }
+}
+
+/// Wraps `decoder` in the decoder of `format`, so reading from the returned reader yields the data
+/// decompressed from `decoder`.
+///
+/// Archive formats are returned untouched, callers are expected to unpack them.
+pub fn chain_reader_decoder(format: &CompressionFormat, decoder: Box<dyn Read>) -> crate::Result<Box<dyn Read>> {
+    let decoder: Box<dyn Read> = match format {
+        Gzip => Box::new(flate2::read::MultiGzDecoder::new(decoder)),
+        Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
+        Bzip3 => {
+            #[cfg(not(feature = "bzip3"))]
+            return Err(archive::bzip3_stub::no_support());
+
+            #[cfg(feature = "bzip3")]
+            Box::new(bzip3::read::Bz3Decoder::new(decoder)?)
+        }
+        Lz4 => {
+            // Both lz4 formats share the extension, tell them apart by their magic number
+            let mut decoder = decoder;
+            let mut magic = Vec::with_capacity(4);
+            decoder.by_ref().take(4).read_to_end(&mut magic)?;
+            let is_legacy = magic == lz4_legacy::MAGIC;
+            let decoder = io::Cursor::new(magic).chain(decoder);
+
+            if is_legacy {
+                Box::new(lz4_legacy::Decoder::new(decoder))
+            } else {
+                Box::new(lz4_flex::frame::FrameDecoder::new(decoder))
+            }
+        }
+        Lzma => Box::new(liblzma::read::XzDecoder::new_stream(
+            decoder,
+            liblzma::stream::Stream::new_lzma_decoder(u64::MAX).unwrap(),
+        )),
+        Xz => Box::new(liblzma::read::XzDecoder::new(decoder)),
+        Lzip => Box::new(liblzma::read::XzDecoder::new_stream(
+            decoder,
+            liblzma::stream::Stream::new_lzip_decoder(u64::MAX, 0).unwrap(),
+        )),
+        Snappy => Box::new(snap::read::FrameDecoder::new(decoder)),
+        // Reads all the concatenated frames, not only the first one
+        Zstd => Box::new(zstd::stream::Decoder::new(decoder)?),
+        Brotli => Box::new(brotli::Decompressor::new(decoder, BUFFER_CAPACITY)),
+        Tar | Zip | Rar | SevenZip => decoder,
+    };
+    Ok(decoder)
+}
+
+/// With `resume`, the archive is unpacked into the existing output instead of a new directory.
+/// With `trash`, the outputs replaced on conflicts are moved to the trash.
+/// With `set_mtime`, everything in the output directory gets this modification time, which is why
+/// it's never combined with `resume`. With `rename_root`, the single root of the archive is moved
+/// to `output_dir` under this name.
+#[allow(clippy::too_many_arguments)]
 fn execute_decompression(
     unpack_fn: impl FnOnce(&Path) -> crate::Result<usize>,
     output_dir: &Path,
     output_file_path: &Path,
     question_policy: QuestionPolicy,
+    trash: bool,
     is_output_dir_provided: bool,
     is_smart_unpack: bool,
+    resume: bool,
+    set_mtime: Option<SystemTime>,
+    rename_root: Option<&str>,
 ) -> crate::Result<ControlFlow<(), usize>> {
-    if is_smart_unpack {
-        return smart_unpack(unpack_fn, output_dir, output_file_path, question_policy);
+    // Everything in the directory was just extracted, even when resuming
+    let unpack_fn = |output_dir: &Path| {
+        let files = unpack_fn(output_dir)?;
+        if let Some(mtime) = set_mtime {
+            set_mtimes(output_dir, FileTime::from_system_time(mtime))?;
+        }
+        Ok(files)
+    };
+
+    if (is_smart_unpack || rename_root.is_some()) && !resume {
+        return smart_unpack(
+            unpack_fn,
+            output_dir,
+            output_file_path,
+            question_policy,
+            trash,
+            rename_root,
+        );
     }
 
     let target_output_dir = if is_output_dir_provided {
@@ -334,24 +883,28 @@ fn execute_decompression(
         output_file_path
     };
 
-    unpack(unpack_fn, target_output_dir, question_policy)
+    unpack(unpack_fn, target_output_dir, question_policy, trash, resume)
 }
 
 /// Unpacks an archive creating the output directory, this function will create the output_dir
 /// directory or replace it if it already exists. The `output_dir` needs to be empty
 /// - If `output_dir` does not exist OR is a empty directory, it will unpack there
 /// - If `output_dir` exist OR is a directory not empty, the user will be asked what to do
+/// - With `resume`, an existing `output_dir` is unpacked into as-is
 fn unpack(
     unpack_fn: impl FnOnce(&Path) -> crate::Result<usize>,
     output_dir: &Path,
     question_policy: QuestionPolicy,
+    trash: bool,
+    resume: bool,
 ) -> crate::Result<ControlFlow<(), usize>> {
-    let is_valid_output_dir = !output_dir.exists() || (output_dir.is_dir() && output_dir.read_dir()?.next().is_none());
+    let is_valid_output_dir =
+        !output_dir.exists() || (output_dir.is_dir() && (resume || output_dir.read_dir()?.next().is_none()));
 
     let output_dir_cleaned = if is_valid_output_dir {
         output_dir.to_owned()
     } else {
-        match utils::resolve_path_conflict(output_dir, question_policy, QuestionAction::Decompression)? {
+        match utils::resolve_path_conflict(output_dir, question_policy, QuestionAction::Decompression, trash)? {
             Some(path) => path,
             None => return Ok(ControlFlow::Break(())),
         }
@@ -371,12 +924,17 @@ fn unpack(
 /// - If the archive contains multiple files, it will be extracted to a subdirectory of the
 ///   output_dir named after the archive (given by `output_file_path`)
 ///
+/// - With `root_name`, the archive must contain only one file, extracted to the `output_dir` under
+///   this name
+///
 /// Note: This functions assumes that `output_dir` exists
 fn smart_unpack(
     unpack_fn: impl FnOnce(&Path) -> crate::Result<usize>,
     output_dir: &Path,
     output_file_path: &Path,
     question_policy: QuestionPolicy,
+    trash: bool,
+    root_name: Option<&str>,
 ) -> crate::Result<ControlFlow<(), usize>> {
     assert!(output_dir.exists());
     let temp_dir = tempfile::Builder::new().prefix("tmp-ouch-").tempdir_in(output_dir)?;
@@ -390,6 +948,9 @@ fn smart_unpack(
     let files = unpack_fn(temp_dir_path)?;
 
     let root_contains_only_one_element = fs::read_dir(temp_dir_path)?.take(2).count() == 1;
+    if let (Some(root_name), false) = (root_name, root_contains_only_one_element) {
+        return Err(not_single_root(temp_dir_path, root_name)?);
+    }
 
     let (previous_path, mut new_path) = if root_contains_only_one_element {
         // Only one file in the root directory, so we can just move it to the output directory
@@ -398,7 +959,7 @@ fn smart_unpack(
         let file_name = file_path
             .file_name()
             .expect("Should be safe because paths in archives should not end with '..'");
-        let correct_path = output_dir.join(file_name);
+        let correct_path = output_dir.join(root_name.map_or(file_name, OsStr::new));
 
         (file_path, correct_path)
     } else {
@@ -407,7 +968,7 @@ fn smart_unpack(
 
     // Before moving, need to check if a file with the same name already exists
     // If it does, need to ask the user what to do
-    new_path = match utils::resolve_path_conflict(&new_path, question_policy, QuestionAction::Decompression)? {
+    new_path = match utils::resolve_path_conflict(&new_path, question_policy, QuestionAction::Decompression, trash)? {
         Some(path) => path,
         None => return Ok(ControlFlow::Break(())),
     };
@@ -424,3 +985,74 @@ fn smart_unpack(
 
     Ok(ControlFlow::Continue(files))
 }
+
+/// Error of `--rename-root` for archives extracted to `dir` with several roots, or none
+fn not_single_root(dir: &Path, root_name: &str) -> crate::Result<Error> {
+    let mut roots = fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<io::Result<Vec<_>>>()?;
+    roots.sort();
+
+    let detail = if roots.is_empty() {
+        "The archive is empty".to_string()
+    } else {
+        format!(
+            "It has {} entries at the top level: {}",
+            roots.len(),
+            pretty_format_list_of_paths(&roots)
+        )
+    };
+    Ok(
+        FinalError::with_title(format!("Cannot extract the root of the archive as '{root_name}'"))
+            .detail("--rename-root only applies to archives with a single entry at the top level")
+            .detail(detail)
+            .hint("Extract it without --rename-root")
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_suffix() {
+        let rule = ReplaceSuffix::new(".min.js=.js").unwrap();
+        assert_eq!(rule.apply(Path::new("data.min.js")), Some("data.js".into()));
+        assert_eq!(rule.apply(Path::new("data.js")), None);
+
+        let rule = ReplaceSuffix::new(".bak=").unwrap();
+        assert_eq!(rule.apply(Path::new("notes.txt.bak")), Some("notes.txt".into()));
+        assert_eq!(rule.apply(Path::new(".bak")), None);
+
+        assert!(ReplaceSuffix::new(".min.js").is_err());
+        assert!(ReplaceSuffix::new("=.js").is_err());
+        assert!(ReplaceSuffix::new(".js=/tmp/.js").is_err());
+    }
+
+    #[test]
+    fn test_find_digest() {
+        let name = OsStr::new("file.tar.gz");
+        assert_eq!(find_digest("abc123\n", name), Some("abc123"));
+        assert_eq!(find_digest("abc123  file.tar.gz\n", name), Some("abc123"));
+        assert_eq!(
+            find_digest("def456 *other.zip\nabc123 *dist/file.tar.gz\n", name),
+            Some("abc123")
+        );
+        assert_eq!(find_digest("SHA256 (file.tar.gz) = abc123", name), Some("abc123"));
+        assert_eq!(find_digest("def456  other.zip\n", name), None);
+        assert_eq!(find_digest("not a digest\n", name), None);
+    }
+
+    #[test]
+    fn test_parse_mtime() {
+        let at = |seconds| SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds);
+        assert_eq!(parse_mtime("@1706702400").unwrap(), at(1706702400));
+        assert_eq!(parse_mtime("2024-01-31").unwrap(), at(1706659200));
+        assert_eq!(parse_mtime("2024-01-31T12:00:00+01:00").unwrap(), at(1706698800));
+
+        assert!(parse_mtime("@soon").is_err());
+        assert!(parse_mtime("2024-13-01").is_err());
+        assert!(parse_mtime("yesterday").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
     io::{self, BufReader, Read},
-    path::Path,
+    path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use fs_err as fs;
@@ -9,20 +10,67 @@ use crate::{
     archive,
     commands::warn_user_about_loading_zip_in_memory,
     extension::CompressionFormat::{self, *},
-    list::{self, FileInArchive, ListOptions},
-    utils::{io::lock_and_flush_output_stdio, user_wants_to_continue},
+    list::{self, FileInArchive, ListEntry, ListOptions},
+    utils::{io::lock_and_flush_output_stdio, logger::warning, user_wants_to_continue, EscapedPathDisplay},
     QuestionAction, QuestionPolicy, BUFFER_CAPACITY,
 };
 
 /// File at input_file_path is opened for reading, example: "archive.tar.gz"
 /// formats contains each format necessary for decompression, example: [Gz, Tar] (in decompression order)
+///
+/// With `--cache`, the entries are read from the cache of the archive if it's up to date, and
+/// written to it otherwise.
 pub fn list_archive_contents(
     archive_path: &Path,
     formats: Vec<CompressionFormat>,
-    list_options: ListOptions,
+    list_options: &ListOptions,
     question_policy: QuestionPolicy,
     password: Option<&[u8]>,
 ) -> crate::Result<()> {
+    if !list_options.cache {
+        return match archive_entries(archive_path, &formats, question_policy, password)? {
+            Some(entries) => list::list_files(archive_path, entries, list_options),
+            None => Ok(()),
+        };
+    }
+
+    let key = CacheKey::new(archive_path, &formats)?;
+    let cache_path = cache_path(archive_path);
+    if let Some(entries) = read_cache(&cache_path, &key) {
+        return list::list_files(archive_path, entries.into_iter().map(Ok), list_options);
+    }
+
+    let Some(entries) = archive_entries(archive_path, &formats, question_policy, password)? else {
+        return Ok(());
+    };
+    let mut listed = vec![];
+    let mut entries = entries.inspect(|entry| {
+        if let Ok(entry) = entry {
+            listed.push(entry.clone());
+        }
+    });
+    list::list_files(archive_path, entries.by_ref(), list_options)?;
+    // Entries past `--limit` aren't listed, but the cache holds all of them
+    if entries.any(|entry| entry.is_err()) {
+        return Ok(());
+    }
+
+    if let Err(err) = write_cache(&cache_path, key, listed) {
+        warning(format!(
+            "Failed to write the cache {}: {err}",
+            EscapedPathDisplay::new(&cache_path)
+        ));
+    }
+    Ok(())
+}
+
+/// Entries of the archive at `archive_path`, `None` if the user chose not to load it in memory
+fn archive_entries(
+    archive_path: &Path,
+    formats: &[CompressionFormat],
+    question_policy: QuestionPolicy,
+    password: Option<&[u8]>,
+) -> crate::Result<Option<Box<dyn Iterator<Item = crate::Result<ListEntry>>>>> {
     let reader = fs::File::open(archive_path)?;
 
     // Zip archives are special, because they require io::Seek, so it requires it's logic separated
@@ -32,11 +80,10 @@ pub fn list_archive_contents(
     // in-memory decompression/copying first.
     //
     // Any other Zip decompression done can take up the whole RAM and freeze ouch.
-    if let &[Zip] = formats.as_slice() {
+    if let &[Zip] = formats {
         let zip_archive = zip::ZipArchive::new(reader)?;
         let files = crate::archive::zip::list_archive(zip_archive, password);
-        list::list_files(archive_path, files, list_options)?;
-        return Ok(());
+        return Ok(Some(files_only(files)));
     }
 
     // Will be used in decoder chaining
@@ -47,7 +94,7 @@ pub fn list_archive_contents(
     let chain_reader_decoder =
         |format: CompressionFormat, decoder: Box<dyn Read + Send>| -> crate::Result<Box<dyn Read + Send>> {
             let decoder: Box<dyn Read + Send> = match format {
-                Gzip => Box::new(flate2::read::GzDecoder::new(decoder)),
+                Gzip => Box::new(flate2::read::MultiGzDecoder::new(decoder)),
                 Bzip => Box::new(bzip2::read::BzDecoder::new(decoder)),
                 Bzip3 => {
                     #[cfg(not(feature = "bzip3"))]
@@ -84,7 +131,7 @@ pub fn list_archive_contents(
     }
 
     let archive_format = misplaced_archive_format.unwrap_or(formats[0]);
-    let files: Box<dyn Iterator<Item = crate::Result<FileInArchive>>> = match archive_format {
+    let files: Box<dyn Iterator<Item = crate::Result<ListEntry>>> = match archive_format {
         Tar => Box::new(crate::archive::tar::list_archive(tar::Archive::new(reader))),
         Zip => {
             if formats.len() > 1 {
@@ -94,7 +141,7 @@ pub fn list_archive_contents(
 
                 warn_user_about_loading_zip_in_memory();
                 if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
@@ -102,16 +149,16 @@ pub fn list_archive_contents(
             io::copy(&mut reader, &mut vec)?;
             let zip_archive = zip::ZipArchive::new(io::Cursor::new(vec))?;
 
-            Box::new(crate::archive::zip::list_archive(zip_archive, password))
+            files_only(crate::archive::zip::list_archive(zip_archive, password))
         }
         #[cfg(feature = "unrar")]
         Rar => {
             if formats.len() > 1 {
                 let mut temp_file = tempfile::NamedTempFile::new()?;
                 io::copy(&mut reader, &mut temp_file)?;
-                Box::new(crate::archive::rar::list_archive(temp_file.path(), password)?)
+                files_only(crate::archive::rar::list_archive(temp_file.path(), password)?)
             } else {
-                Box::new(crate::archive::rar::list_archive(archive_path, password)?)
+                files_only(crate::archive::rar::list_archive(archive_path, password)?)
             }
         }
         #[cfg(not(feature = "unrar"))]
@@ -119,6 +166,12 @@ pub fn list_archive_contents(
             return Err(crate::archive::rar_stub::no_support());
         }
         SevenZip => {
+            if formats.len() == 1 {
+                if let Some(volumes) = archive::sevenz::open_volumes(archive_path)? {
+                    return Ok(Some(files_only(archive::sevenz::list_archive(volumes, password)?)));
+                }
+            }
+
             if formats.len() > 1 {
                 // Locking necessary to guarantee that warning and question
                 // messages stay adjacent
@@ -126,19 +179,76 @@ pub fn list_archive_contents(
 
                 warn_user_about_loading_zip_in_memory();
                 if !user_wants_to_continue(archive_path, question_policy, QuestionAction::Decompression)? {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
             let mut vec = vec![];
             io::copy(&mut reader, &mut vec)?;
 
-            Box::new(archive::sevenz::list_archive(io::Cursor::new(vec), password)?)
+            files_only(archive::sevenz::list_archive(io::Cursor::new(vec), password)?)
         }
         Gzip | Bzip | Bzip3 | Lz4 | Lzma | Xz | Lzip | Snappy | Zstd | Brotli => {
             unreachable!("Not an archive, should be validated before calling this function.");
         }
     };
 
-    list::list_files(archive_path, files, list_options)
+    Ok(Some(files))
+}
+
+/// Entries of formats that only store files, without archive metadata
+fn files_only(
+    files: impl Iterator<Item = crate::Result<FileInArchive>> + 'static,
+) -> Box<dyn Iterator<Item = crate::Result<ListEntry>>> {
+    Box::new(files.map(|file| file.map(ListEntry::File)))
+}
+
+/// Version of the cache format, caches of other versions are ignored
+const CACHE_VERSION: u32 = 1;
+
+/// What a cache is valid for, it's out of date as soon as the archive changes
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct CacheKey {
+    version: u32,
+    size: u64,
+    mtime: SystemTime,
+    formats: Vec<String>,
+}
+
+impl CacheKey {
+    fn new(archive_path: &Path, formats: &[CompressionFormat]) -> crate::Result<Self> {
+        let metadata = fs::metadata(archive_path)?;
+        Ok(Self {
+            version: CACHE_VERSION,
+            size: metadata.len(),
+            mtime: metadata.modified()?,
+            formats: formats.iter().map(|format| format!("{format:?}")).collect(),
+        })
+    }
+}
+
+/// Contents of the cache of an archive
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Cache {
+    key: CacheKey,
+    entries: Vec<ListEntry>,
+}
+
+/// Path of the cache of an archive, like "backup.tar.zst" -> "backup.tar.zst.ouchidx"
+fn cache_path(archive_path: &Path) -> PathBuf {
+    let mut path = archive_path.as_os_str().to_owned();
+    path.push(".ouchidx");
+    PathBuf::from(path)
+}
+
+/// Reads the entries of the cache at `path` if it's valid for `key`, missing, damaged or out of
+/// date caches are silently replaced.
+fn read_cache(path: &Path, key: &CacheKey) -> Option<Vec<ListEntry>> {
+    let cache: Cache = serde_json::from_slice(&std::fs::read(path).ok()?).ok()?;
+    (cache.key == *key).then_some(cache.entries)
+}
+
+fn write_cache(path: &Path, key: CacheKey, entries: Vec<ListEntry>) -> io::Result<()> {
+    let cache = serde_json::to_vec(&Cache { key, entries })?;
+    fs::write(path, cache)
 }
@@ -1,26 +1,48 @@
 //! Receive command from the cli and call the respective function for that command.
 
-mod compress;
-mod decompress;
+pub mod compress;
+pub mod decompress;
 mod list;
-
-use std::{ops::ControlFlow, path::PathBuf};
+mod repair;
+mod verify;
+
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bstr::ByteSlice;
-use decompress::DecompressOptions;
+use decompress::{check_root_name, parse_mtime, DecompressOptions, ReplaceSuffix};
 use rayon::prelude::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use utils::colors;
 
 use crate::{
+    archive::{EntryModes, OwnerMaps},
     check,
-    cli::Subcommand,
-    commands::{compress::compress_files, decompress::decompress_file, list::list_archive_contents},
+    cli::{LogFormat, Preset, Subcommand},
+    commands::{
+        compress::{
+            compress_files, copy_stdin_input, enforce_min_ratio, measure_inputs, parse_ratio, parse_size, plan_volumes,
+            read_exclude_patterns, remove_inputs, volume_path, CompressionParameters, CompressionStats, StoredOutput,
+        },
+        decompress::decompress_file,
+        list::list_archive_contents,
+    },
     error::{Error, FinalError},
-    extension::{self, parse_format_flag},
+    extension::{self, parse_format_flag, CompressionFormat},
     list::ListOptions,
     utils::{
-        self, colors::*, is_path_stdin, logger::info_accessible, path_to_str, EscapedPathDisplay, FileVisibilityPolicy,
-        QuestionAction,
+        self,
+        colors::*,
+        is_path_stdin,
+        logger::{format_record, info_accessible, log_format, warning},
+        output::print_message,
+        partial_output::PartialOutput,
+        path_to_str, pretty_format_list_of_paths,
+        progress::{self, AggregateProgress},
+        user_wants_to_continue, DateFormat, EscapedPathDisplay, FileVisibilityPolicy, QuestionAction,
     },
     CliArgs, QuestionPolicy,
 };
@@ -32,7 +54,7 @@ fn warn_user_about_loading_zip_in_memory() {
         When chaining '.zip' with other formats, all (de)compression needs to be done in-memory\n  \
         Careful, you might run out of RAM if the archive is too large!";
 
-    eprintln!("{}[WARNING]{}: {ZIP_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET);
+    print_in_memory_warning(ZIP_IN_MEMORY_LIMITATION_WARNING);
 }
 
 /// Warn the user that (de)compressing this .7z archive might freeze their system.
@@ -42,7 +64,15 @@ fn warn_user_about_loading_sevenz_in_memory() {
         When chaining '.7z' with other formats, all (de)compression needs to be done in-memory\n  \
         Careful, you might run out of RAM if the archive is too large!";
 
-    eprintln!("{}[WARNING]{}: {SEVENZ_IN_MEMORY_LIMITATION_WARNING}", *ORANGE, *RESET);
+    print_in_memory_warning(SEVENZ_IN_MEMORY_LIMITATION_WARNING);
+}
+
+/// Prints the in-memory warnings right away, so they stay next to the question following them
+fn print_in_memory_warning(warning: &str) {
+    match log_format() {
+        LogFormat::Human => print_message(format_args!("{}[WARNING]{}: {warning}\n", *ORANGE, *RESET)),
+        format => print_message(format_args!("{}\n", format_record(format, "warning", warning.trim()))),
+    }
 }
 
 /// This function checks what command needs to be run and performs A LOT of ahead-of-time checks
@@ -63,18 +93,61 @@ pub fn run(
 
     match args.cmd {
         Subcommand::Compress {
-            files,
+            mut files,
             output: output_path,
             level,
             fast,
             slow,
             follow_symlinks,
+            read0: _,
+            preset,
+            mut name,
+            mode,
+            embed_manifest,
+            lz4_format,
+            mtime_granularity,
+            split_by_entry,
+            frame_size,
+            traversal,
+            allow_empty,
+            exclude_from,
+            eol,
+            min_ratio,
+            min_ratio_fallback,
+            remove,
+            stats_json,
+            verify_against_source,
         } => {
-            // After cleaning, if there are no input files left, exit
-            if files.is_empty() {
-                return Err(FinalError::with_title("No files to compress").into());
+            // After cleaning, if there are no input files left, exit, as an empty archive most
+            // likely hides a mistake like paths read with `--read0` that matched nothing
+            if files.is_empty() && !allow_empty {
+                return Err(FinalError::with_title("No files to compress")
+                    .detail("Nothing would be written to the output")
+                    .hint("Check that the inputs, or the patterns expanded into them, matched what you meant")
+                    .hint("Use --allow-empty to create an empty archive anyway")
+                    .into());
+            }
+
+            // Taken before stdin is replaced by a copy, which isn't an input to remove
+            let inputs: Vec<PathBuf> = files.iter().filter(|path| !is_path_stdin(path)).cloned().collect();
+            let _stdin_copy = copy_stdin_input(&mut files, &mut name, args.stdin_name.as_deref())?;
+            let mut file_visibility_policy = file_visibility_policy.traversal(traversal);
+            // The other options of the preset were resolved with the arguments, as the output can
+            // be named after its format
+            let preset_exclude = match &preset {
+                Some(name) => Preset::named(name)?.exclude,
+                None => vec![],
+            };
+            if !exclude_from.is_empty() || !preset_exclude.is_empty() {
+                file_visibility_policy =
+                    file_visibility_policy.exclude(read_exclude_patterns(&exclude_from, &preset_exclude)?);
             }
 
+            if let Some(name) = &name {
+                check::check_stored_name(name, &files)?;
+            }
+            let modes = mode.as_deref().map(EntryModes::new).transpose()?.unwrap_or_default();
+
             // Formats from path extension, like "file.tar.gz.xz" -> vec![Tar, Gzip, Lzma]
             let (formats_from_flag, formats) = match args.format {
                 Some(formats) => {
@@ -84,19 +157,60 @@ pub fn run(
                 None => (None, extension::extensions_from_path(&output_path)?),
             };
 
+            // With --format, the last of multiple inputs would be taken as the output, don't
+            // overwrite a directory the user meant to compress
+            if formats_from_flag.is_some() && output_path.is_dir() {
+                return Err(FinalError::with_title("Missing the output path")
+                    .detail(format!(
+                        "{} is a directory, it can't be the output file",
+                        EscapedPathDisplay::new(&output_path)
+                    ))
+                    .hint("Pass the output file as the last path when compressing multiple files")
+                    .into());
+            }
+
             check::check_invalid_compression_with_non_archive_format(
                 &formats,
                 &output_path,
                 &files,
                 formats_from_flag.as_ref(),
             )?;
-            check::check_archive_formats_position(&formats, &output_path)?;
+            if files.is_empty() && !formats[0].is_archive() {
+                return Err(FinalError::with_title("No files to compress")
+                    .detail("Only archives can be created empty")
+                    .into());
+            }
+            // The filters can leave nothing of the input directories but themselves, which is as
+            // likely a mistake. Directories that are empty to begin with are archived as asked.
+            if !files.is_empty() && formats[0].is_archive() && !allow_empty {
+                let (_, entries) = measure_inputs(&files, &file_visibility_policy, follow_symlinks)?;
+                let input_dirs: Vec<_> = files.iter().filter(|path| path.is_dir()).collect();
+                let filtered_out = entries <= input_dirs.len() as u64
+                    && input_dirs
+                        .iter()
+                        .any(|dir| dir.read_dir().is_ok_and(|mut entries| entries.next().is_some()));
+                if filtered_out {
+                    return Err(FinalError::with_title("No files to compress")
+                        .detail("Nothing is left in the input directories once filtered")
+                        .hint("Check the patterns of --exclude-from, --gitignore and the preset")
+                        .hint("Use --allow-empty to create the archive anyway")
+                        .into());
+                }
+            }
+            if embed_manifest {
+                check::check_manifest_support(&formats)?;
+            }
 
-            let output_file =
-                match utils::ask_to_create_file(&output_path, question_policy, QuestionAction::Compression)? {
-                    Some(writer) => writer,
-                    None => return Ok(()),
-                };
+            let min_ratio = min_ratio.as_deref().map(parse_ratio).transpose()?;
+            let zstd_frame_size = frame_size
+                .as_deref()
+                .map(|size| parse_size(size, "frame"))
+                .transpose()?;
+            if zstd_frame_size.is_some()
+                && !extension::flatten_compression_formats(&formats).contains(&CompressionFormat::Zstd)
+            {
+                warning("--frame-size only applies to zstd, ignoring it".to_string());
+            }
 
             let level = if fast {
                 Some(1) // Lowest level of compression
@@ -106,52 +220,194 @@ pub fn run(
                 level
             };
 
-            let compress_result = compress_files(
-                files,
-                formats,
-                output_file,
-                &output_path,
-                args.quiet,
-                follow_symlinks,
-                question_policy,
-                file_visibility_policy,
-                level,
-            );
-
-            if let Ok(true) = compress_result {
-                // this is only printed once, so it doesn't result in much text. On the other hand,
-                // having a final status message is important especially in an accessibility context
-                // as screen readers may not read a commands exit code, making it hard to reason
-                // about whether the command succeeded without such a message
-                info_accessible(format!("Successfully compressed '{}'", path_to_str(&output_path)));
-            } else {
-                // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
-                //
-                // if deleting fails, print an extra alert message pointing
-                // out that we left a possibly CORRUPTED file at `output_path`
-                if utils::remove_file_or_dir(&output_path).is_err() {
-                    eprintln!("{red}FATAL ERROR:\n", red = *colors::RED);
-                    eprintln!(
-                        "  Ouch failed to delete the file '{}'.",
-                        EscapedPathDisplay::new(&output_path)
-                    );
-                    eprintln!("  Please delete it manually.");
-                    eprintln!("  This file is corrupted if compression didn't finished.");
+            // Volumes and stored outputs of --min-ratio-fallback are written next to the output
+            let output_dir = match output_path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
 
-                    if compress_result.is_err() {
-                        eprintln!("  Compression failed for reasons below.");
+            // Inputs are walked for --stats-json and --verify-against-source with all their
+            // entries, whatever the volumes
+            let inputs_policy =
+                (stats_json.is_some() || verify_against_source.is_some()).then(|| file_visibility_policy.clone());
+            let mut outputs = vec![];
+            // Replacing the outputs of --min-ratio-fallback store, they have no compression formats
+            let mut stored_outputs = vec![];
+            let mut duration = Duration::ZERO;
+
+            // Each volume is compressed as its own archive, with only some of the entries
+            let volumes = match split_by_entry {
+                Some(max_size) => {
+                    check::check_split_by_entry_support(&formats)?;
+                    let max_size = parse_size(&max_size, "volume")?;
+                    let volumes = plan_volumes(&files, &file_visibility_policy, follow_symlinks, max_size)?;
+                    volumes
+                        .into_iter()
+                        .enumerate()
+                        .map(|(index, paths)| {
+                            let policy = file_visibility_policy.clone().only_paths(paths);
+                            Ok((volume_path(&output_path, index + 1)?, policy))
+                        })
+                        .collect::<crate::Result<Vec<_>>>()?
+                }
+                None => vec![(output_path, file_visibility_policy)],
+            };
+
+            for (output_path, file_visibility_policy) in volumes {
+                let output_file =
+                    match utils::ask_to_create_file(&output_path, question_policy, QuestionAction::Compression, false)?
+                    {
+                        Some(writer) => writer,
+                        None => return Ok(()),
+                    };
+                let partial_output = PartialOutput::new(&output_path)?;
+
+                let started = Instant::now();
+                let compress_result = compress_files(
+                    files.clone(),
+                    formats.clone(),
+                    output_file,
+                    &output_path,
+                    args.quiet,
+                    follow_symlinks,
+                    question_policy,
+                    file_visibility_policy.clone(),
+                    level,
+                    name.as_deref(),
+                    modes,
+                    embed_manifest,
+                    lz4_format,
+                    mtime_granularity,
+                    zstd_frame_size,
+                    eol,
+                );
+                duration += started.elapsed();
+
+                if let Ok(true) = compress_result {
+                    partial_output.finish();
+                    let stored = match min_ratio {
+                        Some(min_ratio) => enforce_min_ratio(
+                            &files,
+                            &formats,
+                            &output_path,
+                            &file_visibility_policy,
+                            follow_symlinks,
+                            min_ratio,
+                            min_ratio_fallback,
+                            question_policy,
+                        )?,
+                        None => None,
+                    };
+                    // this is only printed once per volume, so it doesn't result in much text. On the other hand,
+                    // having a final status message is important especially in an accessibility context
+                    // as screen readers may not read a commands exit code, making it hard to reason
+                    // about whether the command succeeded without such a message
+                    match stored {
+                        Some(stored) => stored_outputs.push(stored),
+                        None => {
+                            info_accessible(format!("Successfully compressed '{}'", path_to_str(&output_path)));
+                            outputs.push(output_path.clone());
+                        }
                     }
+                } else {
+                    // If Ok(false) or Err() occurred, delete incomplete file at `output_path`
+                    //
+                    // if deleting fails, print an extra alert message pointing
+                    // out that we left a possibly CORRUPTED file at `output_path`
+                    if utils::remove_file_or_dir(&output_path).is_err() {
+                        eprintln!("{red}FATAL ERROR:\n", red = *colors::RED);
+                        eprintln!(
+                            "  Ouch failed to delete the file '{}'.",
+                            EscapedPathDisplay::new(&output_path)
+                        );
+                        eprintln!("  Please delete it manually.");
+                        eprintln!("  This file is corrupted if compression didn't finished.");
+
+                        if compress_result.is_err() {
+                            eprintln!("  Compression failed for reasons below.");
+                        }
+                    }
+                }
+
+                if !compress_result? {
+                    return Ok(());
                 }
             }
 
-            compress_result.map(|_| ())
+            if let (Some(check), Some(policy)) = (verify_against_source, &inputs_policy) {
+                verify::verify_against_source(
+                    &files,
+                    &extension::flatten_compression_formats(&formats),
+                    &outputs,
+                    name.as_deref(),
+                    policy,
+                    follow_symlinks,
+                    check,
+                )?;
+            }
+            if let (Some(stats_path), Some(policy)) = (&stats_json, &inputs_policy) {
+                let parameters = CompressionParameters::new(&formats, level, lz4_format, zstd_frame_size);
+                let outputs = outputs
+                    .iter()
+                    .map(PathBuf::as_path)
+                    .chain(stored_outputs.iter().map(StoredOutput::path))
+                    .map(Path::to_path_buf)
+                    .collect();
+                CompressionStats::measure(&files, &formats, outputs, policy, follow_symlinks, duration, parameters)?
+                    .write(stats_path)?;
+            }
+
+            if remove {
+                let kept_inputs: Vec<_> = stored_outputs
+                    .iter()
+                    .filter_map(|stored| match stored {
+                        StoredOutput::KeptInput(path) => Some(path.clone()),
+                        StoredOutput::Stored(_) => None,
+                    })
+                    .collect();
+                remove_inputs(&inputs, &output_dir, &kept_inputs)?;
+            }
+
+            Ok(())
         }
         Subcommand::Decompress {
             files,
             output_dir,
             remove,
             no_smart_unpack,
+            absolute_symlinks,
+            continue_on_error,
+            output,
+            replace_suffix,
+            preserve_special_bits,
+            strip_trailing_components,
+            rename_root,
+            no_crc_check,
+            trash,
+            resume,
+            confined,
+            owner_map,
+            group_map,
+            preserve_atime,
+            no_verify,
+            set_mtime,
+            start_at,
+            force_local,
         } => {
+            utils::set_force_local(force_local);
+
+            if output.is_some() && files.len() > 1 {
+                return Err(FinalError::with_title("Cannot use --output with multiple input files")
+                    .detail("Each input file would be decompressed to the same output path")
+                    .hint("Use --dir to place the results in another directory")
+                    .into());
+            }
+            let replace_suffix = replace_suffix.as_deref().map(ReplaceSuffix::new).transpose()?;
+            if let Some(name) = &rename_root {
+                check_root_name(name)?;
+            }
+            let set_mtime = set_mtime.as_deref().map(parse_mtime).transpose()?;
+
             let mut output_paths = vec![];
             let mut formats = vec![];
 
@@ -167,6 +423,16 @@ pub fn run(
                 }
             } else {
                 for path in files.iter() {
+                    // Data from stdin is named after --stdin-name, it can't be read to guess its type
+                    if let (Some(stdin_name), true) = (&args.stdin_name, is_path_stdin(path)) {
+                        let (pathbase, file_formats) =
+                            extension::separate_known_extensions_from_name(Path::new(stdin_name))?;
+                        check::check_stdin_name(stdin_name, &file_formats)?;
+                        output_paths.push(pathbase);
+                        formats.push(file_formats);
+                        continue;
+                    }
+
                     let (pathbase, mut file_formats) = extension::separate_known_extensions_from_name(path)?;
 
                     if let ControlFlow::Break(_) = check::check_mime_type(path, &mut file_formats, question_policy)? {
@@ -180,6 +446,59 @@ pub fn run(
 
             check::check_missing_formats_when_decompressing(&files, &formats)?;
 
+            let owners = OwnerMaps::new(owner_map.as_deref(), group_map.as_deref())?;
+            if owners.is_some()
+                && formats
+                    .iter()
+                    .any(|formats| !extension::flatten_compression_formats(formats).contains(&CompressionFormat::Tar))
+            {
+                warning(
+                    "Only tar archives store owners, ignoring --owner-map and --group-map for other formats"
+                        .to_string(),
+                );
+            }
+            if preserve_atime
+                && formats
+                    .iter()
+                    .any(|formats| !extension::flatten_compression_formats(formats).contains(&CompressionFormat::Tar))
+            {
+                warning(
+                    "Only tar archives store access times, ignoring --preserve-atime for other formats".to_string(),
+                );
+            }
+
+            if rename_root.is_some()
+                && formats.iter().any(|formats| {
+                    !extension::flatten_compression_formats(formats)
+                        .iter()
+                        .any(CompressionFormat::archive_format)
+                })
+            {
+                warning("Only archives have a root to rename, ignoring --rename-root for other files".to_string());
+            }
+            if start_at.is_some()
+                && formats.iter().any(|formats| {
+                    extension::flatten_compression_formats(formats)
+                        .iter()
+                        .any(CompressionFormat::archive_format)
+                })
+            {
+                warning("Archives are extracted entirely, ignoring --start-at for them".to_string());
+            }
+
+            if preserve_special_bits {
+                warning(
+                    "--preserve-special-bits keeps the setuid, setgid and sticky bits of extracted files, a setuid \
+                     binary runs with the rights of its owner"
+                        .to_string(),
+                );
+                for path in &files {
+                    if !user_wants_to_continue(path, question_policy, QuestionAction::Decompression)? {
+                        return Ok(());
+                    }
+                }
+            }
+
             let is_output_dir_provided = output_dir.is_some();
             let is_smart_unpack = !is_output_dir_provided && !no_smart_unpack;
 
@@ -192,34 +511,121 @@ pub fn run(
                 PathBuf::from(".")
             };
 
-            files
-                .par_iter()
-                .zip(formats)
-                .zip(output_paths)
-                .try_for_each(|((input_path, formats), file_name)| {
-                    // Path used by single file format archives
-                    let output_file_path = if is_path_stdin(file_name) {
-                        output_dir.join("stdin-output")
-                    } else {
-                        output_dir.join(file_name)
-                    };
-                    decompress_file(DecompressOptions {
-                        input_file_path: input_path,
-                        formats,
-                        is_output_dir_provided,
-                        output_dir: &output_dir,
-                        output_file_path,
-                        is_smart_unpack,
-                        question_policy,
-                        quiet: args.quiet,
-                        password: args.password.as_deref().map(|str| {
-                            <[u8] as ByteSlice>::from_os_str(str).expect("convert password to bytes failed")
-                        }),
-                        remove,
+            // Overall progress, only worth it when there's more than one file, or when the size of the
+            // input is unknown as nothing else would tell how far along the decompression is
+            let sizes: Vec<_> = files.iter().map(|path| progress::input_size(path)).collect();
+            let progress = ((files.len() > 1 || sizes.contains(&None)) && AggregateProgress::is_supported(args.quiet))
+                .then(|| Arc::new(AggregateProgress::new(sizes)));
+
+            let decompress = |((input_path, formats), file_name): ((&PathBuf, Vec<_>), &Path)| {
+                // Path used by single file format archives
+                let output_file_path = if let Some(output) = &output {
+                    output_dir.join(output)
+                } else if is_path_stdin(file_name) {
+                    output_dir.join("stdin-output")
+                } else if let Some(rule) = &replace_suffix {
+                    match rule.apply(file_name) {
+                        Some(file_name) => output_dir.join(file_name),
+                        None => {
+                            warning(format!(
+                                "{} doesn't end with the suffix to replace, leaving its name unchanged",
+                                EscapedPathDisplay::new(file_name)
+                            ));
+                            output_dir.join(file_name)
+                        }
+                    }
+                } else {
+                    output_dir.join(file_name)
+                };
+                let result = decompress_file(DecompressOptions {
+                    input_file_path: input_path,
+                    formats,
+                    is_output_dir_provided,
+                    output_dir: &output_dir,
+                    output_file_path,
+                    is_smart_unpack,
+                    question_policy,
+                    trash,
+                    quiet: args.quiet,
+                    password: args
+                        .password
+                        .as_deref()
+                        .map(|str| <[u8] as ByteSlice>::from_os_str(str).expect("convert password to bytes failed")),
+                    remove,
+                    absolute_symlinks,
+                    preserve_special_bits,
+                    strip_trailing_components,
+                    rename_root: rename_root.as_deref(),
+                    check_crc: !no_crc_check,
+                    resume,
+                    confined,
+                    owners: owners.as_ref(),
+                    preserve_atime,
+                    verify: !no_verify,
+                    set_mtime,
+                    start_at: start_at.unwrap_or(0),
+                    progress: progress.clone(),
+                });
+                if let Some(progress) = &progress {
+                    progress.finish_item();
+                }
+                result
+            };
+            let inputs = files.par_iter().zip(formats).zip(output_paths);
+
+            // Multiple inputs are independent, one failing shouldn't stop the others
+            let continue_on_error = continue_on_error || files.len() > 1;
+            if !continue_on_error {
+                return inputs.try_for_each(decompress);
+            }
+
+            let results: Vec<_> = inputs
+                .map(|input @ ((input_path, _), _)| {
+                    decompress(input).inspect_err(|err| {
+                        warning(format!(
+                            "Failed to decompress {}, continuing with the other files\n{err}",
+                            EscapedPathDisplay::new(input_path)
+                        ))
                     })
                 })
+                .collect();
+            if let Some(progress) = &progress {
+                progress.finish();
+            }
+
+            let failed_files: Vec<&PathBuf> = files
+                .iter()
+                .zip(&results)
+                .filter(|(_, result)| result.is_err())
+                .map(|(path, _)| path)
+                .collect();
+
+            if failed_files.is_empty() {
+                Ok(())
+            } else {
+                Err(FinalError::with_title(format!(
+                    "Failed to decompress {} of {} files",
+                    failed_files.len(),
+                    files.len()
+                ))
+                .detail(format!(
+                    "Files that failed: {}",
+                    pretty_format_list_of_paths(&failed_files)
+                ))
+                .into())
+            }
         }
-        Subcommand::List { archives: files, tree } => {
+        Subcommand::List {
+            archives: files,
+            tree,
+            long,
+            date_format,
+            list_duplicates,
+            cache,
+            no_cache: _,
+            offset,
+            limit,
+        } => {
             let mut formats = vec![];
 
             if let Some(format) = args.format {
@@ -242,7 +648,20 @@ pub fn run(
             // Ensure we were not told to list the content of a non-archive compressed file
             check::check_for_non_archive_formats(&files, &formats)?;
 
-            let list_options = ListOptions { tree };
+            let date_format = date_format
+                .as_deref()
+                .map(DateFormat::new)
+                .transpose()?
+                .unwrap_or_default();
+            let list_options = ListOptions {
+                tree,
+                long,
+                date_format,
+                list_duplicates,
+                cache,
+                offset,
+                limit,
+            };
 
             for (i, (archive_path, formats)) in files.iter().zip(formats).enumerate() {
                 if i > 0 {
@@ -252,7 +671,7 @@ pub fn run(
                 list_archive_contents(
                     archive_path,
                     formats,
-                    list_options,
+                    &list_options,
                     question_policy,
                     args.password
                         .as_deref()
@@ -262,5 +681,10 @@ pub fn run(
 
             Ok(())
         }
+        Subcommand::Repair { archive, output } => {
+            let output = output.unwrap_or_else(|| repair::default_output_path(&archive));
+            repair::repair_zip(&archive, &output, question_policy)?;
+            Ok(())
+        }
     }
 }
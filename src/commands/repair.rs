@@ -0,0 +1,289 @@
+//! Rebuilding the central directory of a damaged zip archive from its local file entries.
+//!
+//! The archive is scanned for local file headers, each entry found is copied as-is to the repaired
+//! archive, followed by a new central directory listing them. Only what local headers store can be
+//! recovered: permissions and comments kept in the central directory are lost.
+
+use std::{
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bstr::ByteSlice;
+use fs_err as fs;
+
+use crate::{
+    error::FinalError,
+    utils::{self, logger::info_accessible, EscapedPathDisplay},
+    QuestionAction, QuestionPolicy,
+};
+
+const LOCAL_HEADER_SIGNATURE: [u8; 4] = *b"PK\x03\x04";
+const DATA_DESCRIPTOR_SIGNATURE: [u8; 4] = *b"PK\x07\x08";
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = *b"PK\x01\x02";
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: [u8; 4] = *b"PK\x05\x06";
+
+/// Size of a local file header, without its name and extra field
+const LOCAL_HEADER_SIZE: usize = 30;
+
+/// Flag of entries whose CRC32 and sizes are in a data descriptor following their content
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+
+/// Sizes and offsets of zip64 archives, which aren't supported, don't fit in 32 bits
+const ZIP64_MARKER: u32 = u32::MAX;
+
+/// Entry found in the damaged archive
+#[derive(Debug, PartialEq, Eq)]
+struct LocalEntry {
+    /// Offset of the local header
+    start: usize,
+    /// Offset right after the content, and the data descriptor if any
+    end: usize,
+    version_needed: u16,
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc32: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    name: Vec<u8>,
+}
+
+/// Path of the repaired archive if not given, like "archive.zip" -> "archive.repaired.zip"
+pub fn default_output_path(archive_path: &Path) -> PathBuf {
+    let stem = archive_path.file_stem().unwrap_or_default();
+    let mut name = stem.to_owned();
+    name.push(".repaired.zip");
+    archive_path.with_file_name(name)
+}
+
+/// Writes a repaired copy of the zip archive at `archive_path` to `output_path`, returns `false`
+/// if the user chose not to overwrite it.
+pub fn repair_zip(archive_path: &Path, output_path: &Path, question_policy: QuestionPolicy) -> crate::Result<bool> {
+    let data = fs::read(archive_path)?;
+    let entries = scan_local_entries(&data);
+
+    if entries.is_empty() {
+        return Err(
+            FinalError::with_title(format!("No entries found in {}", EscapedPathDisplay::new(archive_path)))
+                .detail("No intact local file header was found, it may not be a zip archive")
+                .into(),
+        );
+    }
+    if entries.len() > u16::MAX as usize {
+        return Err(zip64_not_supported(archive_path));
+    }
+
+    let Some(output_file) =
+        utils::ask_to_create_file(output_path, question_policy, QuestionAction::Compression, false)?
+    else {
+        return Ok(false);
+    };
+    let mut writer = BufWriter::new(output_file);
+
+    let mut offsets = Vec::with_capacity(entries.len());
+    let mut offset = 0_usize;
+    for entry in &entries {
+        offsets.push(u32::try_from(offset).map_err(|_| zip64_not_supported(archive_path))?);
+        writer.write_all(&data[entry.start..entry.end])?;
+        offset += entry.end - entry.start;
+    }
+
+    let central_directory_offset = u32::try_from(offset).map_err(|_| zip64_not_supported(archive_path))?;
+    let mut central_directory = vec![];
+    for (entry, offset) in entries.iter().zip(offsets) {
+        write_central_header(&mut central_directory, entry, offset);
+    }
+    writer.write_all(&central_directory)?;
+
+    let mut end = vec![];
+    end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE);
+    end.extend_from_slice(&[0; 4]); // Number of this disk, and of the one with the central directory
+    end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    end.extend_from_slice(&(central_directory.len() as u32).to_le_bytes());
+    end.extend_from_slice(&central_directory_offset.to_le_bytes());
+    end.extend_from_slice(&0_u16.to_le_bytes()); // Comment length
+    writer.write_all(&end)?;
+    writer.flush()?;
+
+    info_accessible(format!(
+        "Recovered {} entries from {} into {}",
+        entries.len(),
+        EscapedPathDisplay::new(archive_path),
+        EscapedPathDisplay::new(output_path)
+    ));
+
+    Ok(true)
+}
+
+fn zip64_not_supported(archive_path: &Path) -> crate::Error {
+    FinalError::with_title(format!("Cannot repair {}", EscapedPathDisplay::new(archive_path)))
+        .detail("Archives with more than 65535 entries or larger than 4 GiB need zip64, which isn't supported")
+        .into()
+}
+
+/// Finds the entries of `data` whose local header and content are intact.
+///
+/// Signatures found inside of the content of an entry are skipped, as scanning resumes after it.
+fn scan_local_entries(data: &[u8]) -> Vec<LocalEntry> {
+    let mut entries = vec![];
+    let mut position = 0;
+    while let Some(offset) = data[position..].find(LOCAL_HEADER_SIGNATURE) {
+        let start = position + offset;
+        match parse_local_entry(data, start) {
+            Some(entry) => {
+                position = entry.end;
+                entries.push(entry);
+            }
+            // Looked like a header by chance, or the entry is truncated
+            None => position = start + LOCAL_HEADER_SIGNATURE.len(),
+        }
+    }
+    entries
+}
+
+/// Parses the entry whose local header starts at `start`, returns `None` if it's damaged.
+fn parse_local_entry(data: &[u8], start: usize) -> Option<LocalEntry> {
+    let header = data.get(start..start + LOCAL_HEADER_SIZE)?;
+    let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+    let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+    let flags = u16_at(6);
+    let name_length = u16_at(26) as usize;
+    let extra_length = u16_at(28) as usize;
+
+    let name_start = start + LOCAL_HEADER_SIZE;
+    let name = data.get(name_start..name_start + name_length)?.to_vec();
+    if name.is_empty() {
+        return None;
+    }
+    let content_start = name_start + name_length + extra_length;
+
+    let (crc32, compressed_size, uncompressed_size, end) = if flags & DATA_DESCRIPTOR_FLAG != 0 {
+        find_data_descriptor(data, content_start)?
+    } else {
+        let compressed_size = u32_at(18);
+        let end = content_start + compressed_size as usize;
+        if end > data.len() {
+            return None;
+        }
+        (u32_at(14), compressed_size, u32_at(22), end)
+    };
+
+    if compressed_size == ZIP64_MARKER || uncompressed_size == ZIP64_MARKER {
+        return None;
+    }
+
+    Some(LocalEntry {
+        start,
+        end,
+        version_needed: u16_at(4),
+        flags,
+        method: u16_at(8),
+        time: u16_at(10),
+        date: u16_at(12),
+        crc32,
+        compressed_size,
+        uncompressed_size,
+        name,
+    })
+}
+
+/// Finds the data descriptor following the content starting at `content_start`, as the one whose
+/// compressed size matches its distance from the start. Returns the CRC32 and sizes it holds,
+/// along with the offset right after it.
+fn find_data_descriptor(data: &[u8], content_start: usize) -> Option<(u32, u32, u32, usize)> {
+    let mut position = content_start;
+    while let Some(offset) = data.get(position..)?.find(DATA_DESCRIPTOR_SIGNATURE) {
+        let descriptor_start = position + offset;
+        let descriptor = data.get(descriptor_start..descriptor_start + 16)?;
+        let u32_at = |offset: usize| u32::from_le_bytes(descriptor[offset..offset + 4].try_into().unwrap());
+
+        let compressed_size = u32_at(8);
+        if compressed_size as usize == descriptor_start - content_start {
+            return Some((u32_at(4), compressed_size, u32_at(12), descriptor_start + 16));
+        }
+        position = descriptor_start + DATA_DESCRIPTOR_SIGNATURE.len();
+    }
+    None
+}
+
+fn write_central_header(output: &mut Vec<u8>, entry: &LocalEntry, local_header_offset: u32) {
+    // Directories are told apart by the trailing slash of their name
+    let external_attributes: u32 = if entry.name.ends_with(b"/") { 0x10 } else { 0 };
+
+    output.extend_from_slice(&CENTRAL_HEADER_SIGNATURE);
+    output.extend_from_slice(&entry.version_needed.to_le_bytes()); // Version made by
+    output.extend_from_slice(&entry.version_needed.to_le_bytes());
+    output.extend_from_slice(&entry.flags.to_le_bytes());
+    output.extend_from_slice(&entry.method.to_le_bytes());
+    output.extend_from_slice(&entry.time.to_le_bytes());
+    output.extend_from_slice(&entry.date.to_le_bytes());
+    output.extend_from_slice(&entry.crc32.to_le_bytes());
+    output.extend_from_slice(&entry.compressed_size.to_le_bytes());
+    output.extend_from_slice(&entry.uncompressed_size.to_le_bytes());
+    output.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    output.extend_from_slice(&[0; 8]); // Extra field and comment lengths, disk number, internal attributes
+    output.extend_from_slice(&external_attributes.to_le_bytes());
+    output.extend_from_slice(&local_header_offset.to_le_bytes());
+    output.extend_from_slice(&entry.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stored entry named "a" holding "hello", with its CRC32 and sizes in a data descriptor
+    fn streamed_entry() -> Vec<u8> {
+        let mut entry = LOCAL_HEADER_SIGNATURE.to_vec();
+        entry.extend_from_slice(&20_u16.to_le_bytes());
+        entry.extend_from_slice(&DATA_DESCRIPTOR_FLAG.to_le_bytes());
+        entry.extend_from_slice(&[0; 18]); // Method, time, date, and the CRC32 and sizes left out
+        entry.extend_from_slice(&1_u16.to_le_bytes());
+        entry.extend_from_slice(&0_u16.to_le_bytes());
+        entry.extend_from_slice(b"ahello");
+        entry.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE);
+        entry.extend_from_slice(&0x3610a686_u32.to_le_bytes());
+        entry.extend_from_slice(&5_u32.to_le_bytes());
+        entry.extend_from_slice(&5_u32.to_le_bytes());
+        entry
+    }
+
+    #[test]
+    fn test_scan_streamed_entries() {
+        let mut data = b"garbage".to_vec();
+        data.extend(streamed_entry());
+        data.extend(streamed_entry());
+
+        let entries = scan_local_entries(&data);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].start, 7);
+        assert_eq!(entries[0].end, entries[1].start);
+        assert_eq!(entries[1].end, data.len());
+        assert_eq!(entries[0].name, b"a");
+        assert_eq!(
+            (
+                entries[0].crc32,
+                entries[0].compressed_size,
+                entries[0].uncompressed_size
+            ),
+            (0x3610a686, 5, 5)
+        );
+    }
+
+    #[test]
+    fn test_scan_truncated_entry() {
+        let entry = streamed_entry();
+        assert_eq!(scan_local_entries(&entry[..entry.len() - 1]), []);
+    }
+
+    #[test]
+    fn test_default_output_path() {
+        assert_eq!(
+            default_output_path(Path::new("dir/archive.zip")),
+            Path::new("dir/archive.repaired.zip")
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Comparison of freshly written outputs with the inputs they were compressed from, for
+//! `--verify-against-source`.
+//!
+//! Outputs are read back with [`Decoder`], their entries are matched by path to the ones found by
+//! walking the inputs like the archive builders do. Symlinks that aren't followed are left out, as
+//! entries read back don't tell them apart from files. Zip and 7z archives are read in memory.
+
+use std::{
+    collections::BTreeMap,
+    env, fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use fs_err as fs;
+use same_file::Handle;
+
+use crate::{
+    archive::MANIFEST_FILE_NAME,
+    cli::SourceCheck,
+    error::FinalError,
+    extension::CompressionFormat,
+    utils::{self, cd_into_same_dir_as, logger::info, EscapedPathDisplay, FileVisibilityPolicy},
+    Decoder, BUFFER_CAPACITY,
+};
+
+/// Most discrepancies listed in the error, the others are only counted
+const MAX_LISTED: usize = 10;
+
+/// Entry found in the inputs
+enum SourceEntry {
+    Dir,
+    File { path: PathBuf, size: u64 },
+    Symlink,
+}
+
+/// Difference between an output and its inputs, of the entry at `path` in the archive
+enum Discrepancy {
+    Missing(PathBuf),
+    Extra(PathBuf),
+    Type(PathBuf),
+    Size { path: PathBuf, archive: u64, source: u64 },
+    Content(PathBuf),
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(path) => write!(f, "{} is missing from the output", EscapedPathDisplay::new(path)),
+            Self::Extra(path) => write!(f, "{} isn't in the inputs", EscapedPathDisplay::new(path)),
+            Self::Type(path) => write!(
+                f,
+                "{} is a directory on one side and a file on the other",
+                EscapedPathDisplay::new(path)
+            ),
+            Self::Size { path, archive, source } => write!(
+                f,
+                "{} has {archive} bytes in the output but {source} in the inputs",
+                EscapedPathDisplay::new(path)
+            ),
+            Self::Content(path) => write!(f, "{} has a different content", EscapedPathDisplay::new(path)),
+        }
+    }
+}
+
+/// Reads the `outputs` compressed with `formats` back and compares them to the inputs `files`,
+/// stored under `root_name` if given.
+///
+/// Volumes are compared together, as each one holds some of the entries. Fails listing the
+/// discrepancies found, if any.
+pub fn verify_against_source(
+    files: &[PathBuf],
+    formats: &[CompressionFormat],
+    outputs: &[PathBuf],
+    root_name: Option<&str>,
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+    check: SourceCheck,
+) -> crate::Result<()> {
+    let mut discrepancies = vec![];
+    // 7z archives are built ignoring `--name`, with a warning
+    let root_name = root_name.filter(|_| formats.first() != Some(&CompressionFormat::SevenZip));
+
+    if formats.first().is_some_and(CompressionFormat::archive_format) {
+        let output_handles = outputs.iter().map(Handle::from_path).collect::<io::Result<Vec<_>>>()?;
+        let mut source = walk_inputs(
+            files,
+            root_name,
+            &output_handles,
+            file_visibility_policy,
+            follow_symlinks,
+        )?;
+
+        for output in outputs {
+            let mut archive = Decoder::new(fs::File::open(output)?, formats)?.into_archive()?;
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let Some(path) = utils::normalize_entry_path(&entry.path) else {
+                    continue;
+                };
+                if path == Path::new(MANIFEST_FILE_NAME) {
+                    continue;
+                }
+
+                let discrepancy = match source.remove(&path) {
+                    None => Some(Discrepancy::Extra(path)),
+                    Some(SourceEntry::Symlink) => None,
+                    Some(SourceEntry::Dir) => (!entry.is_dir).then_some(Discrepancy::Type(path)),
+                    Some(SourceEntry::File { .. }) if entry.is_dir => Some(Discrepancy::Type(path)),
+                    Some(SourceEntry::File {
+                        path: source_path,
+                        size,
+                    }) => compare_file(path, entry.size, entry, &source_path, size, check)?,
+                };
+                discrepancies.extend(discrepancy);
+            }
+        }
+
+        for (path, entry) in source {
+            if !matches!(entry, SourceEntry::Symlink) {
+                discrepancies.push(Discrepancy::Missing(path));
+            }
+        }
+    } else {
+        // Single file formats hold the content of their only input
+        let [input] = files else {
+            unreachable!("single file formats are checked to have one input");
+        };
+        let name = root_name.map_or_else(|| input.file_name().unwrap_or_default().into(), PathBuf::from);
+        let mut decoder = Decoder::new(fs::File::open(&outputs[0])?, formats)?;
+        let size = io::copy(&mut decoder, &mut io::sink())?;
+        let decoder = Decoder::new(fs::File::open(&outputs[0])?, formats)?;
+        let source_size = input.metadata()?.len();
+        discrepancies.extend(compare_file(name, size, decoder, input, source_size, check)?);
+    }
+
+    if discrepancies.is_empty() {
+        info(format!(
+            "Verified {} against its inputs",
+            EscapedPathDisplay::new(&outputs[0])
+        ));
+        return Ok(());
+    }
+
+    let title = match outputs {
+        [output] => format!("{} doesn't match its inputs", EscapedPathDisplay::new(output)),
+        _ => "The volumes don't match their inputs".to_string(),
+    };
+    let mut error = FinalError::with_title(title);
+    for discrepancy in discrepancies.iter().take(MAX_LISTED) {
+        error = error.detail(discrepancy.to_string());
+    }
+    if discrepancies.len() > MAX_LISTED {
+        error = error.detail(format!("And {} more", discrepancies.len() - MAX_LISTED));
+    }
+    Err(error
+        .hint("The inputs may have changed while they were compressed")
+        .into())
+}
+
+/// Entries of the inputs by the normalized path they're stored under, skipping the outputs
+fn walk_inputs(
+    files: &[PathBuf],
+    root_name: Option<&str>,
+    output_handles: &[Handle],
+    file_visibility_policy: &FileVisibilityPolicy,
+    follow_symlinks: bool,
+) -> crate::Result<BTreeMap<PathBuf, SourceEntry>> {
+    let source_entry = |path: PathBuf| -> io::Result<SourceEntry> {
+        // Broken symlinks are left as they are, even when following them
+        let metadata = match follow_symlinks.then(|| fs::metadata(&path)) {
+            Some(Ok(metadata)) => metadata,
+            _ => fs::symlink_metadata(&path)?,
+        };
+        Ok(if metadata.is_dir() {
+            SourceEntry::Dir
+        } else if metadata.is_symlink() {
+            SourceEntry::Symlink
+        } else {
+            SourceEntry::File {
+                size: metadata.len(),
+                path,
+            }
+        })
+    };
+
+    let mut entries = BTreeMap::new();
+    for file in files {
+        // Unwrap safety:
+        //   paths should be canonicalized by now, and the root directory rejected.
+        let file_name = file.file_name().unwrap();
+        if !file.is_dir() {
+            let name = root_name.map_or_else(|| PathBuf::from(file_name), PathBuf::from);
+            entries.insert(name, source_entry(file.clone())?);
+            continue;
+        }
+
+        let parent = file.parent().unwrap_or(Path::new("/"));
+        let previous_location = cd_into_same_dir_as(file)?;
+        for entry in file_visibility_policy.build_walker(file_name) {
+            let path = entry?.into_path();
+            if matches!(Handle::from_path(&path), Ok(handle) if output_handles.contains(&handle)) {
+                continue;
+            }
+
+            let stored_path = match root_name {
+                Some(name) => utils::replace_root_component(&path, name),
+                None => path.clone(),
+            };
+            let Some(stored_path) = utils::normalize_entry_path(&stored_path) else {
+                continue;
+            };
+            entries.insert(stored_path, source_entry(parent.join(path))?);
+        }
+        env::set_current_dir(previous_location)?;
+    }
+    Ok(entries)
+}
+
+/// Compares the file stored at `path` in the output, of `size` and `content`, with the input at
+/// `source_path`
+fn compare_file(
+    path: PathBuf,
+    size: u64,
+    content: impl Read,
+    source_path: &Path,
+    source_size: u64,
+    check: SourceCheck,
+) -> crate::Result<Option<Discrepancy>> {
+    if size != source_size {
+        return Ok(Some(Discrepancy::Size {
+            path,
+            archive: size,
+            source: source_size,
+        }));
+    }
+    if check == SourceCheck::Content && !same_content(content, fs::File::open(source_path)?)? {
+        return Ok(Some(Discrepancy::Content(path)));
+    }
+    Ok(None)
+}
+
+fn same_content(mut a: impl Read, mut b: impl Read) -> io::Result<bool> {
+    let mut buf_a = vec![0; BUFFER_CAPACITY];
+    let mut buf_b = vec![0; BUFFER_CAPACITY];
+    loop {
+        let read_a = fill(&mut a, &mut buf_a)?;
+        let read_b = fill(&mut b, &mut buf_b)?;
+        if buf_a[..read_a] != buf_b[..read_b] {
+            return Ok(false);
+        }
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Reads until `buf` is full or the end of `reader`, so both sides are compared by the same chunks
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `dir` as a tar archive at `output`, as ouch would store it
+    fn write_tar(dir: &Path, output: &Path) {
+        let mut builder = tar::Builder::new(std::fs::File::create(output).unwrap());
+        builder.append_dir_all(dir.file_name().unwrap(), dir).unwrap();
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_verify_against_tampered_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("same"), "content").unwrap();
+        fs::write(dir.join("changed"), "content").unwrap();
+        fs::write(dir.join("removed"), "content").unwrap();
+        let output = temp_dir.path().join("archive.tar");
+        write_tar(&dir, &output);
+
+        let verify = |check| {
+            verify_against_source(
+                std::slice::from_ref(&dir),
+                &[CompressionFormat::Tar],
+                std::slice::from_ref(&output),
+                None,
+                &FileVisibilityPolicy::new(),
+                false,
+                check,
+            )
+        };
+        verify(SourceCheck::Content).unwrap();
+
+        // Changed as if it happened while the archive was written
+        fs::write(dir.join("changed"), "CONTENT").unwrap();
+        fs::remove_file(dir.join("removed")).unwrap();
+        fs::write(dir.join("added"), "content").unwrap();
+        assert!(verify(SourceCheck::Size).is_err());
+
+        let crate::Error::Custom { reason } = verify(SourceCheck::Content).unwrap_err() else {
+            panic!("expected a discrepancy report");
+        };
+        let report = reason.to_string();
+        assert!(report.contains("dir/added is missing from the output"), "{report}");
+        assert!(report.contains("dir/removed isn't in the inputs"), "{report}");
+        assert!(report.contains("dir/changed has a different content"), "{report}");
+        assert!(!report.contains("dir/same"), "{report}");
+    }
+}
@@ -0,0 +1,327 @@
+//! Decoding of compressed streams and archives held in memory or read from anywhere, without the
+//! CLI and without touching the filesystem.
+//!
+//! ```no_run
+//! use std::{fs::File, io::Read};
+//!
+//! use ouch::Decoder;
+//!
+//! # fn main() -> ouch::Result<()> {
+//! let mut archive = Decoder::sniff(File::open("archive.tar.gz")?)?.into_archive()?;
+//! for entry in archive.entries()? {
+//!     let mut entry = entry?;
+//!     let mut content = vec![];
+//!     entry.read_to_end(&mut content)?;
+//!     println!("{} ({} bytes)", entry.path.display(), content.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    io::{self, Read},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    archive,
+    commands::decompress::chain_reader_decoder,
+    extension::CompressionFormat::{self, *},
+    utils::{infer_extension, SNIFFED_LEN},
+    Error, Result,
+};
+
+/// Decompressed stream of a reader compressed with one or more formats, like a `.tar.gz` file.
+pub struct Decoder {
+    reader: Box<dyn Read>,
+    /// In the order of the extensions, so the archive format comes first
+    formats: Vec<CompressionFormat>,
+}
+
+impl Decoder {
+    /// Decodes `reader`, compressed with `formats` in the order of the extensions of its name,
+    /// like `[Tar, Gzip]` for a `.tar.gz` file.
+    pub fn new(reader: impl Read + 'static, formats: &[CompressionFormat]) -> Result<Self> {
+        if formats.iter().skip(1).any(CompressionFormat::archive_format) {
+            return Err(Error::UnsupportedFormat {
+                reason: "Only the first format can be an archive format".to_owned(),
+            });
+        }
+
+        let mut reader: Box<dyn Read> = Box::new(reader);
+        for format in formats.iter().rev() {
+            reader = chain_reader_decoder(format, reader)?;
+        }
+        Ok(Self {
+            reader,
+            formats: formats.to_vec(),
+        })
+    }
+
+    /// Decodes `reader`, detecting its formats by their magic numbers.
+    ///
+    /// Formats are peeled off until the stream is an archive or doesn't start like a known format
+    /// anymore, data with no known format is read as it is.
+    pub fn sniff(reader: impl Read + 'static) -> Result<Self> {
+        let mut reader: Box<dyn Read> = Box::new(reader);
+        let mut formats = vec![];
+        loop {
+            let mut start = Vec::with_capacity(SNIFFED_LEN);
+            reader.by_ref().take(SNIFFED_LEN as u64).read_to_end(&mut start)?;
+            let format = infer_extension(&start).map(|extension| extension.compression_formats[0]);
+            reader = Box::new(io::Cursor::new(start).chain(reader));
+
+            match format {
+                Some(format) if format.archive_format() => {
+                    formats.insert(0, format);
+                    break;
+                }
+                Some(format) => {
+                    reader = chain_reader_decoder(&format, reader)?;
+                    formats.insert(0, format);
+                }
+                None => break,
+            }
+        }
+        Ok(Self { reader, formats })
+    }
+
+    /// Formats the data is compressed with, in the order of extensions
+    pub fn formats(&self) -> &[CompressionFormat] {
+        &self.formats
+    }
+
+    /// Format of the archive the decompressed data is, if any
+    pub fn archive_format(&self) -> Option<CompressionFormat> {
+        self.formats.first().copied().filter(CompressionFormat::archive_format)
+    }
+
+    /// Reader of the decompressed data, which are the bytes of the archive for archive formats.
+    pub fn into_reader(self) -> Box<dyn Read> {
+        self.reader
+    }
+
+    /// Archive of the decompressed data, whose entries can be read.
+    ///
+    /// Zip and 7z archives are loaded in memory, as they can only be read by seeking. Rar archives
+    /// can only be read from files.
+    pub fn into_archive(self) -> Result<Archive> {
+        let format = self.archive_format();
+        let mut reader = self.reader;
+        let inner = match format {
+            Some(Tar) => ArchiveInner::Tar(tar::Archive::new(reader)),
+            Some(Zip) => {
+                let mut data = vec![];
+                reader.read_to_end(&mut data)?;
+                ArchiveInner::Zip(zip::ZipArchive::new(io::Cursor::new(data))?)
+            }
+            Some(SevenZip) => {
+                let mut data = vec![];
+                reader.read_to_end(&mut data)?;
+                ArchiveInner::SevenZip(io::Cursor::new(data))
+            }
+            Some(Rar) => {
+                return Err(Error::UnsupportedFormat {
+                    reason: "Rar archives can only be read from files".to_owned(),
+                })
+            }
+            _ => {
+                return Err(Error::UnsupportedFormat {
+                    reason: "The data isn't an archive".to_owned(),
+                })
+            }
+        };
+        Ok(Archive(inner))
+    }
+}
+
+impl Read for Decoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Archive decoded by a [`Decoder`].
+pub struct Archive(ArchiveInner);
+
+enum ArchiveInner {
+    Tar(tar::Archive<Box<dyn Read>>),
+    Zip(zip::ZipArchive<io::Cursor<Vec<u8>>>),
+    SevenZip(io::Cursor<Vec<u8>>),
+}
+
+impl Archive {
+    /// Entries of the archive, which can only be listed once.
+    ///
+    /// Tar entries are read from the stream as they come, the ones of other formats from memory.
+    pub fn entries(&mut self) -> Result<Box<dyn Iterator<Item = Result<Entry<'_>>> + '_>> {
+        match &mut self.0 {
+            ArchiveInner::Tar(archive) => {
+                let entries = archive.entries()?.filter_map(|entry| {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(err) => return Some(Err(err.into())),
+                    };
+                    // Records of PAX global headers apply to the whole archive, they aren't files
+                    if entry.header().entry_type() == tar::EntryType::XGlobalHeader {
+                        return None;
+                    }
+                    Some(tar_entry(entry))
+                });
+                Ok(Box::new(entries))
+            }
+            ArchiveInner::Zip(archive) => {
+                let mut idx = 0;
+                let entries = std::iter::from_fn(move || {
+                    while idx < archive.len() {
+                        idx += 1;
+                        match zip_entry(archive, idx - 1).transpose() {
+                            Some(entry) => return Some(entry),
+                            // Entries of unsupported methods are skipped with a warning
+                            None => continue,
+                        }
+                    }
+                    None
+                });
+                Ok(Box::new(entries))
+            }
+            ArchiveInner::SevenZip(data) => {
+                let mut entries = vec![];
+                sevenz_rust2::decompress_with_extract_fn(data, ".", |entry, reader, _| {
+                    let mut content = vec![];
+                    reader.read_to_end(&mut content)?;
+                    entries.push(Ok(Entry {
+                        path: entry.name().into(),
+                        is_dir: entry.is_directory(),
+                        size: entry.size(),
+                        mtime: entry.has_last_modified_date.then(|| entry.last_modified_date().into()),
+                        content: Box::new(io::Cursor::new(content)),
+                    }));
+                    Ok(true)
+                })?;
+                Ok(Box::new(entries.into_iter()))
+            }
+        }
+    }
+}
+
+fn tar_entry<'a>(entry: tar::Entry<'a, Box<dyn Read>>) -> Result<Entry<'a>> {
+    let header = entry.header();
+    Ok(Entry {
+        path: entry.path()?.into_owned(),
+        is_dir: header.entry_type().is_dir(),
+        size: entry.size(),
+        mtime: header
+            .mtime()
+            .ok()
+            .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime)),
+        content: Box::new(entry),
+    })
+}
+
+/// Entry numbered `idx` of `archive`, with its content read in memory as it borrows the archive
+fn zip_entry(archive: &mut zip::ZipArchive<io::Cursor<Vec<u8>>>, idx: usize) -> Result<Option<Entry<'static>>> {
+    let Some(mut file) = archive::zip::open_entry(archive, idx, None)? else {
+        return Ok(None);
+    };
+    let mut content = vec![];
+    archive::zip::entry_content(&mut file, true)?.read_to_end(&mut content)?;
+    Ok(Some(Entry {
+        path: file.mangled_name(),
+        is_dir: file.is_dir(),
+        size: file.size(),
+        mtime: file.last_modified().to_time().ok().map(SystemTime::from),
+        content: Box::new(io::Cursor::new(content)),
+    }))
+}
+
+/// Entry of an [`Archive`], reading it yields its content.
+pub struct Entry<'a> {
+    /// The path stored in the archive, entries may point outside of where they would be extracted
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// The uncompressed size
+    pub size: u64,
+    /// The last modification time, if stored in the archive
+    pub mtime: Option<SystemTime>,
+    content: Box<dyn Read + 'a>,
+}
+
+impl Read for Entry<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.content.read(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn tar_gz(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(vec![], Default::default()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(1_000_000_000);
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn test_entries_of_tar_gz_in_memory() {
+        let data = tar_gz(&[("a.txt", b"hello"), ("dir/b.txt", b"world")]);
+
+        for decoder in [
+            Decoder::sniff(io::Cursor::new(data.clone())).unwrap(),
+            Decoder::new(io::Cursor::new(data), &[Tar, Gzip]).unwrap(),
+        ] {
+            assert_eq!(decoder.formats(), [Tar, Gzip]);
+            assert_eq!(decoder.archive_format(), Some(Tar));
+
+            let mut archive = decoder.into_archive().unwrap();
+            let entries: Vec<_> = archive
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content).unwrap();
+                    (entry.path, entry.size, entry.mtime, content)
+                })
+                .collect();
+
+            let mtime = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000));
+            assert_eq!(
+                entries,
+                [
+                    ("a.txt".into(), 5, mtime, "hello".to_owned()),
+                    ("dir/b.txt".into(), 5, mtime, "world".to_owned()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_decoded_stream() {
+        let mut encoder = zstd::stream::Encoder::new(vec![], 0).unwrap();
+        encoder.write_all(b"plain text").unwrap();
+        let data = encoder.finish().unwrap();
+
+        let mut decoder = Decoder::sniff(io::Cursor::new(data)).unwrap();
+        assert_eq!(decoder.formats(), [Zstd]);
+        assert_eq!(decoder.archive_format(), None);
+        let mut text = String::new();
+        decoder.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "plain text");
+
+        assert!(Decoder::sniff(io::Cursor::new(b"plain text".to_vec()))
+            .unwrap()
+            .into_archive()
+            .is_err());
+    }
+}
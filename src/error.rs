@@ -7,12 +7,13 @@ use std::{
     ffi::OsString,
     fmt::{self, Display},
     io,
+    path::PathBuf,
 };
 
 use crate::{
     accessible::is_running_in_accessible_mode,
-    extension::{PRETTY_SUPPORTED_ALIASES, PRETTY_SUPPORTED_EXTENSIONS},
-    utils::os_str_to_str,
+    extension::{Extension, PRETTY_SUPPORTED_ALIASES, PRETTY_SUPPORTED_EXTENSIONS},
+    utils::{os_str_to_str, pretty_format_list_of_paths, EscapedPathDisplay},
 };
 
 /// All errors that can be generated by `ouch`
@@ -47,6 +48,26 @@ pub enum Error {
     UnsupportedFormat { reason: String },
     /// Invalid password provided
     InvalidPassword { reason: String },
+    /// Encrypted archive read without `--password`, `format` is the name of its format
+    PasswordRequired { format: &'static str },
+    /// Inputs matching no known format, neither by extension nor by contents
+    UnknownFormat { paths: Vec<PathBuf> },
+    /// Formats chained in a way that can't work, like "tar.tar" or "gz.tar". `name` is what's left
+    /// of the file name without them, if they come from one.
+    UnsupportedFormatChain {
+        formats: Vec<Extension>,
+        name: Option<PathBuf>,
+    },
+    /// Output compressed worse than `--min-ratio`, it was removed
+    PoorCompressionRatio { path: PathBuf, ratio: f64, min_ratio: f64 },
+    /// Input whose digest doesn't match the one of its sibling checksum file, like "file.sha256"
+    ChecksumMismatch {
+        path: PathBuf,
+        checksum_file: PathBuf,
+        algorithm: &'static str,
+        expected: String,
+        actual: String,
+    },
 }
 
 /// Alias to std's Result with ouch's Error
@@ -134,7 +155,7 @@ impl FinalError {
     /// Adds all supported formats as hints.
     ///
     /// This is what it looks like:
-    /// ```
+    /// ```text
     /// hint: Supported extensions are: tar, zip, bz, bz2, gz, lz4, xz, lzma, lz, sz, zst
     /// hint: Supported aliases are: tgz, tbz, tlz4, txz, tlzma, tsz, tzst, tlz
     /// ```
@@ -176,6 +197,121 @@ impl From<Error> for FinalError {
                 FinalError::with_title("Recognised but unsupported format").detail(reason.clone())
             }
             Error::InvalidPassword { reason } => FinalError::with_title("Invalid password").detail(reason.clone()),
+            Error::PasswordRequired { format } => FinalError::with_title(format!("This {format} archive is encrypted"))
+                .detail("A password is needed to read it")
+                .hint("Pass it with '--password <PASSWORD>'"),
+            Error::UnknownFormat { paths } => {
+                let title = match paths.as_slice() {
+                    [_] => "This doesn't look like a supported archive",
+                    _ => "These don't look like supported archives",
+                };
+                let mut error = FinalError::with_title(title);
+
+                let (unsupported_extensions, missing_extensions): (Vec<&PathBuf>, Vec<&PathBuf>) =
+                    paths.iter().partition(|path| path.extension().is_some());
+
+                if !unsupported_extensions.is_empty() {
+                    error = error.detail(format!(
+                        "Files with unsupported extensions: {}",
+                        pretty_format_list_of_paths(&unsupported_extensions)
+                    ));
+                }
+                if !missing_extensions.is_empty() {
+                    error = error.detail(format!(
+                        "Files with missing extensions: {}",
+                        pretty_format_list_of_paths(&missing_extensions)
+                    ));
+                }
+
+                error = error
+                    .detail("Neither the file extension nor the contents match a known format")
+                    .hint_all_supported_formats();
+
+                // If there's exactly one file, give a suggestion to use `--format`
+                if let [path] = paths.as_slice() {
+                    error = error
+                        .hint("")
+                        .hint("If you know its format, you can pass it to the '--format' flag:")
+                        .hint(format!(
+                            "  ouch decompress {} --format tar.gz",
+                            EscapedPathDisplay::new(path),
+                        ));
+                }
+
+                error
+            }
+            Error::UnsupportedFormatChain { formats, name } => {
+                let join =
+                    |formats: &[&Extension]| formats.iter().map(ToString::to_string).collect::<Vec<_>>().join(".");
+                let archives: Vec<_> = formats.iter().filter(|format| format.is_archive()).collect();
+
+                let error = FinalError::with_title(format!(
+                    "Unsupported chain of formats '.{}'",
+                    join(&formats.iter().collect::<Vec<_>>())
+                ));
+                let error = match archives.as_slice() {
+                    [first, second, ..] => error.detail(format!(
+                        "'.{first}' and '.{second}' are both archive formats, a chain can only have one archive"
+                    )),
+                    [archive] => error.detail(format!(
+                        "The archive format '.{archive}' must come first, the formats after it compress the archive"
+                    )),
+                    [] => error,
+                };
+
+                // The first archive, compressed by the other formats in the same order
+                let corrected: Vec<_> = archives
+                    .first()
+                    .copied()
+                    .into_iter()
+                    .chain(formats.iter().filter(|format| !format.is_archive()))
+                    .collect();
+                let corrected = join(&corrected);
+                match name {
+                    // Nested archives are named like this, they can still be read with `--format`
+                    Some(name) => error
+                        .hint(format!(
+                            "Use '.{corrected}' instead, like in '{}.{corrected}'",
+                            EscapedPathDisplay::new(&name)
+                        ))
+                        .hint("Otherwise, you can use `--format` to specify what format to use, examples:")
+                        .hint("  ouch compress file.zip.zip file --format zip")
+                        .hint("  ouch decompress file --format zst")
+                        .hint("  ouch list archive --format tar.gz"),
+                    None => error.hint(format!("Use '--format {corrected}' instead")),
+                }
+            }
+            Error::PoorCompressionRatio { path, ratio, min_ratio } => FinalError::with_title(format!(
+                "Compression ratio of {} below the minimum",
+                EscapedPathDisplay::new(&path)
+            ))
+            .detail(format!("The ratio is {ratio:.2}, below the minimum of {min_ratio}"))
+            .detail("The output was removed, the data is barely compressible")
+            .hint("Use --min-ratio-fallback store to store the data without compression instead"),
+            Error::ChecksumMismatch {
+                path,
+                checksum_file,
+                algorithm,
+                expected,
+                actual,
+            } => FinalError::with_title(format!("Checksum mismatch for {}", EscapedPathDisplay::new(&path)))
+                .detail(format!(
+                    "Expected the {algorithm} digest {expected}, from {}",
+                    EscapedPathDisplay::new(&checksum_file)
+                ))
+                .detail(format!("Found {actual}"))
+                .hint("The file may be corrupted or incomplete, try to download it again")
+                .hint("Use --no-verify to decompress it anyway"),
+        }
+    }
+}
+
+impl Error {
+    /// Status code `ouch` exits with on this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::PoorCompressionRatio { .. } => crate::EXIT_POOR_RATIO,
+            _ => crate::EXIT_FAILURE,
         }
     }
 }
@@ -222,6 +358,7 @@ impl From<zip::result::ZipError> for Error {
             ZipError::FileNotFound => Self::Custom {
                 reason: FinalError::with_title("Unexpected error in zip archive").detail("File not found"),
             },
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED) => Self::PasswordRequired { format: "zip" },
             ZipError::UnsupportedArchive(filename) => Self::UnsupportedZipArchive(filename),
         }
     }
@@ -230,6 +367,9 @@ impl From<zip::result::ZipError> for Error {
 #[cfg(feature = "unrar")]
 impl From<unrar::error::UnrarError> for Error {
     fn from(err: unrar::error::UnrarError) -> Self {
+        if err.code == unrar::error::Code::MissingPassword {
+            return Self::PasswordRequired { format: "rar" };
+        }
         Self::Custom {
             reason: FinalError::with_title("Unexpected error in rar archive").detail(format!("{:?}", err.code)),
         }
@@ -238,6 +378,9 @@ impl From<unrar::error::UnrarError> for Error {
 
 impl From<sevenz_rust2::Error> for Error {
     fn from(err: sevenz_rust2::Error) -> Self {
+        if let sevenz_rust2::Error::PasswordRequired = err {
+            return Self::PasswordRequired { format: "7z" };
+        }
         Self::SevenzipError {
             reason: err.to_string(),
         }
@@ -257,3 +400,15 @@ impl From<FinalError> for Error {
         Self::Custom { reason: err }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_final_error_is_uncolored_without_cli_initialization() {
+        let error = FinalError::with_title("Title").detail("Detail").hint("Hint");
+
+        assert_eq!(error.to_string(), "[ERROR] Title\n - Detail\n\nhint: Hint");
+    }
+}
@@ -6,7 +6,8 @@ use bstr::ByteSlice;
 use CompressionFormat::*;
 
 use crate::{
-    error::{Error, FinalError, Result},
+    archive::volumes::VolumeName,
+    error::{Error, Result},
     utils::logger::warning,
 };
 
@@ -184,48 +185,57 @@ pub fn parse_format_flag(input: &OsStr) -> crate::Result<Vec<Extension>> {
             reason: "Parsing got an empty list of extensions.".to_string(),
         });
     }
+    check_format_chain(&extensions, None)?;
 
     Ok(extensions)
 }
 
+/// Check that the only archive format of `formats` comes first, as the other formats compress it.
+/// `name` is the file name the extensions were taken from, if any.
+fn check_format_chain(formats: &[Extension], name: Option<&Path>) -> crate::Result<()> {
+    if formats.iter().skip(1).any(Extension::is_archive) {
+        return Err(Error::UnsupportedFormatChain {
+            formats: formats.to_vec(),
+            name: name.map(Path::to_owned),
+        });
+    }
+    Ok(())
+}
+
 /// Extracts extensions from a path.
 ///
 /// Returns both the remaining path and the list of extension objects.
 pub fn separate_known_extensions_from_name(path: &Path) -> Result<(&Path, Vec<Extension>)> {
     let mut extensions = vec![];
 
-    let Some(mut name) = path.file_name().and_then(<[u8] as ByteSlice>::from_os_str) else {
+    let Some(file_name) = path.file_name().and_then(<[u8] as ByteSlice>::from_os_str) else {
         return Ok((path, extensions));
     };
+    let mut name = file_name;
+
+    // Volumes of split archives are named after the archive, like "archive.7z.001"
+    if let Some(volume) = VolumeName::sevenz(name) {
+        name = &volume.prefix[..volume.prefix.len() - 1];
+    }
 
     while let Some((new_name, extension)) = split_extension_at_end(name) {
         name = new_name;
         extensions.insert(0, extension);
-        if extensions[0].is_archive() {
-            if let Some((_, misplaced_extension)) = split_extension_at_end(name) {
-                let mut error = FinalError::with_title("File extensions are invalid for operation").detail(format!(
-                    "The archive extension '.{}' can only be placed at the start of the extension list",
-                    extensions[0].display_text,
-                ));
-
-                if misplaced_extension.compression_formats == extensions[0].compression_formats {
-                    error = error.detail(format!(
-                        "File: '{path:?}' contains '.{}' and '.{}'",
-                        misplaced_extension.display_text, extensions[0].display_text,
-                    ));
-                }
-
-                return Err(error
-                    .hint("You can use `--format` to specify what format to use, examples:")
-                    .hint("  ouch compress file.zip.zip file --format zip")
-                    .hint("  ouch decompress file --format zst")
-                    .hint("  ouch list archive --format tar.gz")
-                    .into());
-            }
-            break;
-        }
     }
 
+    // Or like "archive.part1.rar"
+    if let (
+        Some(volume),
+        [Extension {
+            compression_formats: [Rar],
+            ..
+        }],
+    ) = (VolumeName::rar(file_name), &*extensions)
+    {
+        name = &volume.prefix[..volume.prefix.len() - ".part".len()];
+    }
+    check_format_chain(&extensions, Some(name.to_path().unwrap()))?;
+
     if let Ok(name) = name.to_str() {
         let file_stem = name.trim_matches('.');
         if SUPPORTED_EXTENSIONS.contains(&file_stem) || SUPPORTED_ALIASES.contains(&file_stem) {
@@ -336,6 +346,14 @@ mod tests {
             separate_known_extensions_from_name(".tar.gz".as_ref()).unwrap(),
             (".tar".as_ref(), vec![Extension::new(&[Gzip], "gz")])
         );
+        assert_eq!(
+            separate_known_extensions_from_name("file.7z.001".as_ref()).unwrap(),
+            ("file".as_ref(), vec![Extension::new(&[SevenZip], "7z")])
+        );
+        assert_eq!(
+            separate_known_extensions_from_name("file.part1.rar".as_ref()).unwrap(),
+            ("file".as_ref(), vec![Extension::new(&[Rar], "rar")])
+        );
     }
 
     #[test]
@@ -395,4 +413,41 @@ mod tests {
         assert!(separate_known_extensions_from_name("file.tar.zip".as_ref()).is_err());
         assert!(separate_known_extensions_from_name("file.7z.zst.zip.lz4".as_ref()).is_err());
     }
+
+    #[test]
+    fn test_unsupported_format_chains() {
+        let cases = [
+            (
+                "file.tar.tar",
+                "'.tar' and '.tar' are both archive formats",
+                "'file.tar'",
+            ),
+            (
+                "file.7z.zst.zip.lz4",
+                "'.7z' and '.zip' are both archive formats",
+                "'file.7z.zst.lz4'",
+            ),
+            (
+                "file.gz.tar",
+                "The archive format '.tar' must come first",
+                "'file.tar.gz'",
+            ),
+            (
+                "file.xz.tgz",
+                "The archive format '.tgz' must come first",
+                "'file.tgz.xz'",
+            ),
+        ];
+        for (path, detail, example) in cases {
+            let error = separate_known_extensions_from_name(path.as_ref()).unwrap_err();
+            assert!(matches!(error, Error::UnsupportedFormatChain { .. }), "{path}");
+            let message = error.to_string();
+            assert!(message.contains(detail), "{message}");
+            assert!(message.contains(example), "{message}");
+        }
+
+        let error = parse_format_flag(OsStr::new("gz.zip")).unwrap_err();
+        assert!(matches!(error, Error::UnsupportedFormatChain { name: None, .. }));
+        assert!(error.to_string().contains("Use '--format zip.gz' instead"));
+    }
 }
@@ -0,0 +1,38 @@
+//! Library side of `ouch`, the binary in `main.rs` is a thin wrapper around [`commands::run`].
+//!
+//! The CLI is the main interface of this crate, so expect this API to follow its needs. The
+//! exception is [`Decoder`], meant to decode data from anywhere in other programs.
+
+pub mod accessible;
+pub mod archive;
+pub mod check;
+pub mod cli;
+pub mod commands;
+pub mod decoder;
+pub mod error;
+pub mod extension;
+pub mod list;
+pub mod utils;
+
+use std::{env, path::PathBuf};
+
+pub use cli::CliArgs;
+use once_cell::sync::Lazy;
+
+pub use self::{
+    decoder::Decoder,
+    error::{Error, Result},
+    utils::{QuestionAction, QuestionPolicy},
+};
+
+// Used in BufReader and BufWriter to perform less syscalls
+const BUFFER_CAPACITY: usize = 1024 * 32;
+
+/// Current directory or empty directory
+static CURRENT_DIRECTORY: Lazy<PathBuf> = Lazy::new(|| env::current_dir().unwrap_or_default());
+
+/// The status code returned from `ouch` on error
+pub const EXIT_FAILURE: i32 = libc::EXIT_FAILURE;
+
+/// The status code returned when the output was compressed worse than `--min-ratio`
+pub const EXIT_POOR_RATIO: i32 = 3;
@@ -3,50 +3,141 @@
 use std::{
     io::{stdout, BufWriter, Write},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
+use linked_hash_map::LinkedHashMap;
+
 use self::tree::Tree;
-use crate::{accessible::is_running_in_accessible_mode, utils::EscapedPathDisplay};
+use crate::{
+    accessible::is_running_in_accessible_mode,
+    utils::{normalize_entry_path, Bytes, DateFormat, EscapedPathDisplay},
+};
 
 /// Options controlling how archive contents should be listed
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Default)]
 pub struct ListOptions {
     /// Whether to show a tree view
     pub tree: bool,
+    /// Whether to show the size and modification time of each entry
+    pub long: bool,
+    /// How modification times are displayed in the long listing
+    pub date_format: DateFormat,
+    /// Whether to report the paths of entries found more than once
+    pub list_duplicates: bool,
+    /// Whether entries are read from and written to the sidecar cache of the archive
+    pub cache: bool,
+    /// Number of entries skipped before the listed ones
+    pub offset: usize,
+    /// Maximum number of entries listed, the following ones aren't read
+    pub limit: Option<usize>,
 }
 
 /// Represents a single file in an archive, used in `list::list_files()`
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FileInArchive {
     /// The file path
     pub path: PathBuf,
 
     /// Whether this file is a directory
     pub is_dir: bool,
+
+    /// The uncompressed size of the file
+    pub size: u64,
+
+    /// The last modification time, if stored in the archive
+    pub mtime: Option<SystemTime>,
+}
+
+/// Item found while listing an archive, used in `list::list_files()`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum ListEntry {
+    File(FileInArchive),
+    /// Metadata about the whole archive, like the records of a tar PAX global header
+    Metadata {
+        key: String,
+        value: String,
+    },
 }
 
 /// Actually print the files
 /// Returns an Error, if one of the files can't be read
 pub fn list_files(
     archive: &Path,
-    files: impl IntoIterator<Item = crate::Result<FileInArchive>>,
-    list_options: ListOptions,
+    entries: impl IntoIterator<Item = crate::Result<ListEntry>>,
+    list_options: &ListOptions,
 ) -> crate::Result<()> {
     let mut out = BufWriter::new(stdout().lock());
     let _ = writeln!(out, "Archive: {}", EscapedPathDisplay::new(archive));
 
+    let mut tree = Tree::default();
+    // Number of entries of each path, compared as they would be extracted
+    let mut path_counts = LinkedHashMap::<PathBuf, usize>::new();
+    // Only files are paged through, metadata is about the whole archive
+    let end = list_options
+        .limit
+        .map(|limit| list_options.offset.saturating_add(limit));
+    let mut files_read = 0;
+    for entry in entries {
+        if end.is_some_and(|end| files_read >= end) {
+            break;
+        }
+        let file = match entry? {
+            ListEntry::File(file) => file,
+            ListEntry::Metadata { key, value } => {
+                let _ = writeln!(out, "Archive {}: {}", key.escape_debug(), value.escape_debug());
+                continue;
+            }
+        };
+        files_read += 1;
+        if files_read <= list_options.offset {
+            continue;
+        }
+
+        if list_options.list_duplicates {
+            let path = normalize_entry_path(&file.path).unwrap_or_else(|| file.path.clone());
+            *path_counts.entry(path).or_insert(0) += 1;
+        }
+
+        if list_options.tree {
+            tree.insert(file);
+        } else {
+            if list_options.long {
+                print_details(&mut out, &file, &list_options.date_format);
+            }
+            print_entry(&mut out, EscapedPathDisplay::new(&file.path), file.is_dir);
+        }
+    }
+
     if list_options.tree {
-        let tree = files.into_iter().collect::<crate::Result<Tree>>()?;
         tree.print(&mut out);
-    } else {
-        for file in files {
-            let FileInArchive { path, is_dir } = file?;
-            print_entry(&mut out, EscapedPathDisplay::new(&path), is_dir);
-        }
+    }
+    if list_options.list_duplicates {
+        print_duplicates(&mut out, &path_counts);
     }
     Ok(())
 }
 
+/// Print the paths found in more than one entry, in the order they first appeared
+fn print_duplicates(out: &mut impl Write, path_counts: &LinkedHashMap<PathBuf, usize>) {
+    let mut duplicates = path_counts.iter().filter(|(_, &count)| count > 1).peekable();
+    if duplicates.peek().is_none() {
+        let _ = writeln!(out, "No duplicate paths");
+        return;
+    }
+
+    let _ = writeln!(out, "Duplicate paths:");
+    for (path, count) in duplicates {
+        let _ = writeln!(out, "{} ({count} entries)", EscapedPathDisplay::new(path));
+    }
+}
+
+/// Print the size and modification time columns that precede an entry in the long listing
+fn print_details(out: &mut impl Write, file: &FileInArchive, date_format: &DateFormat) {
+    let mtime = file.mtime.map(|mtime| date_format.format(mtime)).unwrap_or_default();
+    let _ = write!(out, "{}  {mtime}  ", Bytes::new(file.size));
+}
+
 /// Print an entry and highlight directories, either by coloring them
 /// if that's supported or by adding a trailing /
 fn print_entry(out: &mut impl Write, name: impl std::fmt::Display, is_dir: bool) {
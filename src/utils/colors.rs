@@ -2,19 +2,51 @@
 
 #![allow(dead_code)]
 
-use std::env;
+use std::{env, ops::Deref};
 
-use once_cell::sync::Lazy;
+use once_cell::sync::OnceCell;
 
-static DISABLE_COLORED_TEXT: Lazy<bool> = Lazy::new(|| {
-    env::var_os("NO_COLOR").is_some() || atty::isnt(atty::Stream::Stdout) || atty::isnt(atty::Stream::Stderr)
-});
+/// Global flag for colored output, unset until the CLI initializes it.
+///
+/// When used as a library, ouch doesn't know where its text ends up, so it stays uncolored.
+static COLORED_TEXT: OnceCell<bool> = OnceCell::new();
+
+/// Check if the terminal should receive colored text, `NO_COLOR` disables it.
+pub fn is_colored_text_supported() -> bool {
+    env::var_os("NO_COLOR").is_none() && atty::is(atty::Stream::Stdout) && atty::is(atty::Stream::Stderr)
+}
+
+/// Set the value of the global colored output flag, defaults to uncolored if never set.
+pub fn set_colored_text(value: bool) {
+    if COLORED_TEXT.get().is_none() {
+        COLORED_TEXT.set(value).unwrap();
+    }
+}
+
+fn is_colored_text_enabled() -> bool {
+    COLORED_TEXT.get().copied().unwrap_or(false)
+}
+
+/// An ANSI escape sequence, dereferences to an empty string when colors are disabled
+pub struct Color(&'static str);
+
+impl Deref for Color {
+    type Target = &'static str;
+
+    fn deref(&self) -> &Self::Target {
+        if is_colored_text_enabled() {
+            &self.0
+        } else {
+            &""
+        }
+    }
+}
 
 macro_rules! color {
     ($name:ident = $value:literal) => {
         #[cfg(target_family = "unix")]
         /// Inserts color onto text based on configuration
-        pub static $name: Lazy<&str> = Lazy::new(|| if *DISABLE_COLORED_TEXT { "" } else { $value });
+        pub static $name: Color = Color($value);
         #[cfg(not(target_family = "unix"))]
         pub static $name: &&str = &"";
     };
@@ -0,0 +1,162 @@
+//! Extraction confined to the destination directory, with `--confined`.
+//!
+//! The destination is opened once as a [`cap_std`] directory, then every entry is created relative
+//! to it. Paths are resolved inside of it, symlinks included, and the ones leading outside are
+//! refused instead of followed, so neither the entries of an archive nor another process swapping a
+//! directory for a symlink during the extraction can make a write land outside of it.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use cap_std::{ambient_authority, fs::Dir};
+
+use super::{fs::rename_or_increment_filename, user_wants_to_overwrite, EscapedPathDisplay, FileConflitOperation};
+use crate::{QuestionAction, QuestionPolicy};
+
+/// Destination directory, entries are created relative to it.
+pub struct ConfinedDir {
+    root: Dir,
+}
+
+impl ConfinedDir {
+    /// Opens the destination at `path`, which is trusted and may itself go through symlinks.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let root = Dir::open_ambient_dir(path, ambient_authority())?;
+        Ok(Self { root })
+    }
+
+    /// Creates the directory at the relative `path` along with its missing parents, and returns it
+    /// opened so its metadata can be set.
+    pub fn create_dir_all(&self, path: &Path) -> io::Result<File> {
+        self.create_dirs(path, path)?;
+        // Opened as a file, the handles of `Dir::open_dir` can't have their metadata set
+        self.root
+            .open(path)
+            .map(cap_std::fs::File::into_std)
+            .map_err(|err| refuse_escape(err, path))
+    }
+
+    /// Creates or truncates the regular file at the relative `path`, its missing parents are
+    /// created.
+    pub fn create_file(&self, path: &Path) -> io::Result<File> {
+        self.create_parents(path)?;
+        self.root
+            .create(path)
+            .map(cap_std::fs::File::into_std)
+            .map_err(|err| refuse_escape(err, path))
+    }
+
+    /// Creates a symlink at the relative `path` pointing to `target`, its missing parents are
+    /// created.
+    ///
+    /// The target isn't checked, as the symlinks of the destination are only followed inside of it.
+    pub fn symlink(&self, target: &Path, path: &Path) -> io::Result<()> {
+        self.create_parents(path)?;
+        self.root.symlink(target, path).map_err(|err| refuse_escape(err, path))
+    }
+
+    /// Creates a hard link at the relative `path` to the file at the relative `target`, its
+    /// missing parents are created.
+    pub fn hard_link(&self, target: &Path, path: &Path) -> io::Result<()> {
+        self.create_parents(path)?;
+        self.root
+            .hard_link(target, &self.root, path)
+            .map_err(|err| refuse_escape(err, path))
+    }
+
+    /// Whether something is at the relative `path`, without following a symlink there
+    pub fn exists(&self, path: &Path) -> bool {
+        self.root.symlink_metadata(path).is_ok()
+    }
+
+    /// Removes the file, symlink or directory at the relative `path`
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let result = match self.root.symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() => self.root.remove_dir_all(path),
+            Ok(_) => self.root.remove_file(path),
+            Err(err) => Err(err),
+        };
+        result.map_err(|err| refuse_escape(err, path))
+    }
+
+    /// Handles something already at the relative `path` like [`super::resolve_path_conflict`],
+    /// asking about it as `output_folder.join(path)` according to `question_policy`. Returns the
+    /// relative path to create the entry at, or `None` to skip it.
+    pub fn resolve_conflict(
+        &self,
+        output_folder: &Path,
+        path: &Path,
+        question_policy: QuestionPolicy,
+    ) -> crate::Result<Option<PathBuf>> {
+        if !self.exists(path) {
+            return Ok(Some(path.to_path_buf()));
+        }
+
+        match user_wants_to_overwrite(
+            &output_folder.join(path),
+            question_policy,
+            QuestionAction::Decompression,
+        )? {
+            FileConflitOperation::Cancel => Ok(None),
+            FileConflitOperation::Overwrite => {
+                self.remove(path)?;
+                Ok(Some(path.to_path_buf()))
+            }
+            FileConflitOperation::Rename => {
+                let mut renamed_path = rename_or_increment_filename(path);
+                while self.exists(&renamed_path) {
+                    renamed_path = rename_or_increment_filename(&renamed_path);
+                }
+                Ok(Some(renamed_path))
+            }
+            FileConflitOperation::Merge => Ok(Some(path.to_path_buf())),
+        }
+    }
+
+    fn create_parents(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) => self.create_dirs(parent, path),
+            None => Ok(()),
+        }
+    }
+
+    /// Creates the directory at the relative `dir` along with its missing parents, following the
+    /// symlinks on the way as long as they stay inside of the destination. `path` is only used for
+    /// errors.
+    fn create_dirs(&self, dir: &Path, path: &Path) -> io::Result<()> {
+        let mut ancestor = PathBuf::new();
+        for component in dir.components() {
+            ancestor.push(component);
+            let result = match self.root.metadata(&ancestor) {
+                Ok(metadata) if metadata.is_dir() => Ok(()),
+                Ok(_) => Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} isn't a directory", EscapedPathDisplay::new(&ancestor)),
+                )),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => self.root.create_dir(&ancestor),
+                Err(err) => Err(err),
+            };
+            result.map_err(|err| refuse_escape(err, path))?;
+        }
+        Ok(())
+    }
+}
+
+/// Explains the error of a path of `path` leading outside of the destination, which is an escape
+/// attempt. cap-std reports them as permission errors that don't come from the OS.
+fn refuse_escape(err: io::Error, path: &Path) -> io::Error {
+    if err.kind() != io::ErrorKind::PermissionDenied || err.raw_os_error().is_some() {
+        return err;
+    }
+
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!(
+            "{} goes through a symlink leading outside of the destination, which isn't allowed with --confined",
+            EscapedPathDisplay::new(path)
+        ),
+    )
+}
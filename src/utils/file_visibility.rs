@@ -1,6 +1,16 @@
-use std::path::Path;
+use std::{
+    collections::HashSet,
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use globset::GlobSet;
+
+use crate::cli::Traversal;
 
 /// Determines which files should be read or ignored during directory walking
+#[derive(Clone)]
 pub struct FileVisibilityPolicy {
     /// Enables reading .ignore files.
     ///
@@ -19,6 +29,16 @@ pub struct FileVisibilityPolicy {
 
     /// Enables reading `.git/info/exclude` files.
     pub read_git_exclude: bool,
+
+    /// If set, only the entries with these absolute paths are walked, used to split archives in
+    /// volumes.
+    pub only_paths: Option<Arc<HashSet<PathBuf>>>,
+
+    /// Order of the walk, depth-first by default.
+    pub traversal: Traversal,
+
+    /// Entries whose path or name match these globs are skipped, along with their content.
+    pub exclude: Option<Arc<GlobSet>>,
 }
 
 impl Default for FileVisibilityPolicy {
@@ -28,6 +48,9 @@ impl Default for FileVisibilityPolicy {
             read_hidden: true,
             read_git_ignore: false,
             read_git_exclude: false,
+            only_paths: None,
+            traversal: Traversal::Dfs,
+            exclude: None,
         }
     }
 }
@@ -67,8 +90,35 @@ impl FileVisibilityPolicy {
         Self { read_hidden, ..self }
     }
 
-    /// Walks through a directory using [`ignore::Walk`]
-    pub fn build_walker(&self, path: impl AsRef<Path>) -> ignore::Walk {
+    #[must_use]
+    /// Only walks the entries with these absolute paths.
+    pub fn only_paths(self, only_paths: HashSet<PathBuf>) -> Self {
+        Self {
+            only_paths: Some(Arc::new(only_paths)),
+            ..self
+        }
+    }
+
+    #[must_use]
+    /// Sets the order of the walk.
+    pub fn traversal(self, traversal: Traversal) -> Self {
+        Self { traversal, ..self }
+    }
+
+    #[must_use]
+    /// Skips the entries matching `exclude`.
+    pub fn exclude(self, exclude: GlobSet) -> Self {
+        Self {
+            exclude: Some(Arc::new(exclude)),
+            ..self
+        }
+    }
+
+    /// Walks through a directory using [`ignore::Walk`], reordered breadth-first if asked to
+    pub fn build_walker(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Box<dyn Iterator<Item = Result<ignore::DirEntry, ignore::Error>>> {
         let mut builder = ignore::WalkBuilder::new(path);
 
         builder
@@ -77,10 +127,37 @@ impl FileVisibilityPolicy {
             .ignore(self.read_ignore)
             .hidden(self.read_hidden);
 
-        if self.read_git_ignore {
-            builder.filter_entry(|p| p.path().file_name().is_some_and(|name| name != ".git"));
-        }
+        // Only one filter can be set, pruning directories along with their content
+        let read_git_ignore = self.read_git_ignore;
+        let exclude = self.exclude.clone();
+        builder.filter_entry(move |entry| {
+            let path = entry.path();
+            if read_git_ignore && path.file_name().is_none_or(|name| name == ".git") {
+                return false;
+            }
+            exclude.as_ref().is_none_or(|exclude| {
+                !exclude.is_match(path) && !path.file_name().is_some_and(|name| exclude.is_match(name))
+            })
+        });
 
-        builder.build()
+        // Relative entries are found from the current directory
+        let only_paths = self.only_paths.clone();
+        let current_dir = env::current_dir().unwrap_or_default();
+
+        let walk = builder.build().filter(move |entry| match (&only_paths, entry) {
+            (Some(only_paths), Ok(entry)) => only_paths.contains(&current_dir.join(entry.path())),
+            _ => true,
+        });
+
+        match self.traversal {
+            Traversal::Dfs => Box::new(walk),
+            // The walk lists everything under a directory before its next sibling, so a stable
+            // sort by depth keeps each level in the order of their parents. Errors come first.
+            Traversal::Bfs => {
+                let mut entries: Vec<_> = walk.collect();
+                entries.sort_by_key(|entry| entry.as_ref().map_or(0, ignore::DirEntry::depth));
+                Box::new(entries.into_iter())
+            }
+        }
     }
 }
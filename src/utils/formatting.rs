@@ -1,6 +1,12 @@
-use std::{borrow::Cow, cmp, ffi::OsStr, fmt::Display, path::Path};
+use std::{borrow::Cow, cmp, env, ffi::OsStr, fmt::Display, path::Path, time::SystemTime};
 
-use crate::CURRENT_DIRECTORY;
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+use once_cell::sync::Lazy;
+
+use crate::{error::FinalError, CURRENT_DIRECTORY};
 
 /// Converts invalid UTF-8 bytes to the Unicode replacement codepoint (�) in its Display implementation.
 pub struct EscapedPathDisplay<'a> {
@@ -45,11 +51,11 @@ impl Display for EscapedPathDisplay<'_> {
 /// This is different from [`Path::display`].
 ///
 /// See <https://gist.github.com/marcospb19/ebce5572be26397cf08bbd0fd3b65ac1> for a comparison.
-pub fn path_to_str(path: &Path) -> Cow<str> {
+pub fn path_to_str(path: &Path) -> Cow<'_, str> {
     os_str_to_str(path.as_ref())
 }
 
-pub fn os_str_to_str(os_str: &OsStr) -> Cow<str> {
+pub fn os_str_to_str(os_str: &OsStr) -> Cow<'_, str> {
     let format = || {
         let text = format!("{os_str:?}");
         Cow::Owned(text.trim_matches('"').to_string())
@@ -83,7 +89,7 @@ pub fn pretty_format_list_of_paths(paths: &[impl AsRef<Path>]) -> String {
 }
 
 /// Display the directory name, but use "current directory" when necessary.
-pub fn nice_directory_display(path: &Path) -> Cow<str> {
+pub fn nice_directory_display(path: &Path) -> Cow<'_, str> {
     if path == Path::new(".") {
         Cow::Borrowed("current directory")
     } else {
@@ -91,6 +97,76 @@ pub fn nice_directory_display(path: &Path) -> Cow<str> {
     }
 }
 
+/// Number separators of the user's locale, detected once from `LC_ALL`, `LC_NUMERIC` or `LANG`.
+static NUMBER_FORMAT: Lazy<NumberFormat> = Lazy::new(NumberFormat::from_env);
+
+/// Separators used when displaying numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Separates the integer part from the fractional part
+    pub decimal_separator: char,
+    /// Separates groups of thousands, if the locale uses grouping
+    pub grouping_separator: Option<char>,
+}
+
+impl NumberFormat {
+    /// The "C" or "POSIX" locale, which is also the fallback for unknown locales.
+    pub const POSIX: Self = Self {
+        decimal_separator: '.',
+        grouping_separator: None,
+    };
+
+    /// Detect the number format from the environment, following POSIX precedence rules.
+    pub fn from_env() -> Self {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .into_iter()
+            .filter_map(env::var_os)
+            .find(|value| !value.is_empty())
+            .map_or(Self::POSIX, |locale| Self::from_locale_name(&locale.to_string_lossy()))
+    }
+
+    /// Build the number format of a locale name such as "de_DE.UTF-8" or "pt_BR".
+    pub fn from_locale_name(locale: &str) -> Self {
+        let language = locale.split(['_', '.', '@', '-']).next().unwrap_or_default();
+
+        let (decimal_separator, grouping_separator) = match language {
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" | "ro" | "hr" | "sl" | "sr" => (',', '.'),
+            "fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "nn" | "no" | "uk" | "hu" | "bg" | "lt" | "lv"
+            | "et" => (',', '\u{a0}'),
+            "en" | "ja" | "zh" | "ko" | "he" | "th" => ('.', ','),
+            _ => return Self::POSIX,
+        };
+
+        Self {
+            decimal_separator,
+            grouping_separator: Some(grouping_separator),
+        }
+    }
+
+    /// Format `num` with a fixed amount of decimal places.
+    pub fn format(&self, num: f64, precision: usize) -> String {
+        let text = format!("{num:.precision$}");
+        let (integer, fraction) = text.split_once('.').unwrap_or((&text, ""));
+
+        let mut result = String::with_capacity(text.len() + integer.len() / 3);
+        for (i, digit) in integer.chars().enumerate() {
+            let remaining_digits = integer.len() - i;
+            if i > 0 && remaining_digits % 3 == 0 {
+                if let Some(separator) = self.grouping_separator {
+                    result.push(separator);
+                }
+            }
+            result.push(digit);
+        }
+
+        if !fraction.is_empty() {
+            result.push(self.decimal_separator);
+            result.push_str(fraction);
+        }
+        result
+    }
+}
+
 /// Struct useful to printing bytes as kB, MB, GB, etc.
 pub struct Bytes(f64);
 
@@ -101,29 +177,69 @@ impl Bytes {
     pub fn new(bytes: u64) -> Self {
         Self(bytes as f64)
     }
-}
 
-impl std::fmt::Display for Bytes {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    /// Format using the given number format instead of the one detected from the locale.
+    pub fn to_string_with(&self, number_format: NumberFormat) -> String {
         let num = self.0;
 
         debug_assert!(num >= 0.0);
         if num < 1_f64 {
-            return write!(f, "{num:>6.2}   B");
+            return format!("{:>6}   B", number_format.format(num, 2));
         }
 
         let delimiter = 1000_f64;
         let exponent = cmp::min((num.ln() / 6.90775).floor() as i32, 4);
 
-        write!(
-            f,
-            "{:>6.2} {:>2}B",
-            num / delimiter.powi(exponent),
+        format!(
+            "{:>6} {:>2}B",
+            number_format.format(num / delimiter.powi(exponent), 2),
             Bytes::UNIT_PREFIXES[exponent as usize],
         )
     }
 }
 
+impl std::fmt::Display for Bytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_string_with(*NUMBER_FORMAT))
+    }
+}
+
+/// A validated strftime-like pattern used to display modification times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormat(String);
+
+impl DateFormat {
+    /// Close to ISO 8601, but without the seconds and timezone noise.
+    pub const DEFAULT: &'static str = "%Y-%m-%d %H:%M";
+
+    /// Parse a strftime pattern, failing on unknown or incomplete specifiers.
+    pub fn new(pattern: &str) -> crate::Result<Self> {
+        if let Some(position) = StrftimeItems::new(pattern).position(|item| matches!(item, Item::Error)) {
+            return Err(FinalError::with_title(format!("Invalid date format '{pattern}'"))
+                .detail(format!(
+                    "Item number {} is not a valid strftime specifier",
+                    position + 1
+                ))
+                .hint("Use strftime specifiers, e.g. `--date-format '%Y-%m-%d %H:%M:%S'`")
+                .hint("See https://docs.rs/chrono/latest/chrono/format/strftime for the supported specifiers")
+                .into());
+        }
+
+        Ok(Self(pattern.to_owned()))
+    }
+
+    /// Render `time` in the local timezone.
+    pub fn format(&self, time: SystemTime) -> String {
+        DateTime::<Local>::from(time).format(&self.0).to_string()
+    }
+}
+
+impl Default for DateFormat {
+    fn default() -> Self {
+        Self(Self::DEFAULT.to_owned())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -131,7 +247,7 @@ mod tests {
     #[test]
     fn test_pretty_bytes_formatting() {
         fn format_bytes(bytes: u64) -> String {
-            format!("{}", Bytes::new(bytes))
+            Bytes::new(bytes).to_string_with(NumberFormat::POSIX)
         }
         let b = 1;
         let kb = b * 1000;
@@ -167,4 +283,35 @@ mod tests {
         assert_eq!("999.90 GiB", format_bytes(999900000000));
         assert_eq!("  1.00 TiB", format_bytes(999990000000));
     }
+
+    #[test]
+    fn test_locale_number_formatting() {
+        let german = NumberFormat::from_locale_name("de_DE.UTF-8");
+        let english = NumberFormat::from_locale_name("en_US.UTF-8");
+
+        assert_eq!(NumberFormat::from_locale_name("C"), NumberFormat::POSIX);
+        assert_eq!(NumberFormat::from_locale_name("POSIX"), NumberFormat::POSIX);
+
+        assert_eq!(german.format(5.5, 2), "5,50");
+        assert_eq!(german.format(1234567.891, 2), "1.234.567,89");
+        assert_eq!(english.format(1234567.891, 2), "1,234,567.89");
+        assert_eq!(NumberFormat::POSIX.format(1234567.891, 2), "1234567.89");
+        assert_eq!(english.format(999.0, 0), "999");
+
+        assert_eq!(Bytes::new(5_500_000).to_string_with(german), "  5,50 MiB");
+        assert_eq!(
+            Bytes::new(1_200_000_000_000_000).to_string_with(english),
+            "1,200.00 TiB"
+        );
+    }
+
+    #[test]
+    fn test_date_format_validation() {
+        assert!(DateFormat::new(DateFormat::DEFAULT).is_ok());
+        assert!(DateFormat::new("%d/%m/%Y").is_ok());
+        assert!(DateFormat::new("plain text").is_ok());
+
+        assert!(DateFormat::new("%Y-%").is_err());
+        assert!(DateFormat::new("%Q").is_err());
+    }
 }
@@ -1,26 +1,43 @@
 //! Filesystem utility functions.
 
 use std::{
+    borrow::Cow,
     env,
     io::Read,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 use fs_err as fs;
+use once_cell::sync::OnceCell;
 
 use super::{question::FileConflitOperation, user_wants_to_overwrite};
 use crate::{
+    cli::AbsoluteSymlinks,
+    error::FinalError,
     extension::Extension,
-    utils::{logger::info_accessible, EscapedPathDisplay, QuestionAction},
+    utils::{
+        logger::{info_accessible, warning},
+        trash, EscapedPathDisplay, QuestionAction,
+    },
     QuestionPolicy,
 };
 
+static FORCE_LOCAL: OnceCell<bool> = OnceCell::new();
+
+/// Set if entries with absolute or remote-looking paths are extracted, they're refused if never set.
+pub fn set_force_local(value: bool) {
+    if FORCE_LOCAL.get().is_none() {
+        FORCE_LOCAL.set(value).unwrap();
+    }
+}
+
 pub fn is_path_stdin(path: &Path) -> bool {
     path.as_os_str() == "-"
 }
 
 /// Check if &Path exists, if it does then ask the user if they want to overwrite or rename it.
-/// If the user want to overwrite then the file or directory will be removed and returned the same input path
+/// If the user want to overwrite then the file or directory will be removed (or moved to the trash if `trash`)
+/// and returned the same input path
 /// If the user want to rename then nothing will be removed and a new path will be returned with a new name
 ///
 /// * `Ok(None)` means the user wants to cancel the operation
@@ -30,12 +47,13 @@ pub fn resolve_path_conflict(
     path: &Path,
     question_policy: QuestionPolicy,
     question_action: QuestionAction,
+    trash: bool,
 ) -> crate::Result<Option<PathBuf>> {
     if path.exists() {
         match user_wants_to_overwrite(path, question_policy, question_action)? {
             FileConflitOperation::Cancel => Ok(None),
             FileConflitOperation::Overwrite => {
-                remove_file_or_dir(path)?;
+                trash::remove_replaced(path, trash)?;
                 Ok(Some(path.to_path_buf()))
             }
             FileConflitOperation::Rename => {
@@ -93,6 +111,142 @@ pub fn rename_or_increment_filename(path: &Path) -> PathBuf {
     new_path
 }
 
+/// Normalizes the path of an archive entry before extraction.
+///
+/// Leading `./` and `/` prefixes and inner `.` components are dropped, so `./dir/./file` becomes
+/// `dir/file`. Returns `None` for entries that point at the extraction root itself, like `./`.
+///
+/// `..` components are kept so the zip-slip checks of each format can still reject them.
+pub fn normalize_entry_path(path: &Path) -> Option<PathBuf> {
+    let normalized: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_) | Component::ParentDir))
+        .collect();
+
+    (!normalized.as_os_str().is_empty()).then_some(normalized)
+}
+
+/// Refuses the path of an archive entry before extraction if it's absolute, or if it looks like a
+/// remote location like `host:path`, with a colon before any separator. `--force-local` allows
+/// them, they're then extracted relative to the output directory like the other entries.
+///
+/// Archives made by tools store relative paths, these are more likely to come from crafted ones.
+pub fn check_entry_path(path: &Path) -> crate::Result<()> {
+    if FORCE_LOCAL.get().copied().unwrap_or(false) {
+        return Ok(());
+    }
+
+    let name = path.as_os_str().as_encoded_bytes();
+    let first_component = name
+        .split(|&byte| byte == b'/' || byte == b'\\')
+        .next()
+        .unwrap_or_default();
+    let reason = if path.has_root() || name.starts_with(b"/") || name.starts_with(b"\\") {
+        "Its path is absolute"
+    } else if first_component.contains(&b':') {
+        "Its path looks like a remote location, like \"host:path\""
+    } else {
+        return Ok(());
+    };
+
+    Err(FinalError::with_title(format!(
+        "Refusing to extract the entry {}",
+        EscapedPathDisplay::new(path)
+    ))
+    .detail(reason)
+    .detail("Archives created by tools only store relative paths, this one may have been crafted")
+    .hint("Pass --force-local to extract it relative to the output directory anyway")
+    .into())
+}
+
+/// Removes the last `count` components of a normalized entry path, for
+/// `--strip-trailing-components`.
+///
+/// Returns `None` if nothing is left, or if the path has `..` components, as it's not extracted by
+/// `unpack_in` anymore, whose checks would have rejected it.
+pub fn strip_trailing_components(path: &Path, count: usize) -> Option<PathBuf> {
+    if path.components().any(|component| component == Component::ParentDir) {
+        return None;
+    }
+
+    let components: Vec<_> = path.components().collect();
+    let kept = components.len().checked_sub(count).filter(|&kept| kept > 0)?;
+    Some(components[..kept].iter().collect())
+}
+
+/// Replaces the first component of an entry path by `root`, so `dir/file` becomes `root/file`.
+///
+/// Used to store an input under another name, the walker yields paths starting with the input's name.
+pub fn replace_root_component(path: &Path, root: &str) -> PathBuf {
+    let mut components = path.components();
+    components.next();
+
+    let rest = components.as_path();
+    if rest.as_os_str().is_empty() {
+        PathBuf::from(root)
+    } else {
+        Path::new(root).join(rest)
+    }
+}
+
+/// Name the entry at `path` is stored under, with the `/` separators archive formats expect on all
+/// platforms. Returns `None` if the path isn't valid UTF-8.
+pub fn entry_name(path: &Path) -> Option<Cow<'_, str>> {
+    path.to_str()
+        .map(|name| with_forward_slashes(name, std::path::MAIN_SEPARATOR))
+}
+
+fn with_forward_slashes(name: &str, separator: char) -> Cow<'_, str> {
+    match separator {
+        '/' => Cow::Borrowed(name),
+        _ => Cow::Owned(name.replace(separator, "/")),
+    }
+}
+
+/// Applies the `--absolute-symlinks` policy to the `target` of the symlink extracted at `link_path`.
+///
+/// `link_path` is relative to the extraction root. Returns `None` if the symlink should be skipped,
+/// relative targets are always kept verbatim.
+pub fn resolve_symlink_target(policy: AbsoluteSymlinks, link_path: &Path, target: &Path) -> Option<PathBuf> {
+    if !target.has_root() {
+        return Some(target.to_path_buf());
+    }
+
+    match policy {
+        AbsoluteSymlinks::Allow => Some(target.to_path_buf()),
+        AbsoluteSymlinks::Skip => {
+            warning(format!(
+                "Skipping symlink {} with absolute target {}, pass --absolute-symlinks to extract it",
+                EscapedPathDisplay::new(link_path),
+                EscapedPathDisplay::new(target),
+            ));
+            None
+        }
+        AbsoluteSymlinks::Relativize => {
+            // Resolve `..` lexically, it can't climb above the root of an absolute path
+            let mut target_in_root = PathBuf::new();
+            for component in target.components() {
+                match component {
+                    Component::Normal(name) => target_in_root.push(name),
+                    Component::ParentDir => _ = target_in_root.pop(),
+                    Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+                }
+            }
+
+            // Climb from the directory of the link up to the extraction root
+            let depth = link_path.components().count().saturating_sub(1);
+            let mut relative_target: PathBuf = std::iter::repeat_n(Component::ParentDir, depth).collect();
+            relative_target.push(target_in_root);
+
+            if relative_target.as_os_str().is_empty() {
+                Some(PathBuf::from("."))
+            } else {
+                Some(relative_target)
+            }
+        }
+    }
+}
+
 /// Creates a directory at the path, if there is nothing there.
 pub fn create_dir_if_non_existent(path: &Path) -> crate::Result<()> {
     if !path.exists() {
@@ -118,8 +272,26 @@ pub fn cd_into_same_dir_as(filename: &Path) -> crate::Result<PathBuf> {
 /// Try to detect the file extension by looking for known magic strings
 /// Source: <https://en.wikipedia.org/wiki/List_of_file_signatures>
 pub fn try_infer_extension(path: &Path) -> Option<Extension> {
+    let mut buf = [0; SNIFFED_LEN];
+
+    // Error cause will be ignored, so use std::fs instead of fs_err
+    let result = std::fs::File::open(path).map(|mut file| file.read(&mut buf));
+
+    // In case of file open or read failure, could not infer a extension
+    if result.is_err() {
+        return None;
+    }
+    infer_extension(&buf)
+}
+
+/// Bytes at the start of a file that [`infer_extension`] needs to tell all formats apart
+pub const SNIFFED_LEN: usize = 270;
+
+/// Detects the format of data starting with `buf` by its magic number, `buf` may be shorter than
+/// [`SNIFFED_LEN`] for shorter data.
+pub fn infer_extension(buf: &[u8]) -> Option<Extension> {
     fn is_zip(buf: &[u8]) -> bool {
-        buf.len() >= 3
+        buf.len() >= 4
             && buf[..=1] == [0x50, 0x4B]
             && (buf[2..=3] == [0x3, 0x4] || buf[2..=3] == [0x5, 0x6] || buf[2..=3] == [0x7, 0x8])
     }
@@ -145,7 +317,7 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
         buf.starts_with(&[0x4C, 0x5A, 0x49, 0x50])
     }
     fn is_lz4(buf: &[u8]) -> bool {
-        buf.starts_with(&[0x04, 0x22, 0x4D, 0x18])
+        buf.starts_with(&[0x04, 0x22, 0x4D, 0x18]) || buf.starts_with(&crate::archive::lz4_legacy::MAGIC)
     }
     fn is_sz(buf: &[u8]) -> bool {
         buf.starts_with(&[0xFF, 0x06, 0x00, 0x00, 0x73, 0x4E, 0x61, 0x50, 0x70, 0x59])
@@ -165,51 +337,79 @@ pub fn try_infer_extension(path: &Path) -> Option<Extension> {
         buf.starts_with(&[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C])
     }
 
-    let buf = {
-        let mut buf = [0; 270];
-
-        // Error cause will be ignored, so use std::fs instead of fs_err
-        let result = std::fs::File::open(path).map(|mut file| file.read(&mut buf));
-
-        // In case of file open or read failure, could not infer a extension
-        if result.is_err() {
-            return None;
-        }
-        buf
-    };
-
     use crate::extension::CompressionFormat::*;
-    if is_zip(&buf) {
+    if is_zip(buf) {
         Some(Extension::new(&[Zip], "zip"))
-    } else if is_tar(&buf) {
+    } else if is_tar(buf) {
         Some(Extension::new(&[Tar], "tar"))
-    } else if is_gz(&buf) {
+    } else if is_gz(buf) {
         Some(Extension::new(&[Gzip], "gz"))
-    } else if is_bz2(&buf) {
+    } else if is_bz2(buf) {
         Some(Extension::new(&[Bzip], "bz2"))
-    } else if is_bz3(&buf) {
+    } else if is_bz3(buf) {
         Some(Extension::new(&[Bzip3], "bz3"))
-    } else if is_lzma(&buf) {
+    } else if is_lzma(buf) {
         Some(Extension::new(&[Lzma], "lzma"))
-    } else if is_xz(&buf) {
+    } else if is_xz(buf) {
         Some(Extension::new(&[Xz], "xz"))
-    } else if is_lzip(&buf) {
+    } else if is_lzip(buf) {
         Some(Extension::new(&[Lzip], "lzip"))
-    } else if is_lz4(&buf) {
+    } else if is_lz4(buf) {
         Some(Extension::new(&[Lz4], "lz4"))
-    } else if is_sz(&buf) {
+    } else if is_sz(buf) {
         Some(Extension::new(&[Snappy], "sz"))
-    } else if is_zst(&buf) {
+    } else if is_zst(buf) {
         Some(Extension::new(&[Zstd], "zst"))
-    } else if is_rar(&buf) {
+    } else if is_rar(buf) {
         Some(Extension::new(&[Rar], "rar"))
-    } else if is_sevenz(&buf) {
+    } else if is_sevenz(buf) {
         Some(Extension::new(&[SevenZip], "7z"))
     } else {
         None
     }
 }
 
+/// Size of the content read from `path`: the length of a regular file, or the capacity of a block
+/// device on Linux. `None` for what can only be known by reading it, like pipes.
+pub fn content_size(path: &Path) -> Option<u64> {
+    let metadata = fs::metadata(path).ok()?;
+    if metadata.is_file() {
+        return Some(metadata.len());
+    }
+
+    #[cfg(target_os = "linux")]
+    if is_block_device(path) {
+        return block_device_size(path).ok();
+    }
+
+    None
+}
+
+/// Check if `path` is a block device, like "/dev/sdb", whose content is read in full
+#[cfg(unix)]
+pub fn is_block_device(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_block_device())
+}
+
+/// Capacity of the block device at `path`, which its metadata leaves at zero
+#[cfg(target_os = "linux")]
+fn block_device_size(path: &Path) -> std::io::Result<u64> {
+    use std::os::fd::AsRawFd;
+
+    // `_IOR(0x12, 114, size_t)` of <linux/fs.h>, which libc doesn't export
+    const BLKGETSIZE64: u64 = 2 << 30 | (std::mem::size_of::<usize>() as u64) << 16 | 0x12 << 8 | 114;
+
+    let file = fs::File::open(path)?;
+    let mut size = 0_u64;
+    // SAFETY: the descriptor stays open during the call, which only writes a u64 to `size`
+    if unsafe { libc::ioctl(file.as_raw_fd(), BLKGETSIZE64 as _, &mut size) } == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(size)
+}
+
 /// Rename the src directory into the dst directory recursively
 pub fn rename_recursively(src: &Path, dst: &Path) -> crate::Result<()> {
     if !src.exists() || !dst.exists() {
@@ -229,3 +429,101 @@ pub fn rename_recursively(src: &Path, dst: &Path) -> crate::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_symlink_target() {
+        let resolve = |policy, link: &str, target: &str| resolve_symlink_target(policy, link.as_ref(), target.as_ref());
+
+        for policy in [
+            AbsoluteSymlinks::Allow,
+            AbsoluteSymlinks::Skip,
+            AbsoluteSymlinks::Relativize,
+        ] {
+            assert_eq!(resolve(policy, "dir/link", "../foo"), Some("../foo".into()));
+        }
+        assert_eq!(
+            resolve(AbsoluteSymlinks::Allow, "dir/link", "/etc/passwd"),
+            Some("/etc/passwd".into())
+        );
+        assert_eq!(resolve(AbsoluteSymlinks::Skip, "dir/link", "/etc/passwd"), None);
+        assert_eq!(
+            resolve(AbsoluteSymlinks::Relativize, "dir/link", "/etc/passwd"),
+            Some("../etc/passwd".into())
+        );
+        assert_eq!(
+            resolve(AbsoluteSymlinks::Relativize, "link", "/etc/../../passwd"),
+            Some("passwd".into())
+        );
+        assert_eq!(resolve(AbsoluteSymlinks::Relativize, "link", "/"), Some(".".into()));
+    }
+
+    #[test]
+    fn test_replace_root_component() {
+        assert_eq!(replace_root_component(Path::new("file"), "name"), Path::new("name"));
+        assert_eq!(
+            replace_root_component(Path::new("dir/a/b"), "name"),
+            Path::new("name/a/b")
+        );
+    }
+
+    #[test]
+    fn test_entry_name_separators() {
+        assert_eq!(with_forward_slashes(r"dir\a\b", '\\'), "dir/a/b");
+        assert_eq!(with_forward_slashes("dir/a/b", '\\'), "dir/a/b");
+        // Backslashes are part of names where they aren't separators
+        assert_eq!(with_forward_slashes(r"dir/a\b", '/'), r"dir/a\b");
+    }
+
+    #[test]
+    fn test_strip_trailing_components() {
+        assert_eq!(strip_trailing_components(Path::new("a/b/c"), 0), Some("a/b/c".into()));
+        assert_eq!(strip_trailing_components(Path::new("a/b/c"), 1), Some("a/b".into()));
+        assert_eq!(strip_trailing_components(Path::new("a/b/c"), 2), Some("a".into()));
+        assert_eq!(strip_trailing_components(Path::new("a/b/c"), 3), None);
+        assert_eq!(strip_trailing_components(Path::new("a/b/c"), 4), None);
+        assert_eq!(strip_trailing_components(Path::new("../a/b"), 1), None);
+    }
+
+    #[test]
+    fn test_normalize_entry_path() {
+        assert_eq!(normalize_entry_path(Path::new("./")), None);
+        assert_eq!(normalize_entry_path(Path::new(".")), None);
+        assert_eq!(normalize_entry_path(Path::new("/")), None);
+        assert_eq!(normalize_entry_path(Path::new("file")), Some("file".into()));
+        assert_eq!(normalize_entry_path(Path::new("./file")), Some("file".into()));
+        assert_eq!(normalize_entry_path(Path::new("./dir/./file")), Some("dir/file".into()));
+        assert_eq!(normalize_entry_path(Path::new("/dir/")), Some("dir".into()));
+        assert_eq!(normalize_entry_path(Path::new("../file")), Some("../file".into()));
+    }
+
+    #[test]
+    fn test_check_entry_path() {
+        assert!(check_entry_path(Path::new("file")).is_ok());
+        assert!(check_entry_path(Path::new("./dir/file")).is_ok());
+        assert!(check_entry_path(Path::new("dir/a:b")).is_ok());
+        assert!(check_entry_path(Path::new("/etc/passwd")).is_err());
+        assert!(check_entry_path(Path::new("\\dir\\file")).is_err());
+        assert!(check_entry_path(Path::new("host:file")).is_err());
+        assert!(check_entry_path(Path::new("user@host:dir/file")).is_err());
+    }
+
+    #[test]
+    fn test_content_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("file");
+        fs::write(&path, "content").unwrap();
+        assert_eq!(content_size(&path), Some(7));
+
+        assert_eq!(content_size(&temp_dir.path().join("missing")), None);
+        #[cfg(unix)]
+        {
+            // Character devices have no size to query, unlike block devices
+            assert!(!is_block_device(Path::new("/dev/null")));
+            assert_eq!(content_size(Path::new("/dev/null")), None);
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use std::io::{self, stderr, stdout, StderrLock, StdoutLock, Write};
+use std::io::{self, stderr, stdout, Read, Seek, SeekFrom, StderrLock, StdoutLock, Write};
 
-use crate::utils::logger;
+use crate::{cli::Eol, utils::logger};
+
+/// Leading bytes looked at to tell text from binary content, as many as git does
+const TEXT_DETECTION_LEN: u64 = 8000;
 
 type StdioOutputLocks = (StdoutLock<'static>, StderrLock<'static>);
 
@@ -14,3 +17,66 @@ pub fn lock_and_flush_output_stdio() -> io::Result<StdioOutputLocks> {
 
     Ok((stdout, stderr))
 }
+
+/// Reads `file` in full with its line endings converted to `eol`, if its content looks like text.
+///
+/// Binary content, with a NUL byte in its first 8000 bytes, is left untouched: `None` is returned
+/// and `file` is back where it was, to be read as is.
+pub fn read_with_line_endings<R: Read + Seek>(file: &mut R, eol: Eol) -> io::Result<Option<Vec<u8>>> {
+    if eol == Eol::Keep {
+        return Ok(None);
+    }
+
+    let mut content = vec![];
+    file.by_ref().take(TEXT_DETECTION_LEN).read_to_end(&mut content)?;
+    if content.contains(&0) {
+        file.seek(SeekFrom::Current(-(content.len() as i64)))?;
+        return Ok(None);
+    }
+    file.read_to_end(&mut content)?;
+
+    Ok(Some(convert_line_endings(&content, eol)))
+}
+
+/// Replaces the "\r\n", "\r" and "\n" line endings of `text` by the ones of `eol`
+fn convert_line_endings(text: &[u8], eol: Eol) -> Vec<u8> {
+    let line_ending: &[u8] = match eol {
+        Eol::Crlf => b"\r\n",
+        Eol::Lf | Eol::Keep => b"\n",
+    };
+
+    let mut converted = Vec::with_capacity(text.len());
+    let mut bytes = text.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\r' => {
+                bytes.next_if_eq(&&b'\n');
+                converted.extend_from_slice(line_ending);
+            }
+            b'\n' => converted.extend_from_slice(line_ending),
+            _ => converted.push(byte),
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_read_with_line_endings() {
+        let read = |content: &[u8], eol| read_with_line_endings(&mut Cursor::new(content), eol).unwrap();
+
+        assert_eq!(read(b"a\r\nb\rc\n", Eol::Lf).unwrap(), b"a\nb\nc\n");
+        assert_eq!(read(b"a\r\nb\rc\n", Eol::Crlf).unwrap(), b"a\r\nb\r\nc\r\n");
+        assert_eq!(read(b"a\r\n", Eol::Keep), None);
+
+        // Binary content is left to be read from the start
+        let mut binary = Cursor::new(b"\x89PNG\r\n\x1a\n\0\0".to_vec());
+        assert_eq!(read_with_line_endings(&mut binary, Eol::Lf).unwrap(), None);
+        assert_eq!(binary.position(), 0);
+    }
+}
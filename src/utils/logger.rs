@@ -1,12 +1,58 @@
 use std::{
-    sync::{mpsc, Arc, Barrier, OnceLock},
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Barrier, OnceLock,
+    },
     thread,
 };
 
 pub use logger_thread::spawn_logger_thread;
+use once_cell::sync::OnceCell;
 
 use super::colors::{ORANGE, RESET, YELLOW};
-use crate::accessible::is_running_in_accessible_mode;
+use crate::{accessible::is_running_in_accessible_mode, cli::LogFormat};
+
+static LOG_FORMAT: OnceCell<LogFormat> = OnceCell::new();
+
+/// Set the format of messages, defaults to human readable text if never set.
+pub fn set_log_format(format: LogFormat) {
+    if LOG_FORMAT.get().is_none() {
+        LOG_FORMAT.set(format).unwrap();
+    }
+}
+
+pub fn log_format() -> LogFormat {
+    LOG_FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Formats the error that stopped ouch, as a record with the "error" level for structured formats.
+pub fn format_error(error: &impl fmt::Display) -> String {
+    match log_format() {
+        LogFormat::Human => error.to_string(),
+        format => format_record(format, "error", &error.to_string()),
+    }
+}
+
+/// One line with the level and message, for log collectors
+pub fn format_record(format: LogFormat, level: &str, message: &str) -> String {
+    match format {
+        LogFormat::Human => unreachable!("human messages aren't records"),
+        LogFormat::Json => serde_json::json!({ "level": level, "msg": message }).to_string(),
+        LogFormat::Logfmt => {
+            let mut escaped = String::with_capacity(message.len());
+            for char in message.chars() {
+                match char {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    char => escaped.push(char),
+                }
+            }
+            format!("level={level} msg=\"{escaped}\"")
+        }
+    }
+}
 
 /// Asks logger to shutdown and waits till it flushes all pending messages.
 #[track_caller]
@@ -52,8 +98,17 @@ fn info_with_accessibility(contents: String, accessible: bool) {
     });
 }
 
+/// Number of warnings emitted so far
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of warnings emitted so far, used by `--fail-on-warning`.
+pub fn warning_count() -> usize {
+    WARNING_COUNT.load(Ordering::Relaxed)
+}
+
 #[track_caller]
 pub fn warning(contents: String) {
+    WARNING_COUNT.fetch_add(1, Ordering::Relaxed);
     logger_thread::send_print_command(PrintMessage {
         contents,
         // Warnings are important and unlikely to flood, so they should be displayed
@@ -62,9 +117,18 @@ pub fn warning(contents: String) {
     });
 }
 
+/// Shows `status` on a line kept below the logs, or removes it if `None`.
+///
+/// Callers are expected to only set it when messages go to a terminal.
+#[track_caller]
+pub fn set_status_line(status: Option<String>) {
+    logger_thread::send_status_command(status);
+}
+
 #[derive(Debug)]
 enum LoggerCommand {
     Print(PrintMessage),
+    Status(Option<String>),
     Flush { finished_barrier: Arc<Barrier> },
     FlushAndShutdown { finished_barrier: Arc<Barrier> },
 }
@@ -80,27 +144,26 @@ struct PrintMessage {
 
 impl PrintMessage {
     fn to_formatted_message(&self) -> Option<String> {
-        match self.level {
-            MessageLevel::Info => {
-                if self.accessible {
-                    if is_running_in_accessible_mode() {
-                        Some(format!("{}Info:{} {}", *YELLOW, *RESET, self.contents))
-                    } else {
-                        Some(format!("{}[INFO]{} {}", *YELLOW, *RESET, self.contents))
-                    }
-                } else if !is_running_in_accessible_mode() {
-                    Some(format!("{}[INFO]{} {}", *YELLOW, *RESET, self.contents))
-                } else {
-                    None
-                }
-            }
-            MessageLevel::Warning => {
-                if is_running_in_accessible_mode() {
-                    Some(format!("{}Warning:{} {}", *ORANGE, *RESET, self.contents))
+        // Info messages that aren't accessible are noise for screen readers
+        if self.level == MessageLevel::Info && !self.accessible && is_running_in_accessible_mode() {
+            return None;
+        }
+
+        let (level, prefix, accessible_prefix, color) = match self.level {
+            MessageLevel::Info => ("info", "[INFO]", "Info:", &*YELLOW),
+            MessageLevel::Warning => ("warning", "[WARNING]", "Warning:", &*ORANGE),
+        };
+
+        match log_format() {
+            LogFormat::Human => {
+                let prefix = if is_running_in_accessible_mode() {
+                    accessible_prefix
                 } else {
-                    Some(format!("{}[WARNING]{} {}", *ORANGE, *RESET, self.contents))
-                }
+                    prefix
+                };
+                Some(format!("{color}{prefix}{} {}", *RESET, self.contents))
             }
+            format => Some(format_record(format, level, &self.contents)),
         }
     }
 }
@@ -118,6 +181,7 @@ mod logger_thread {
     };
 
     use super::*;
+    use crate::utils::output::print_message;
 
     type LogReceiver = mpsc::Receiver<LoggerCommand>;
     type LogSender = mpsc::Sender<LoggerCommand>;
@@ -150,6 +214,16 @@ mod logger_thread {
             .expect("Failed to send print command");
     }
 
+    #[track_caller]
+    pub(super) fn send_status_command(status: Option<String>) {
+        if cfg!(test) {
+            spawn_logger_thread();
+        }
+        get_sender()
+            .send(LoggerCommand::Status(status))
+            .expect("Failed to send status command");
+    }
+
     #[track_caller]
     pub(super) fn send_flush_command_and_wait() {
         let barrier = Arc::new(Barrier::new(2));
@@ -186,12 +260,13 @@ mod logger_thread {
         const FLUSH_TIMEOUT: Duration = Duration::from_millis(200);
 
         let mut buffer = Vec::<String>::with_capacity(16);
+        let mut status = None::<String>;
 
         loop {
             let msg = match log_receiver.recv_timeout(FLUSH_TIMEOUT) {
                 Ok(msg) => msg,
                 Err(RecvTimeoutError::Timeout) => {
-                    flush_logs_to_stderr(&mut buffer);
+                    flush_logs(&mut buffer, status.as_deref());
                     continue;
                 }
                 Err(RecvTimeoutError::Disconnected) => unreachable!("sender is static"),
@@ -205,15 +280,21 @@ mod logger_thread {
                     }
 
                     if buffer.len() == buffer.capacity() {
-                        flush_logs_to_stderr(&mut buffer);
+                        flush_logs(&mut buffer, status.as_deref());
                     }
                 }
+                LoggerCommand::Status(new_status) => {
+                    flush_logs(&mut buffer, status.as_deref());
+                    redraw_status_line(status.as_deref(), new_status.as_deref());
+                    status = new_status;
+                }
                 LoggerCommand::Flush { finished_barrier } => {
-                    flush_logs_to_stderr(&mut buffer);
+                    flush_logs(&mut buffer, status.as_deref());
                     finished_barrier.wait();
                 }
                 LoggerCommand::FlushAndShutdown { finished_barrier } => {
-                    flush_logs_to_stderr(&mut buffer);
+                    flush_logs(&mut buffer, status.as_deref());
+                    redraw_status_line(status.as_deref(), None);
                     finished_barrier.wait();
                     return;
                 }
@@ -221,11 +302,27 @@ mod logger_thread {
         }
     }
 
-    fn flush_logs_to_stderr(buffer: &mut Vec<String>) {
+    /// Prints the buffered logs above the status line, if any
+    fn flush_logs(buffer: &mut Vec<String>, status: Option<&str>) {
         if !buffer.is_empty() {
             let text = buffer.join("\n");
-            eprintln!("{text}");
+            match status {
+                Some(status) => print_message(format_args!("{CLEAR_LINE}{text}\n{status}")),
+                None => print_message(format_args!("{text}\n")),
+            }
             buffer.clear();
         }
     }
+
+    fn redraw_status_line(old_status: Option<&str>, new_status: Option<&str>) {
+        if old_status.is_some() {
+            print_message(format_args!("{CLEAR_LINE}"));
+        }
+        if let Some(status) = new_status {
+            print_message(format_args!("{status}"));
+        }
+    }
+
+    /// Moves the cursor to the start of the line and erases it
+    const CLEAR_LINE: &str = "\r\u{1b}[2K";
 }
@@ -4,22 +4,32 @@
 //! stdin interaction helpers.
 
 pub mod colors;
+#[cfg(unix)]
+pub mod confined;
 mod file_visibility;
 mod formatting;
 mod fs;
 pub mod io;
 pub mod logger;
+pub mod output;
+pub mod partial_output;
+pub mod progress;
 mod question;
+pub mod trash;
 
+#[cfg(unix)]
+pub use self::fs::is_block_device;
 pub use self::{
     file_visibility::FileVisibilityPolicy,
     formatting::{
         nice_directory_display, os_str_to_str, path_to_str, pretty_format_list_of_paths, strip_cur_dir, Bytes,
-        EscapedPathDisplay,
+        DateFormat, EscapedPathDisplay, NumberFormat,
     },
     fs::{
-        cd_into_same_dir_as, create_dir_if_non_existent, is_path_stdin, remove_file_or_dir,
-        rename_for_available_filename, rename_recursively, resolve_path_conflict, try_infer_extension,
+        cd_into_same_dir_as, check_entry_path, content_size, create_dir_if_non_existent, entry_name, infer_extension,
+        is_path_stdin, normalize_entry_path, remove_file_or_dir, rename_for_available_filename, rename_recursively,
+        replace_root_component, resolve_path_conflict, resolve_symlink_target, set_force_local,
+        strip_trailing_components, try_infer_extension, SNIFFED_LEN,
     },
     question::{
         ask_to_create_file, user_wants_to_continue, user_wants_to_overwrite, FileConflitOperation, QuestionAction,
@@ -0,0 +1,35 @@
+//! Where messages are printed.
+//!
+//! Stdout is reserved for data, like decompressed bytes or listed entries, so it can be piped
+//! safely. Messages for humans go to stderr, unless `--messages stdout` asks for the opposite.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use once_cell::sync::OnceCell;
+
+use crate::cli::MessageStream;
+
+static MESSAGE_STREAM: OnceCell<MessageStream> = OnceCell::new();
+
+/// Set the stream of messages, defaults to stderr if never set.
+pub fn set_message_stream(stream: MessageStream) {
+    if MESSAGE_STREAM.get().is_none() {
+        MESSAGE_STREAM.set(stream).unwrap();
+    }
+}
+
+pub fn message_stream() -> MessageStream {
+    MESSAGE_STREAM.get().copied().unwrap_or_default()
+}
+
+/// Prints a message to the message stream, same as `eprint!` when it's stderr.
+pub fn print_message(message: fmt::Arguments) {
+    // Like `eprint!`, failing to print a message isn't worth stopping for
+    let _ = match message_stream() {
+        MessageStream::Stdout => io::stdout().lock().write_fmt(message),
+        MessageStream::Stderr => io::stderr().lock().write_fmt(message),
+    };
+}
@@ -0,0 +1,108 @@
+//! Removal of the output being compressed when ouch doesn't exit cleanly.
+//!
+//! Compressors only write their last frame and the trailer of archives once finished, so the
+//! output of an interrupted compression is always truncated, even when it starts like a valid
+//! file. It's removed instead, whether ouch is stopped by Ctrl-C, by a signal like the one sent
+//! by `timeout`, or by a panic. Finalizing it from a signal handler isn't possible, as only a few
+//! calls like `unlink` are safe there.
+
+use std::{
+    env, io,
+    path::{Path, PathBuf},
+};
+#[cfg(unix)]
+use std::{
+    ffi::CString,
+    os::unix::ffi::OsStrExt,
+    ptr,
+    sync::{
+        atomic::{AtomicPtr, Ordering},
+        Once,
+    },
+};
+
+use super::remove_file_or_dir;
+
+/// Path of the output being written, removed by the signal handler, null if there's none.
+///
+/// Paths stored here are never freed, a handler that already loaded one may still be reading it.
+#[cfg(unix)]
+static PARTIAL_OUTPUT: AtomicPtr<libc::c_char> = AtomicPtr::new(ptr::null_mut());
+
+/// Signals that would stop ouch, not counting the ones that can't be handled like `SIGKILL`
+#[cfg(unix)]
+const TERMINATION_SIGNALS: [libc::c_int; 3] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP];
+
+/// Output removed if ouch is interrupted before [`PartialOutput::finish`] is called.
+///
+/// Only one output is guarded at a time, like the volumes of `--split-by-entry` are written.
+pub struct PartialOutput {
+    /// Absolute, as the current directory changes while archives are built
+    path: PathBuf,
+    finished: bool,
+}
+
+impl PartialOutput {
+    /// Starts guarding the output at `path`, which was just created.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let path = env::current_dir()?.join(path);
+
+        #[cfg(unix)]
+        {
+            let c_path = CString::new(path.as_os_str().as_bytes())?;
+            install_signal_handlers();
+            PARTIAL_OUTPUT.store(c_path.into_raw(), Ordering::SeqCst);
+        }
+
+        Ok(Self { path, finished: false })
+    }
+
+    /// Stops guarding the output, it's complete.
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for PartialOutput {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        PARTIAL_OUTPUT.store(ptr::null_mut(), Ordering::SeqCst);
+
+        // Errors are handled by the caller, which also removes the output, only panics are left
+        if !self.finished && std::thread::panicking() {
+            let _ = remove_file_or_dir(&self.path);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn install_signal_handlers() {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        for signal in TERMINATION_SIGNALS {
+            // SAFETY: the handler only makes async-signal-safe calls
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = remove_partial_output as *const () as libc::sighandler_t;
+                libc::sigemptyset(&mut action.sa_mask);
+                libc::sigaction(signal, &action, ptr::null_mut());
+            }
+        }
+    });
+}
+
+/// Removes the output being written, then dies of `signal` like ouch would have without handler
+#[cfg(unix)]
+extern "C" fn remove_partial_output(signal: libc::c_int) {
+    let path = PARTIAL_OUTPUT.swap(ptr::null_mut(), Ordering::SeqCst);
+
+    // SAFETY: `unlink`, `signal` and `raise` are async-signal-safe, and paths are never freed
+    unsafe {
+        if !path.is_null() {
+            libc::unlink(path);
+        }
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
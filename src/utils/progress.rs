@@ -0,0 +1,439 @@
+//! Overall progress of a batch of inputs, shown on a status line below the logs.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::OnceCell;
+
+use super::{
+    content_size, is_path_stdin,
+    logger::{log_format, set_status_line},
+    output::message_stream,
+    Bytes,
+};
+use crate::{
+    accessible::is_running_in_accessible_mode,
+    cli::{LogFormat, MessageStream},
+    error::FinalError,
+};
+
+/// Minimum time between two redraws of the status line
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Frames of the spinner shown while the total is unknown, plain ASCII to work on any terminal
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Tokens of `--progress-template` with what they stand for, in the order errors list them
+const TEMPLATE_TOKENS: [(&str, Token); 8] = [
+    ("spinner", Token::Spinner),
+    ("file", Token::File),
+    ("files", Token::Files),
+    ("percent", Token::Percent),
+    ("bytes", Token::Bytes),
+    ("total_bytes", Token::TotalBytes),
+    ("throughput", Token::Throughput),
+    ("elapsed", Token::Elapsed),
+];
+
+static PROGRESS_TEMPLATE: OnceCell<ProgressTemplate> = OnceCell::new();
+
+/// Set the layout of the status line, the default one is kept if never set.
+pub fn set_progress_template(template: ProgressTemplate) {
+    if PROGRESS_TEMPLATE.get().is_none() {
+        PROGRESS_TEMPLATE.set(template).unwrap();
+    }
+}
+
+/// Size of the input at `path`, unknown for stdin and special files like pipes.
+pub fn input_size(path: &Path) -> Option<u64> {
+    if is_path_stdin(path) {
+        return None;
+    }
+    content_size(path)
+}
+
+/// Progress summed over all items of a batch.
+///
+/// The total is known up front from the sizes of the items, unless some of them can't be known
+/// before being read. Until then, a spinner with the bytes processed and the throughput is shown
+/// instead of a percentage.
+pub struct AggregateProgress {
+    total_items: usize,
+    /// Sum of the sizes known so far
+    known_bytes: AtomicU64,
+    /// Items whose size is only known once fully read
+    unknown_items: AtomicUsize,
+    done_items: AtomicUsize,
+    done_bytes: AtomicU64,
+    started: Instant,
+    redraws: AtomicUsize,
+    last_redraw: Mutex<Option<Instant>>,
+}
+
+impl AggregateProgress {
+    pub fn new(item_sizes: impl IntoIterator<Item = Option<u64>>) -> Self {
+        let (total_items, unknown_items, known_bytes) =
+            item_sizes
+                .into_iter()
+                .fold((0, 0, 0), |(items, unknown, bytes), size| match size {
+                    Some(size) => (items + 1, unknown, bytes + size),
+                    None => (items + 1, unknown + 1, bytes),
+                });
+
+        Self {
+            total_items,
+            known_bytes: AtomicU64::new(known_bytes),
+            unknown_items: AtomicUsize::new(unknown_items),
+            done_items: AtomicUsize::new(0),
+            done_bytes: AtomicU64::new(0),
+            started: Instant::now(),
+            redraws: AtomicUsize::new(0),
+            last_redraw: Mutex::new(None),
+        }
+    }
+
+    /// Check if the progress can be displayed, it would only be noise in quiet and accessible
+    /// modes, with structured log formats, or when messages don't go to a terminal.
+    pub fn is_supported(quiet: bool) -> bool {
+        let stream = match message_stream() {
+            MessageStream::Stdout => atty::Stream::Stdout,
+            MessageStream::Stderr => atty::Stream::Stderr,
+        };
+        !quiet && !is_running_in_accessible_mode() && log_format() == LogFormat::Human && atty::is(stream)
+    }
+
+    /// Total of the batch, `None` while the size of some items is still unknown
+    pub fn total_bytes(&self) -> Option<u64> {
+        (self.unknown_items.load(Ordering::Relaxed) == 0).then(|| self.known_bytes.load(Ordering::Relaxed))
+    }
+
+    /// Records the size of an item that was unknown, once all of them are known the spinner turns
+    /// into a percentage.
+    fn resolve_item_size(&self, size: u64) {
+        self.known_bytes.fetch_add(size, Ordering::Relaxed);
+        self.unknown_items.fetch_sub(1, Ordering::Relaxed);
+        self.redraw(true);
+    }
+
+    /// Replaces the `size` an item was counted with by `new_size`, for items whose progress is
+    /// counted in other bytes than the ones of the input, like the uncompressed size of the entries
+    /// of a zip archive.
+    pub fn replace_item_size(&self, size: Option<u64>, new_size: u64) {
+        match size {
+            Some(size) => {
+                self.known_bytes.fetch_sub(size, Ordering::Relaxed);
+            }
+            None => {
+                self.unknown_items.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        self.known_bytes.fetch_add(new_size, Ordering::Relaxed);
+        self.redraw(true);
+    }
+
+    fn add_bytes(&self, bytes: u64) {
+        self.done_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.redraw(false);
+    }
+
+    /// Marks one more item as done, whether it succeeded or not
+    pub fn finish_item(&self) {
+        self.done_items.fetch_add(1, Ordering::Relaxed);
+        self.redraw(true);
+    }
+
+    /// Removes the status line
+    pub fn finish(&self) {
+        set_status_line(None);
+    }
+
+    /// Text of the status line, like "file 7/120, 43% total", or "| file 7/120, 1.20 MiB at 300 kiB/s"
+    /// while the total is unknown
+    pub fn status(&self) -> String {
+        self.format_status(self.started.elapsed())
+    }
+
+    fn format_status(&self, elapsed: Duration) -> String {
+        if let Some(template) = PROGRESS_TEMPLATE.get() {
+            return template.render(self, elapsed);
+        }
+
+        let done_bytes = self.done_bytes.load(Ordering::Relaxed);
+        // The item being processed, unless all of them are done
+        let current_item = (self.done_items.load(Ordering::Relaxed) + 1).min(self.total_items);
+        // Counting items is only noise with a single one
+        let item = (self.total_items > 1).then(|| format!("file {current_item}/{}, ", self.total_items));
+        let item = item.as_deref().unwrap_or_default();
+
+        match self.total_bytes() {
+            Some(total_bytes) => {
+                // Seeking readers may read some bytes twice, don't go past the total
+                let done_bytes = done_bytes.min(total_bytes);
+                // Nothing to read means nothing left to do
+                let percentage = (done_bytes * 100).checked_div(total_bytes).unwrap_or(100);
+                format!("{item}{percentage}% total")
+            }
+            None => {
+                let frame = SPINNER_FRAMES[self.redraws.load(Ordering::Relaxed) % SPINNER_FRAMES.len()];
+                let throughput = (done_bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+                format!(
+                    "{frame} {item}{} at {}/s",
+                    Bytes::new(done_bytes).to_string().trim_start(),
+                    Bytes::new(throughput).to_string().trim_start()
+                )
+            }
+        }
+    }
+
+    fn redraw(&self, force: bool) {
+        let mut last_redraw = self.last_redraw.lock().unwrap();
+        if force || last_redraw.is_none_or(|instant| instant.elapsed() >= REDRAW_INTERVAL) {
+            *last_redraw = Some(Instant::now());
+            self.redraws.fetch_add(1, Ordering::Relaxed);
+            set_status_line(Some(self.status()));
+        }
+    }
+}
+
+/// Layout of the status line given with `--progress-template`, like "{spinner} {bytes} of
+/// {total_bytes}". Braces are written "{{" and "}}".
+#[derive(Debug, PartialEq)]
+pub struct ProgressTemplate(Vec<TemplatePart>);
+
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Token(Token),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Token {
+    Spinner,
+    /// Number of the item being processed
+    File,
+    /// Number of items of the batch
+    Files,
+    Percent,
+    Bytes,
+    TotalBytes,
+    Throughput,
+    Elapsed,
+}
+
+impl ProgressTemplate {
+    pub fn parse(template: &str) -> crate::Result<Self> {
+        let error = |detail: String| {
+            let tokens = TEMPLATE_TOKENS.map(|(name, _)| format!("{{{name}}}")).join(", ");
+            FinalError::with_title(format!("Invalid progress template {template:?}"))
+                .detail(detail)
+                .hint(format!("Supported tokens are: {tokens}"))
+                .hint("Braces outside of tokens are written '{{' and '}}'")
+        };
+
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(char) = chars.next() {
+            match char {
+                '{' | '}' if chars.next_if_eq(&char).is_some() => text.push(char),
+                '{' => {
+                    let mut name = String::new();
+                    let closed = loop {
+                        match chars.next() {
+                            Some('}') => break true,
+                            Some(char) => name.push(char),
+                            None => break false,
+                        }
+                    };
+                    if !closed {
+                        return Err(error(format!("Unclosed token {{{name}")).into());
+                    }
+                    let Some(&(_, token)) = TEMPLATE_TOKENS.iter().find(|(token, _)| *token == name) else {
+                        return Err(error(format!("Unknown token {{{name}}}")).into());
+                    };
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Token(token));
+                }
+                '}' => return Err(error("Unmatched '}'".to_owned()).into()),
+                _ => text.push(char),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+
+        Ok(Self(parts))
+    }
+
+    /// Fills the tokens with the state of `progress`, the ones depending on the total are "?" while
+    /// it's unknown.
+    fn render(&self, progress: &AggregateProgress, elapsed: Duration) -> String {
+        let total_bytes = progress.total_bytes();
+        let done_bytes = progress.done_bytes.load(Ordering::Relaxed);
+        // Seeking readers may read some bytes twice, don't go past the total
+        let done_bytes = total_bytes.map_or(done_bytes, |total_bytes| done_bytes.min(total_bytes));
+        let bytes = |bytes: u64| Bytes::new(bytes).to_string().trim_start().to_owned();
+
+        self.0
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Token(token) => match token {
+                    Token::Spinner => {
+                        let redraws = progress.redraws.load(Ordering::Relaxed);
+                        SPINNER_FRAMES[redraws % SPINNER_FRAMES.len()].to_string()
+                    }
+                    Token::File => (progress.done_items.load(Ordering::Relaxed) + 1)
+                        .min(progress.total_items)
+                        .to_string(),
+                    Token::Files => progress.total_items.to_string(),
+                    // Nothing to read means nothing left to do
+                    Token::Percent => total_bytes.map_or("?%".to_owned(), |total_bytes| {
+                        format!("{}%", (done_bytes * 100).checked_div(total_bytes).unwrap_or(100))
+                    }),
+                    Token::Bytes => bytes(done_bytes),
+                    Token::TotalBytes => total_bytes.map_or("?".to_owned(), bytes),
+                    Token::Throughput => {
+                        let throughput = (done_bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+                        format!("{}/s", bytes(throughput))
+                    }
+                    Token::Elapsed => format!("{}s", elapsed.as_secs()),
+                },
+            })
+            .collect()
+    }
+}
+
+/// Adds the bytes read from `inner` to an [`AggregateProgress`], if any.
+///
+/// When the size of `inner` is unknown, it's given to the progress once the end is reached.
+pub struct ProgressReader<R> {
+    inner: R,
+    progress: Option<Arc<AggregateProgress>>,
+    /// Bytes read so far, only counted while the size is unknown
+    unknown_size_read: Option<u64>,
+}
+
+impl<R> ProgressReader<R> {
+    pub fn new(inner: R, progress: Option<Arc<AggregateProgress>>, size: Option<u64>) -> Self {
+        let unknown_size_read = size.is_none().then_some(0);
+        Self {
+            inner,
+            progress,
+            unknown_size_read,
+        }
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(progress) = &self.progress {
+            progress.add_bytes(read as u64);
+
+            match (read, &mut self.unknown_size_read) {
+                (0, Some(total)) if !buf.is_empty() => {
+                    progress.resolve_item_size(*total);
+                    self.unknown_size_read = None;
+                }
+                (_, Some(total)) => *total += read as u64,
+                _ => {}
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_progress() {
+        let sizes = [100, 300, 600];
+        let progress = Arc::new(AggregateProgress::new(sizes.map(Some)));
+        assert_eq!(progress.total_bytes(), Some(sizes.iter().sum::<u64>()));
+        assert_eq!(progress.status(), "file 1/3, 0% total");
+
+        for size in sizes {
+            let data = vec![0; size as usize];
+            let mut reader = ProgressReader::new(data.as_slice(), Some(progress.clone()), Some(size));
+            io::copy(&mut reader, &mut io::sink()).unwrap();
+            progress.finish_item();
+        }
+        assert_eq!(
+            Some(progress.done_bytes.load(Ordering::Relaxed)),
+            progress.total_bytes()
+        );
+        assert_eq!(progress.status(), "file 3/3, 100% total");
+        progress.finish();
+
+        assert_eq!(AggregateProgress::new([]).status(), "100% total");
+        assert_eq!(AggregateProgress::new([Some(10)]).status(), "0% total");
+    }
+
+    #[test]
+    fn test_aggregate_progress_with_unknown_total() {
+        let progress = AggregateProgress::new([Some(1000), None]);
+        assert_eq!(progress.total_bytes(), None);
+
+        progress.done_bytes.store(2000, Ordering::Relaxed);
+        let status = progress.format_status(Duration::from_secs(2));
+        // A spinner instead of a percentage
+        assert!(SPINNER_FRAMES.iter().any(|&frame| status.starts_with(frame)));
+        assert!(!status.contains('%'));
+        assert!(status[1..].starts_with(" file 1/2, "), "{status}");
+        assert!(status.contains(" at ") && status.ends_with("B/s"), "{status}");
+
+        // Known once the item is fully read, without drawing the status line
+        progress.known_bytes.fetch_add(3000, Ordering::Relaxed);
+        progress.unknown_items.fetch_sub(1, Ordering::Relaxed);
+        assert_eq!(progress.total_bytes(), Some(4000));
+        assert_eq!(progress.format_status(Duration::from_secs(2)), "file 1/2, 50% total");
+    }
+
+    #[test]
+    fn test_progress_template() {
+        let template =
+            ProgressTemplate::parse("{spinner} {{{file}/{files}}} {percent}, {bytes} of {total_bytes}").unwrap();
+
+        let progress = AggregateProgress::new([Some(1000), Some(3000)]);
+        progress.done_items.store(1, Ordering::Relaxed);
+        progress.done_bytes.store(2000, Ordering::Relaxed);
+        assert_eq!(
+            template.render(&progress, Duration::from_secs(2)),
+            "| {2/2} 50%, 2.00 kiB of 4.00 kiB"
+        );
+
+        // Tokens depending on the total wait for it
+        let progress = AggregateProgress::new([None]);
+        progress.done_bytes.store(2000, Ordering::Relaxed);
+        let template = ProgressTemplate::parse("{percent} of {total_bytes} at {throughput} for {elapsed}").unwrap();
+        assert_eq!(
+            template.render(&progress, Duration::from_secs(2)),
+            "?% of ? at 1.00 kiB/s for 2s"
+        );
+    }
+
+    #[test]
+    fn test_invalid_progress_template() {
+        for template in ["{percentage}", "{percent", "percent}", "{}"] {
+            assert!(ProgressTemplate::parse(template).is_err(), "{template}");
+        }
+    }
+}
@@ -97,10 +97,12 @@ pub fn ask_file_conflict_operation(path: &Path, question_action: QuestionAction)
 
 /// Create the file if it doesn't exist and if it does then ask to overwrite it.
 /// If the user doesn't want to overwrite then we return [`Ok(None)`]
+/// An overwritten file is moved to the trash first if `trash`
 pub fn ask_to_create_file(
     path: &Path,
     question_policy: QuestionPolicy,
     question_action: QuestionAction,
+    trash: bool,
 ) -> Result<Option<fs::File>> {
     match fs::OpenOptions::new().write(true).create_new(true).open(path) {
         Ok(w) => Ok(Some(w)),
@@ -114,7 +116,7 @@ pub fn ask_to_create_file(
             match action {
                 FileConflitOperation::Merge => Ok(Some(fs::File::create(path)?)),
                 FileConflitOperation::Overwrite => {
-                    utils::remove_file_or_dir(path)?;
+                    utils::trash::remove_replaced(path, trash)?;
                     Ok(Some(fs::File::create(path)?))
                 }
                 FileConflitOperation::Cancel => Ok(None),
@@ -0,0 +1,42 @@
+//! Moving the files replaced on conflicts to the trash, with `--trash`.
+//!
+//! The trash is the one of the desktop: the recycle bin on Windows, the Finder trash on macOS and
+//! the one of the FreeDesktop.org specification on Linux and BSD.
+
+use std::path::Path;
+
+use super::{remove_file_or_dir, EscapedPathDisplay};
+#[cfg(any(windows, all(unix, not(any(target_os = "ios", target_os = "android")))))]
+use crate::error::FinalError;
+#[cfg(not(any(windows, all(unix, not(any(target_os = "ios", target_os = "android"))))))]
+use crate::utils::logger::warning;
+
+/// Removes the file or directory replaced on a conflict, or moves it to the trash if `trash`.
+///
+/// Fails if it can't be moved to the trash, like when there is no trash on its filesystem. It's
+/// only removed instead, with a warning, on platforms without any trash.
+pub fn remove_replaced(path: &Path, trash: bool) -> crate::Result<()> {
+    if trash {
+        return move_to_trash(path);
+    }
+    remove_file_or_dir(path)
+}
+
+#[cfg(any(windows, all(unix, not(any(target_os = "ios", target_os = "android")))))]
+fn move_to_trash(path: &Path) -> crate::Result<()> {
+    ::trash::delete(path).map_err(|err| {
+        FinalError::with_title(format!("Failed to move {} to the trash", EscapedPathDisplay::new(path)))
+            .detail(err.to_string())
+            .hint("Leave --trash out to delete the replaced files instead")
+            .into()
+    })
+}
+
+#[cfg(not(any(windows, all(unix, not(any(target_os = "ios", target_os = "android"))))))]
+fn move_to_trash(path: &Path) -> crate::Result<()> {
+    warning(format!(
+        "There is no trash on this platform, removing {} instead",
+        EscapedPathDisplay::new(path)
+    ));
+    remove_file_or_dir(path)
+}
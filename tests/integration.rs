@@ -2,7 +2,7 @@
 mod utils;
 
 use std::{
-    io::Write,
+    io::{Read, Write},
     iter::once,
     path::{Path, PathBuf},
 };
@@ -15,6 +15,7 @@ use parse_display::Display;
 use pretty_assertions::assert_eq;
 use proptest::sample::size_range;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
+use sha2::Digest;
 use tempfile::tempdir;
 use test_strategy::{proptest, Arbitrary};
 
@@ -716,11 +717,13 @@ fn symlink_pack_and_unpack(
         .assert()
         .success();
 
+    // The symlink target is absolute, which is skipped by default
     crate::utils::cargo_bin()
         .arg("decompress")
         .arg(archive)
         .arg("-d")
         .arg(&dest_files_path)
+        .arg("--absolute-symlinks=allow")
         .assert()
         .success();
 
@@ -1000,11 +1003,7 @@ fn compressing_archive_with_two_archive_formats() {
             .clone();
 
         let stderr = output.stderr.to_str().unwrap();
-        assert!(memmem::find(
-            stderr.as_bytes(),
-            b"can only be used at the start of the file extension",
-        )
-        .is_some());
+        assert!(memmem::find(stderr.as_bytes(), b"are both archive formats").is_some());
 
         crate::utils::cargo_bin()
             .args([
@@ -1058,7 +1057,7 @@ fn fail_when_compressing_archive_as_the_second_extension() {
         let stderr = output.stderr.to_str().unwrap();
         assert!(memmem::find(
             stderr.as_bytes(),
-            format!("'{archive_format}' can only be used at the start of the file extension").as_bytes(),
+            format!("The archive format '.{archive_format}' must come first").as_bytes(),
         )
         .is_some());
     }
@@ -1087,3 +1086,2933 @@ fn sevenz_list_should_not_failed() {
         .assert()
         .success();
 }
+
+#[test]
+fn list_long_with_custom_date_format() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let file = root_path.join("file.txt");
+    fs::write(&file, "contents").unwrap();
+    // 2001-09-09 12:00:00 UTC, far enough from midnight to keep the date the same in every timezone
+    filetime_creation::set_file_mtime(&file, filetime_creation::FileTime::from_unix_time(1_000_036_800, 0)).unwrap();
+
+    let archive = root_path.join("archive.tar");
+    ouch!("-A", "c", &file, &archive);
+
+    let output = crate::utils::cargo_bin()
+        .args(["list", "--long", "--date-format", "%Y/%m/%d"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = output.stdout.to_str().unwrap();
+    assert!(stdout.contains("2001/09/09"), "date format was not applied: {stdout}");
+    assert!(stdout.contains("file.txt"));
+
+    let output = crate::utils::cargo_bin()
+        .args(["list", "--long", "--date-format", "%Y-%"])
+        .arg(&archive)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = output.stderr.to_str().unwrap();
+    assert!(
+        stderr.contains("Invalid date format '%Y-%'"),
+        "unexpected error: {stderr}"
+    );
+}
+
+#[test]
+fn compress_paths_read_from_stdin_with_read0() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let names = ["explicit.txt", "a.txt", "new\nline.txt"];
+    for name in names {
+        fs::write(root_path.join(name), name).unwrap();
+    }
+
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["compress", "--read0", "explicit.txt", "archive.tar"])
+        .write_stdin("a.txt\0new\nline.txt\0")
+        .assert()
+        .success();
+
+    let out = root_path.join("out");
+    ouch!("-A", "d", root_path.join("archive.tar"), "-d", &out);
+
+    for name in names {
+        assert_eq!(fs::read_to_string(out.join(name)).unwrap(), name);
+    }
+}
+
+#[test]
+fn compress_only_paths_read_from_stdin_with_read0() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    fs::write(root_path.join("a.txt"), "a").unwrap();
+    fs::write(root_path.join("b.txt"), "b").unwrap();
+
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["compress", "--read0", "-", "archive.zip"])
+        .write_stdin("a.txt\0b.txt")
+        .assert()
+        .success();
+
+    let out = root_path.join("out");
+    ouch!("-A", "d", root_path.join("archive.zip"), "-d", &out);
+
+    assert_eq!(fs::read_to_string(out.join("a.txt")).unwrap(), "a");
+    assert_eq!(fs::read_to_string(out.join("b.txt")).unwrap(), "b");
+}
+
+#[test]
+fn decompress_tar_with_current_dir_entries() {
+    fn append(builder: &mut tar::Builder<fs::File>, name: &[u8], entry_type: tar::EntryType, contents: &[u8]) {
+        let mut header = tar::Header::new_ustar();
+        // Write the name directly, `Header::set_path` would normalize it
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(entry_type);
+        header.set_mode(0o755);
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        builder.append(&header, contents).unwrap();
+    }
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let archive = root_path.join("archive.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    append(&mut builder, b"./", tar::EntryType::Directory, b"");
+    append(&mut builder, b"./dir/", tar::EntryType::Directory, b"");
+    append(&mut builder, b"./dir/./file.txt", tar::EntryType::Regular, b"contents");
+    append(&mut builder, b"./other.txt", tar::EntryType::Regular, b"other");
+    builder.finish().unwrap();
+
+    let out = root_path.join("out");
+    ouch!("-A", "d", &archive, "-d", &out);
+
+    let mut entries = fs::read_dir(&out)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(entries, ["dir", "other.txt"]);
+    assert_eq!(fs::read_to_string(out.join("dir/file.txt")).unwrap(), "contents");
+}
+
+#[test]
+fn decompress_with_replaced_suffix() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    fs::write(root_path.join("data.min.js"), "minified").unwrap();
+    fs::write(root_path.join("data.txt"), "text").unwrap();
+    ouch!(
+        "-A",
+        "c",
+        root_path.join("data.min.js"),
+        root_path.join("data.min.js.gz")
+    );
+    ouch!("-A", "c", root_path.join("data.txt"), root_path.join("data.txt.zst"));
+
+    let out = root_path.join("out");
+    ouch!(
+        "-A",
+        "d",
+        root_path.join("data.min.js.gz"),
+        root_path.join("data.txt.zst"),
+        "-d",
+        &out,
+        "--replace-suffix",
+        ".min.js=.js"
+    );
+
+    // The suffix is replaced where present, other names are left unchanged
+    assert_eq!(fs::read_to_string(out.join("data.js")).unwrap(), "minified");
+    assert_eq!(fs::read_to_string(out.join("data.txt")).unwrap(), "text");
+    assert!(!out.join("data.min.js").exists());
+
+    // Invalid rules are rejected
+    crate::utils::cargo_bin()
+        .args(["-A", "d", "--replace-suffix", ".min.js"])
+        .arg(root_path.join("data.min.js.gz"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn decompress_single_file_with_output() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    fs::write(root_path.join("input"), "contents").unwrap();
+    ouch!("-A", "c", root_path.join("input"), root_path.join("input.xz"));
+    ouch!(
+        "-A",
+        "d",
+        root_path.join("input.xz"),
+        "-o",
+        root_path.join("renamed.txt")
+    );
+    assert_eq!(fs::read_to_string(root_path.join("renamed.txt")).unwrap(), "contents");
+
+    // A single output path can't hold multiple input files
+    crate::utils::cargo_bin()
+        .args(["-A", "d", "-o", "out"])
+        .arg(root_path.join("input.xz"))
+        .arg(root_path.join("input.xz"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn compress_with_stored_name() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let input = root_path.join("tmp1234");
+    fs::write(&input, "contents").unwrap();
+
+    for format in ["tar", "zip"] {
+        let archive = root_path.join(format!("archive.{format}"));
+        let out = root_path.join(format!("out-{format}"));
+        ouch!("-A", "c", &input, &archive, "--name", "report.txt");
+        ouch!("-A", "d", &archive, "-d", &out);
+
+        // The stored name is extracted instead of the source basename
+        assert_eq!(fs::read_to_string(out.join("report.txt")).unwrap(), "contents");
+        assert!(!out.join("tmp1234").exists());
+    }
+
+    let archive = root_path.join("input.gz");
+    ouch!("-A", "c", &input, &archive, "--name", "report.txt");
+    let decoder = flate2::read::GzDecoder::new(fs::File::open(&archive).unwrap());
+    assert_eq!(decoder.header().unwrap().filename(), Some(&b"report.txt"[..]));
+
+    // Names with path separators are rejected
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--name", "dir/report.txt"])
+        .arg(&input)
+        .arg(root_path.join("rejected.tar"))
+        .assert()
+        .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn tar_symlinks_with_absolute_targets_policies() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let src = root_path.join("src");
+    fs::create_dir_all(src.join("dir")).unwrap();
+    fs::write(src.join("file"), "contents").unwrap();
+    std::os::unix::fs::symlink("../file", src.join("dir/relative")).unwrap();
+    std::os::unix::fs::symlink("/etc/hostname", src.join("dir/absolute")).unwrap();
+
+    let archive = root_path.join("archive.tar");
+    ouch!("-A", "c", &src, &archive);
+
+    let extract = |policy: &str| {
+        let out = root_path.join(format!("out-{policy}"));
+        ouch!("-A", "d", &archive, "-d", &out, "--absolute-symlinks", policy);
+        out.join("src/dir")
+    };
+
+    for policy in ["allow", "skip", "relativize"] {
+        // Relative targets are kept verbatim
+        let dir = extract(policy);
+        assert_eq!(fs::read_link(dir.join("relative")).unwrap(), Path::new("../file"));
+        assert_eq!(fs::read_to_string(dir.join("relative")).unwrap(), "contents");
+
+        let absolute = dir.join("absolute");
+        match policy {
+            "allow" => assert_eq!(fs::read_link(absolute).unwrap(), Path::new("/etc/hostname")),
+            "skip" => assert!(fs::symlink_metadata(absolute).is_err()),
+            _ => assert_eq!(fs::read_link(absolute).unwrap(), Path::new("../../etc/hostname")),
+        }
+    }
+
+    // Skipping is the default
+    let out = root_path.join("out-default");
+    ouch!("-A", "d", &archive, "-d", &out);
+    assert!(fs::symlink_metadata(out.join("src/dir/absolute")).is_err());
+    assert!(fs::symlink_metadata(out.join("src/dir/relative")).is_ok());
+}
+
+#[test]
+fn decompress_continues_after_corrupt_archive() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let corrupt = root_path.join("corrupt.tar.gz");
+    fs::write(&corrupt, "not a gzip stream").unwrap();
+
+    fs::write(root_path.join("file.txt"), "contents").unwrap();
+    let valid = root_path.join("valid.tar.gz");
+    ouch!("-A", "c", root_path.join("file.txt"), &valid);
+
+    let out = root_path.join("out");
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "d"])
+        .args([&corrupt, &valid])
+        .arg("-d")
+        .arg(&out)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = output.stderr.to_str().unwrap();
+    assert!(memmem::find(stderr.as_bytes(), b"Failed to decompress 1 of 2 files").is_some());
+
+    // The second archive is still extracted
+    assert_eq!(fs::read_to_string(out.join("file.txt")).unwrap(), "contents");
+}
+
+#[cfg(unix)]
+#[test]
+fn compress_with_fixed_modes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let src = root_path.join("src");
+    fs::create_dir_all(src.join("private")).unwrap();
+    fs::write(src.join("script.sh"), "echo").unwrap();
+    fs::write(src.join("private/secret"), "secret").unwrap();
+    fs::set_permissions(src.join("script.sh"), std::fs::Permissions::from_mode(0o700)).unwrap();
+    fs::set_permissions(src.join("private/secret"), std::fs::Permissions::from_mode(0o600)).unwrap();
+    fs::set_permissions(src.join("private"), std::fs::Permissions::from_mode(0o700)).unwrap();
+
+    let tar_path = root_path.join("archive.tar");
+    ouch!("-A", "c", &src, &tar_path, "--mode", "files=644,dirs=755");
+    let mut archive = tar::Archive::new(fs::File::open(&tar_path).unwrap());
+    for entry in archive.entries().unwrap() {
+        let entry = entry.unwrap();
+        let expected = if entry.header().entry_type().is_dir() {
+            0o755
+        } else {
+            0o644
+        };
+        assert_eq!(entry.header().mode().unwrap() & 0o7777, expected, "{:?}", entry.path());
+    }
+
+    let zip_path = root_path.join("archive.zip");
+    ouch!("-A", "c", &src, &zip_path, "--mode", "files=644,dirs=755");
+    let mut archive = zip::ZipArchive::new(fs::File::open(&zip_path).unwrap()).unwrap();
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index).unwrap();
+        let expected = if entry.is_dir() { 0o755 } else { 0o644 };
+        assert_eq!(entry.unix_mode().unwrap() & 0o7777, expected, "{}", entry.name());
+    }
+
+    // Modes must be valid octal numbers
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--mode", "files=999"])
+        .arg(&src)
+        .arg(root_path.join("rejected.tar"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn compress_with_embedded_manifest() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("file.txt"), "contents").unwrap();
+
+    let archive = root_path.join("archive.tar.gz");
+    ouch!("-A", "c", root_path.join("file.txt"), &archive, "--embed-manifest");
+
+    let decoder = flate2::read::GzDecoder::new(fs::File::open(&archive).unwrap());
+    let mut archive = tar::Archive::new(decoder);
+    let mut entries = archive.entries().unwrap();
+
+    // The manifest comes first, before the inputs
+    let mut manifest = entries.next().unwrap().unwrap();
+    assert_eq!(manifest.path().unwrap(), Path::new(".ouch-manifest.json"));
+    let manifest: serde_json::Value = serde_json::from_reader(&mut manifest).unwrap();
+    assert_eq!(manifest["ouch_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(manifest["formats"], serde_json::json!(["tar", "gz"]));
+    assert_eq!(manifest["inputs"], serde_json::json!(["file.txt"]));
+    assert!(manifest["created"].is_string());
+
+    let file = entries.next().unwrap().unwrap();
+    assert_eq!(file.path().unwrap(), Path::new("file.txt"));
+
+    // Single file formats have nowhere to store it
+    crate::utils::cargo_bin()
+        .args(["-A", "c", "--embed-manifest"])
+        .arg(root_path.join("file.txt"))
+        .arg(root_path.join("file.txt.gz"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn lz4_formats_round_trip_and_are_detected() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let input = root_path.join("input");
+    let mut rng = SmallRng::seed_from_u64(0);
+    write_random_content(&mut fs::File::create(&input).unwrap(), &mut rng);
+
+    for (format, magic) in [
+        ("frame", [0x04, 0x22, 0x4D, 0x18]),
+        ("legacy", [0x02, 0x21, 0x4C, 0x18]),
+    ] {
+        let archive = root_path.join(format!("{format}.lz4"));
+        ouch!("-A", "c", &input, &archive, "--lz4-format", format);
+        assert_eq!(fs::read(&archive).unwrap()[..4], magic);
+
+        // Decompression detects the format by itself
+        let out = root_path.join(format!("out-{format}"));
+        ouch!("-A", "d", &archive, "-d", &out);
+        assert_eq!(fs::read(out.join(format)).unwrap(), fs::read(&input).unwrap());
+    }
+}
+
+#[test]
+fn decompress_to_memory_returns_file_contents() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("a.txt"), "first").unwrap();
+    fs::write(dir.join("sub/b.txt"), "second").unwrap();
+
+    let archive = root_path.join("archive.tar.gz");
+    ouch!("-A", "c", &dir, &archive);
+
+    let mut files = ouch::commands::decompress::decompress_to_memory(&archive, None, 1024).unwrap();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            (PathBuf::from("dir/a.txt"), b"first".to_vec()),
+            (PathBuf::from("dir/sub/b.txt"), b"second".to_vec()),
+        ]
+    );
+    // Nothing was written next to the archive
+    assert_eq!(fs::read_dir(root_path).unwrap().count(), 2);
+
+    // The limit covers all entries together, not each of them
+    assert!(ouch::commands::decompress::decompress_to_memory(&archive, None, 10).is_err());
+    assert!(ouch::commands::decompress::decompress_to_memory(&archive, None, 11).is_ok());
+}
+
+#[test]
+fn decompress_to_memory_leaves_out_parent_dir_entries() {
+    let temp_dir = tempdir().unwrap();
+    let archive = temp_dir.path().join("archive.tar");
+
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    for name in [&b"../../etc/x"[..], b"dir/../../y", b"dir/z"] {
+        let mut header = tar::Header::new_ustar();
+        // Write the name directly, `Header::set_path` refuses `..`
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(4);
+        header.set_cksum();
+        builder.append(&header, &b"data"[..]).unwrap();
+    }
+    builder.finish().unwrap();
+
+    let files = ouch::commands::decompress::decompress_to_memory(&archive, None, 1024).unwrap();
+    assert_eq!(files, [(PathBuf::from("dir/z"), b"data".to_vec())]);
+}
+
+/// Builds a zip like streaming writers do, with sizes and CRC left out of the local headers and
+/// stored in data descriptors after each entry instead.
+fn zip_with_data_descriptors(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+    // 1980-01-01, the earliest date of the format
+    const DATE: u16 = (1 << 5) | 1;
+
+    let mut zip = vec![];
+    let mut central_directory = vec![];
+    for (name, content) in entries {
+        let mut crc = flate2::Crc::new();
+        crc.update(content);
+        let crc = crc.sum();
+        let size = content.len() as u32;
+        let offset = zip.len() as u32;
+
+        // Local header, stored without compression
+        zip.extend(0x04034b50_u32.to_le_bytes());
+        zip.extend(20_u16.to_le_bytes());
+        zip.extend(DATA_DESCRIPTOR_FLAG.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend(DATE.to_le_bytes());
+        zip.extend([0; 12]);
+        zip.extend((name.len() as u16).to_le_bytes());
+        zip.extend([0; 2]);
+        zip.extend(name.as_bytes());
+        zip.extend(*content);
+
+        zip.extend(0x08074b50_u32.to_le_bytes());
+        zip.extend(crc.to_le_bytes());
+        zip.extend(size.to_le_bytes());
+        zip.extend(size.to_le_bytes());
+
+        central_directory.extend(0x02014b50_u32.to_le_bytes());
+        central_directory.extend(20_u16.to_le_bytes());
+        central_directory.extend(20_u16.to_le_bytes());
+        central_directory.extend(DATA_DESCRIPTOR_FLAG.to_le_bytes());
+        central_directory.extend([0; 4]);
+        central_directory.extend(DATE.to_le_bytes());
+        central_directory.extend(crc.to_le_bytes());
+        central_directory.extend(size.to_le_bytes());
+        central_directory.extend(size.to_le_bytes());
+        central_directory.extend((name.len() as u16).to_le_bytes());
+        central_directory.extend([0; 12]);
+        central_directory.extend(offset.to_le_bytes());
+        central_directory.extend(name.as_bytes());
+    }
+
+    let central_directory_offset = zip.len() as u32;
+    zip.extend(&central_directory);
+
+    zip.extend(0x06054b50_u32.to_le_bytes());
+    zip.extend([0; 4]);
+    zip.extend((entries.len() as u16).to_le_bytes());
+    zip.extend((entries.len() as u16).to_le_bytes());
+    zip.extend((central_directory.len() as u32).to_le_bytes());
+    zip.extend(central_directory_offset.to_le_bytes());
+    zip.extend([0; 2]);
+    zip
+}
+
+#[test]
+fn unpack_zip_with_data_descriptors() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let entries: [(&str, &[u8]); 2] = [("first.txt", b"first file"), ("dir/second.txt", b"the second file")];
+    let archive = root_path.join("streamed.zip");
+    fs::write(&archive, zip_with_data_descriptors(&entries)).unwrap();
+
+    // Seekable zips, read from the file, and zips loaded in memory when read from stdin
+    ouch!("-A", "d", &archive, "-d", root_path.join("out"));
+    crate::utils::cargo_bin()
+        .args(["-A", "d", "-", "--format", "zip", "-d"])
+        .arg(root_path.join("out-stdin"))
+        .pipe_stdin(&archive)
+        .unwrap()
+        .assert()
+        .success();
+
+    for out in ["out", "out-stdin"] {
+        for (name, content) in entries {
+            assert_eq!(fs::read(root_path.join(out).join(name)).unwrap(), content);
+        }
+    }
+
+    let mut files = ouch::commands::decompress::decompress_to_memory(&archive, None, 1024).unwrap();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            (PathBuf::from("dir/second.txt"), b"the second file".to_vec()),
+            (PathBuf::from("first.txt"), b"first file".to_vec()),
+        ]
+    );
+
+    // Sizes come from the central directory, the local headers have zeroes
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "l", "--long"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = output.stdout.to_str().unwrap();
+    for (name, size) in [("first.txt", "10.00"), ("dir/second.txt", "15.00")] {
+        let line = stdout.lines().find(|line| line.ends_with(name)).unwrap();
+        assert!(line.contains(size), "wrong size listed: {line}");
+    }
+}
+
+#[test]
+fn messages_are_kept_out_of_stdout() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("file.txt"), "contents").unwrap();
+
+    let archive = root_path.join("archive.tar.gz");
+    ouch!("-A", "c", root_path.join("file.txt"), &archive);
+
+    let decompress = |out: &str, extra_args: &[&str]| {
+        crate::utils::cargo_bin()
+            .args(["d", "--yes"])
+            .args(extra_args)
+            .arg(&archive)
+            .arg("-d")
+            .arg(root_path.join(out))
+            .assert()
+            .success()
+            .get_output()
+            .clone()
+    };
+
+    let output = decompress("out", &[]);
+    assert_eq!(output.stdout, b"");
+    assert!(memmem::find(&output.stderr, b"Successfully decompressed").is_some());
+
+    // For scripts that read the messages from stdout, along with the data
+    let output = decompress("out-stdout", &["--messages", "stdout"]);
+    assert!(memmem::find(&output.stdout, b"Successfully decompressed").is_some());
+    assert_eq!(output.stderr, b"");
+
+    // Listings are data, they stay on stdout
+    let output = crate::utils::cargo_bin()
+        .arg("l")
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stdout, b"file.txt").is_some());
+    assert_eq!(output.stderr, b"");
+}
+
+#[cfg(unix)]
+#[test]
+fn special_mode_bits_are_removed_unless_preserved() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let archive = root_path.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(6);
+    header.set_mode(0o4755);
+    builder.append_data(&mut header, "setuid", &b"binary"[..]).unwrap();
+    builder.into_inner().unwrap();
+
+    let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+    let output = crate::utils::cargo_bin()
+        .args(["d", "--yes"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(root_path.join("out"))
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(mode(root_path.join("out/setuid")), 0o755);
+    assert!(memmem::find(&output.stderr, b"--preserve-special-bits").is_some());
+
+    // The risk must be acknowledged, with --yes when not asked on a terminal
+    crate::utils::cargo_bin()
+        .args(["d", "--preserve-special-bits"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(root_path.join("out-refused"))
+        .assert()
+        .success();
+    assert!(!root_path.join("out-refused/setuid").exists());
+
+    ouch!(
+        "-A",
+        "d",
+        &archive,
+        "-d",
+        root_path.join("out-preserved"),
+        "--preserve-special-bits"
+    );
+    assert_eq!(mode(root_path.join("out-preserved/setuid")), 0o4755);
+}
+
+#[test]
+fn mtime_granularity_makes_archives_reproducible() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Same file with modification times differing past the second, as read on different platforms
+    let mtimes = [(1_000_000_000, 123_456_789), (1_000_000_009, 0)];
+    for (i, (secs, nanos)) in mtimes.into_iter().enumerate() {
+        let dir = root_path.join(format!("build{i}/dir"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file.txt"), "contents").unwrap();
+        let mtime = filetime_creation::FileTime::from_unix_time(secs, nanos);
+        filetime_creation::set_file_mtime(dir.join("file.txt"), mtime).unwrap();
+        filetime_creation::set_file_mtime(&dir, mtime).unwrap();
+    }
+
+    for format in ["tar", "zip"] {
+        let build = |i: usize, granularity: &str| {
+            let archive = root_path.join(format!("build{i}-{granularity}.{format}"));
+            ouch!(
+                "-A",
+                "c",
+                root_path.join(format!("build{i}/dir")),
+                &archive,
+                "--mtime-granularity",
+                granularity
+            );
+            fs::read(archive).unwrap()
+        };
+
+        assert_ne!(build(0, "1"), build(1, "1"));
+        assert_eq!(build(0, "10"), build(1, "10"));
+    }
+
+    // Sub-second parts are never stored
+    let archive = root_path.join("build0-1.tar");
+    let mut archive = tar::Archive::new(fs::File::open(archive).unwrap());
+    for entry in archive.entries().unwrap() {
+        assert_eq!(entry.unwrap().header().mtime().unwrap(), 1_000_000_000);
+    }
+}
+
+#[test]
+fn list_shows_tar_global_metadata() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Like the header `git archive` writes, each record starts with its own length
+    let records = b"29 comment=built from abc123\n";
+    let archive = root_path.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_size(records.len() as u64);
+    builder
+        .append_data(&mut header, "pax_global_header", &records[..])
+        .unwrap();
+    let mut header = tar::Header::new_ustar();
+    header.set_size(8);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, "file.txt", &b"contents"[..]).unwrap();
+    builder.into_inner().unwrap();
+
+    for args in [&["l"][..], &["l", "--tree"]] {
+        let output = crate::utils::cargo_bin()
+            .args(args)
+            .arg(&archive)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let stdout = output.stdout.to_str().unwrap();
+        assert!(stdout.contains("Archive comment: built from abc123"), "{stdout}");
+        assert!(stdout.contains("file.txt"));
+        assert!(!stdout.contains("pax_global_header"));
+    }
+
+    // The header isn't extracted as a file
+    ouch!("-A", "d", &archive, "-d", root_path.join("out"));
+    assert_eq!(fs::read_dir(root_path.join("out")).unwrap().count(), 1);
+}
+
+#[test]
+fn decompress_with_stripped_trailing_components() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("a")).unwrap();
+    fs::create_dir_all(dir.join("b")).unwrap();
+    fs::write(dir.join("a/file"), "first").unwrap();
+    fs::write(dir.join("b/file"), "second").unwrap();
+
+    for format in ["tar", "zip"] {
+        let archive = root_path.join(format!("archive.{format}"));
+        ouch!("-A", "c", &dir, &archive);
+
+        // "dir/a/file" and "dir/b/file" land at "dir/a" and "dir/b", "dir" itself is skipped
+        let out = root_path.join(format!("out-{format}"));
+        ouch!("-A", "d", &archive, "-d", &out, "--strip-trailing-components", "1");
+        assert_eq!(fs::read(out.join("dir/a")).unwrap(), b"first");
+        assert_eq!(fs::read(out.join("dir/b")).unwrap(), b"second");
+        assert!(out.join("dir").is_dir());
+
+        // Both files land at "dir", the first one is kept and the other one is skipped
+        let out = root_path.join(format!("out-{format}-collisions"));
+        crate::utils::cargo_bin()
+            .args(["-A", "d", "--no", "--strip-trailing-components", "2"])
+            .arg(&archive)
+            .arg("-d")
+            .arg(&out)
+            .assert()
+            .success();
+        let contents = fs::read(out.join("dir")).unwrap();
+        assert!(
+            [&b"first"[..], b"second"].contains(&contents.as_slice()),
+            "{contents:?}"
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn stripped_entries_cant_escape_through_symlinks() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let outside = root_path.join("outside");
+    fs::create_dir(&outside).unwrap();
+
+    // Once stripped, a symlink "link" to a directory outside of the destination, then
+    // "link/evil.txt" written through it
+    let archive = root_path.join("escape.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "link/stripped", "../outside").unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "link/evil.txt/stripped", &b"evil"[..])
+        .unwrap();
+    builder.finish().unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "d", "--yes", "--strip-trailing-components", "1"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(root_path.join("out"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"outside of the output directory").is_some());
+    assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+}
+
+#[test]
+fn unpack_zip_with_wrong_crc() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let content = b"corrupted content";
+    let mut zip = zip_with_data_descriptors(&[("good.txt", b"fine"), ("bad.txt", content)]);
+
+    // Flip the stored CRC32 of "bad.txt", in its data descriptor and central directory record
+    let mut crc = flate2::Crc::new();
+    crc.update(content);
+    let stored_crc = crc.sum().to_le_bytes();
+    while let Some(position) = memmem::find(&zip, &stored_crc) {
+        zip[position..position + 4].copy_from_slice(&(!crc.sum()).to_le_bytes());
+    }
+
+    let archive = root_path.join("archive.zip");
+    fs::write(&archive, zip).unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .args(["-A", "d", "--yes"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(root_path.join("out"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"The content of 'bad.txt' doesn't match its CRC32").is_some());
+
+    ouch!(
+        "-A",
+        "d",
+        &archive,
+        "-d",
+        root_path.join("out-unchecked"),
+        "--no-crc-check"
+    );
+    assert_eq!(fs::read(root_path.join("out-unchecked/good.txt")).unwrap(), b"fine");
+    assert_eq!(fs::read(root_path.join("out-unchecked/bad.txt")).unwrap(), content);
+
+    // Deflated entries are decoded by ouch, encrypted ones by the zip crate, which checks their CRC32
+    // by itself
+    for encrypted in [false, true] {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let mut options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        if encrypted {
+            use zip::unstable::write::FileOptionsExt;
+            options = options.with_deprecated_encryption(b"password");
+        }
+        writer.start_file("bad.txt", options).unwrap();
+        writer.write_all(content).unwrap();
+        let mut zip = writer.finish().unwrap().into_inner();
+
+        // Only the low byte is changed, the high one is checked along with the password
+        let stored_crc = crc.sum().to_le_bytes();
+        while let Some(position) = memmem::find(&zip, &stored_crc) {
+            zip[position] ^= 1;
+        }
+        let archive = root_path.join(format!("deflated-{encrypted}.zip"));
+        fs::write(&archive, zip).unwrap();
+
+        let out = root_path.join(format!("out-deflated-{encrypted}"));
+        let output = crate::utils::cargo_bin()
+            .args(["-A", "d", "--yes", "--password", "password"])
+            .arg(&archive)
+            .arg("-d")
+            .arg(&out)
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        assert!(memmem::find(&output.stderr, b"The content of 'bad.txt' doesn't match its CRC32").is_some());
+
+        ouch!(
+            "-A",
+            "d",
+            &archive,
+            "-d",
+            &out,
+            "--no-crc-check",
+            "--password",
+            "password"
+        );
+        assert_eq!(fs::read(out.join("bad.txt")).unwrap(), content);
+    }
+}
+
+#[test]
+fn fail_on_warning_exits_with_an_error() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("file.txt"), "contents").unwrap();
+
+    // Xz can't store the name of its input, so it's ignored with a warning
+    let compress = |output: &str, extra_args: &[&str]| {
+        crate::utils::cargo_bin()
+            .args(["c", "--yes", "--name", "renamed.txt"])
+            .args(extra_args)
+            .arg(root_path.join("file.txt"))
+            .arg(root_path.join(output))
+            .assert()
+    };
+
+    compress("without-flag.xz", &[]).success();
+
+    let output = compress("with-flag.xz", &["--fail-on-warning"])
+        .failure()
+        .get_output()
+        .clone();
+    // The operation still completed and its warning was printed
+    assert!(root_path.join("with-flag.xz").exists());
+    assert!(memmem::find(&output.stderr, b"ignoring --name").is_some());
+    assert!(memmem::find(&output.stderr, b"Warnings were emitted").is_some());
+}
+
+#[test]
+fn decompress_gzip_with_multiple_members() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Blocked gzip, like the output of `pigz --independent` split in members or `bgzip`, is a
+    // sequence of gzip members that must all be decompressed
+    let gzip_member = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    let blocks: [&[u8]; 3] = [b"first block\n", b"second block\n", b"third block\n"];
+    let archive = root_path.join("blocked.txt.gz");
+    fs::write(
+        &archive,
+        blocks.iter().flat_map(|block| gzip_member(block)).collect::<Vec<_>>(),
+    )
+    .unwrap();
+
+    ouch!("d", &archive, "-d", root_path.join("out"));
+    assert_eq!(fs::read(root_path.join("out/blocked.txt")).unwrap(), blocks.concat());
+
+    // Archives inside of blocked gzip are listed entirely as well
+    let mut builder = tar::Builder::new(vec![]);
+    for (index, block) in blocks.iter().enumerate() {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(block.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, format!("file{index}.txt"), *block)
+            .unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+    let (head, tail) = tar.split_at(512 * 2);
+    let archive = root_path.join("blocked.tar.gz");
+    fs::write(&archive, [gzip_member(head), gzip_member(tail)].concat()).unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .args(["l", "--yes"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stdout, b"file2.txt").is_some());
+}
+
+#[test]
+fn compress_derives_output_path_from_format() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir(root_path.join("dir")).unwrap();
+    fs::write(root_path.join("dir/file.txt"), "contents").unwrap();
+    fs::write(root_path.join("other.txt"), "other").unwrap();
+
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["c", "--yes", "dir/", "--format", "tar.zst"])
+        .assert()
+        .success();
+    assert!(root_path.join("dir.tar.zst").is_file());
+
+    ouch!("d", root_path.join("dir.tar.zst"), "-d", root_path.join("out"));
+    assert_eq!(fs::read(root_path.join("out/dir/file.txt")).unwrap(), b"contents");
+
+    // Without --format the output path is needed for the formats
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["c", "--yes", "other.txt"])
+        .assert()
+        .failure();
+
+    // The last of multiple inputs isn't overwritten as if it was the output
+    let output = crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["c", "--yes", "other.txt", "dir", "--format", "tar.zst"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"Missing the output path").is_some());
+    assert!(root_path.join("dir/file.txt").is_file());
+
+    fs::write(root_path.join("last.txt"), "last").unwrap();
+    for args in [
+        ["c", "--yes", "other.txt", "last.txt", "--format", "tar.zst"],
+        ["c", "--yes", "other.txt", "last.txt", "--preset", "fast"],
+    ] {
+        let output = crate::utils::cargo_bin()
+            .current_dir(root_path)
+            .args(args)
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        assert!(memmem::find(&output.stderr, b"Missing the output path").is_some());
+        assert_eq!(fs::read(root_path.join("last.txt")).unwrap(), b"last");
+    }
+
+    // A new last path is the output
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["c", "--yes", "other.txt", "last.txt", "both", "--format", "tar.zst"])
+        .assert()
+        .success();
+    ouch!(
+        "d",
+        root_path.join("both"),
+        "--format",
+        "tar.zst",
+        "-d",
+        root_path.join("both-out")
+    );
+    assert_eq!(fs::read(root_path.join("both-out/last.txt")).unwrap(), b"last");
+}
+
+#[test]
+fn decompress_concatenated_zstd_frames() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Some producers split their output in many independent frames
+    let frame = |data: &[u8]| zstd::encode_all(data, 0).unwrap();
+    let archive = root_path.join("frames.txt.zst");
+    fs::write(&archive, [frame(b"first frame\n"), frame(b"second frame\n")].concat()).unwrap();
+
+    ouch!("d", &archive, "-d", root_path.join("out"));
+    assert_eq!(
+        fs::read(root_path.join("out/frames.txt")).unwrap(),
+        b"first frame\nsecond frame\n"
+    );
+
+    // An archive split across frames, each entry in its own frame
+    let entries: [(&str, &[u8]); 2] = [("a.txt", &[b'a'; 1000]), ("b.txt", &[b'b'; 3000])];
+    let mut builder = tar::Builder::new(vec![]);
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+    let tar = builder.into_inner().unwrap();
+    // Header and content of the first entry, then the rest
+    let (head, tail) = tar.split_at(512 + 1024);
+    let archive = root_path.join("frames.tar.zst");
+    fs::write(&archive, [frame(head), frame(tail)].concat()).unwrap();
+
+    ouch!("d", &archive, "-d", root_path.join("out-tar"));
+    let total_size: u64 = entries
+        .iter()
+        .map(|(name, _)| fs::metadata(root_path.join("out-tar").join(name)).unwrap().len())
+        .sum();
+    assert_eq!(total_size, 4000);
+
+    let output = crate::utils::cargo_bin()
+        .args(["l", "--yes", "--long"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    for (name, _) in entries {
+        assert!(memmem::find(&output.stdout, name.as_bytes()).is_some());
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+#[test]
+fn trash_keeps_replaced_files() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let data_home = root_path.join("data");
+    fs::write(root_path.join("file.txt"), "new").unwrap();
+
+    let archive = root_path.join("archive.tar");
+    ouch!("c", root_path.join("file.txt"), &archive);
+
+    let out = root_path.join("out");
+    fs::create_dir(&out).unwrap();
+    fs::write(out.join("file.txt"), "old").unwrap();
+
+    crate::utils::cargo_bin()
+        .env("XDG_DATA_HOME", &data_home)
+        .args(["d", "--yes", "--trash"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(&out)
+        .assert()
+        .success();
+
+    // The replaced output directory was moved to the trash, not destroyed
+    assert_eq!(fs::read(out.join("file.txt")).unwrap(), b"new");
+    assert_eq!(fs::read(data_home.join("Trash/files/out/file.txt")).unwrap(), b"old");
+    let info = fs::read_to_string(data_home.join("Trash/info/out.trashinfo")).unwrap();
+    assert!(info.starts_with("[Trash Info]\n"));
+    assert!(info.contains(&format!("Path={}\n", out.display())));
+
+    // Without the flag, replaced files are deleted
+    fs::write(out.join("file.txt"), "old").unwrap();
+    crate::utils::cargo_bin()
+        .env("XDG_DATA_HOME", &data_home)
+        .args(["d", "--yes"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(&out)
+        .assert()
+        .success();
+    assert!(!data_home.join("Trash/files/out.1").exists());
+
+    // Files that can't be moved to the trash are kept
+    let not_a_dir = root_path.join("not-a-dir");
+    fs::write(&not_a_dir, "").unwrap();
+    fs::write(out.join("file.txt"), "old").unwrap();
+    let output = crate::utils::cargo_bin()
+        .env("XDG_DATA_HOME", &not_a_dir)
+        .args(["d", "--yes", "--trash"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(&out)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"to the trash").is_some());
+    assert_eq!(fs::read(out.join("file.txt")).unwrap(), b"old");
+}
+
+#[test]
+fn split_by_entry_makes_independent_volumes() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    let mut rng = SmallRng::seed_from_u64(0);
+    let names = ["a.bin", "b.bin", "c.bin", "d.bin"];
+    for name in names {
+        let mut data = vec![0; 4000];
+        rng.fill(&mut data[..]);
+        fs::write(dir.join(name), data).unwrap();
+    }
+
+    for extension in ["zip", "tar.gz"] {
+        let archive = root_path.join(format!("archive.{extension}"));
+        ouch!("c", &dir, &archive, "--split-by-entry", "12KB");
+
+        // Two entries of 4000 bytes fit with their headers in each volume
+        let volumes: Vec<_> = (1..=3)
+            .map(|index| root_path.join(format!("archive.{index:03}.{extension}")))
+            .collect();
+        assert!(volumes[0].exists() && volumes[1].exists());
+        assert!(!volumes[2].exists() && !archive.exists());
+
+        // Each volume opens on its own, together they hold all entries
+        let outs: Vec<_> = (1..=2)
+            .map(|index| root_path.join(format!("out-{extension}-{index}")))
+            .collect();
+        for (volume, out) in volumes.iter().zip(&outs) {
+            ouch!("d", volume, "-d", out, "--no-smart-unpack");
+        }
+        for name in names {
+            let mut extracted = outs
+                .iter()
+                .map(|out| out.join("dir").join(name))
+                .filter(|path| path.exists());
+            assert_eq!(
+                fs::read(extracted.next().unwrap()).unwrap(),
+                fs::read(dir.join(name)).unwrap()
+            );
+            assert!(extracted.next().is_none());
+        }
+    }
+
+    crate::utils::cargo_bin()
+        .args(["c", "--yes", "--split-by-entry", "1MB"])
+        .arg(&dir)
+        .arg(root_path.join("archive.7z"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn log_format_emits_structured_records() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("file.txt"), "contents").unwrap();
+
+    // Xz can't store the name of its input, so it's ignored with a warning
+    let compress = |output: &str, log_format: &str| {
+        crate::utils::cargo_bin()
+            .args(["c", "--yes", "--name", "renamed.txt", "--log-format", log_format])
+            .arg(root_path.join("file.txt"))
+            .arg(root_path.join(output))
+            .assert()
+            .success()
+            .get_output()
+            .clone()
+    };
+
+    let output = compress("logfmt.xz", "logfmt");
+    let stderr = output.stderr.to_str().unwrap();
+    assert!(stderr.lines().all(|line| line.starts_with("level=")), "{stderr}");
+    assert!(stderr
+        .lines()
+        .any(|line| line.starts_with("level=warning msg=\"") && line.contains("ignoring --name")));
+
+    let output = compress("json.xz", "json");
+    let records: Vec<serde_json::Value> = output
+        .stderr
+        .lines()
+        .map(|line| serde_json::from_slice(line).unwrap())
+        .collect();
+    assert!(records
+        .iter()
+        .any(|record| record["level"] == "warning" && record["msg"].as_str().unwrap().contains("ignoring --name")));
+    assert!(records.iter().any(|record| record["level"] == "info"));
+}
+
+#[test]
+fn resume_skips_extracted_files() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("a.txt"), "aaaa").unwrap();
+    fs::write(dir.join("b.txt"), "bbbb").unwrap();
+
+    for extension in ["zip", "tar"] {
+        let archive = root_path.join(format!("archive.{extension}"));
+        let out = root_path.join(format!("out-{extension}"));
+        ouch!("c", &dir, &archive);
+        ouch!("d", &archive, "-d", &out);
+
+        // Same size and modification time, but another content
+        let extracted = out.join("dir/a.txt");
+        let mtime = filetime_creation::FileTime::from_last_modification_time(&fs::metadata(&extracted).unwrap());
+        fs::write(&extracted, "XXXX").unwrap();
+        filetime_creation::set_file_mtime(&extracted, mtime).unwrap();
+        // Missing from an interrupted run
+        fs::remove_file(out.join("dir/b.txt")).unwrap();
+
+        ouch!("d", &archive, "-d", &out, "--resume");
+        assert_eq!(fs::read(&extracted).unwrap(), b"XXXX");
+        assert_eq!(fs::read(out.join("dir/b.txt")).unwrap(), b"bbbb");
+
+        // Only zip stores the checksums of entries, tar falls back to size and modification time
+        ouch!("d", &archive, "-d", &out, "--resume=checksum");
+        let expected: &[u8] = if extension == "zip" { b"aaaa" } else { b"XXXX" };
+        assert_eq!(fs::read(&extracted).unwrap(), expected);
+    }
+}
+
+#[test]
+fn resume_doesnt_remove_files_outside_of_the_output_directory() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let archive = root_path.join("archive.tar");
+    tar_with_parent_dir_entry(&archive);
+
+    let out = root_path.join("out");
+    let victim = out.join("victim.txt");
+    fs::create_dir_all(out.join("sub")).unwrap();
+    fs::write(&victim, "victim").unwrap();
+
+    ouch!("d", &archive, "-d", out.join("sub"), "--resume");
+    assert_eq!(fs::read(&victim).unwrap(), b"victim");
+
+    // Nor through a symlink left by the interrupted run
+    #[cfg(unix)]
+    {
+        let outside = root_path.join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("victim.txt"), "victim").unwrap();
+        std::os::unix::fs::symlink("../outside", out.join("sub/link")).unwrap();
+
+        let archive = root_path.join("symlink.tar");
+        let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "link/victim.txt", &b"evil"[..])
+            .unwrap();
+        builder.finish().unwrap();
+        drop(builder);
+
+        crate::utils::cargo_bin()
+            .arg("d")
+            .arg(&archive)
+            .arg("-d")
+            .arg(out.join("sub"))
+            .args(["--resume", "--yes"])
+            .assert()
+            .failure();
+        assert_eq!(fs::read(outside.join("victim.txt")).unwrap(), b"victim");
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn confined_refuses_symlink_escapes() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let outside = root_path.join("outside");
+    fs::create_dir(&outside).unwrap();
+
+    // A symlink to a directory outside of the destination, then a file written through it
+    let zip_archive = root_path.join("escape.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&zip_archive).unwrap());
+    let options = zip::write::FileOptions::default();
+    writer.add_symlink("link", "../outside", options).unwrap();
+    writer.start_file("link/evil.txt", options).unwrap();
+    writer.write_all(b"evil").unwrap();
+    writer.finish().unwrap();
+
+    let tar_archive = root_path.join("escape.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&tar_archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "link", "../outside").unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, "link/evil.txt", &b"evil"[..]).unwrap();
+    builder.finish().unwrap();
+
+    for archive in [zip_archive, tar_archive] {
+        let out = root_path.join("out");
+        let output = crate::utils::cargo_bin()
+            .args(["d", "--confined", "--yes"])
+            .arg(&archive)
+            .arg("-d")
+            .arg(&out)
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        assert!(memmem::find(&output.stderr, b"goes through a symlink").is_some());
+        assert!(!outside.join("evil.txt").exists());
+        fs::remove_dir_all(&out).unwrap();
+    }
+
+    // Archives without escape attempts are extracted as usual
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/file.txt"), "contents").unwrap();
+    std::os::unix::fs::symlink("sub/file.txt", dir.join("link")).unwrap();
+    for extension in ["zip", "tar.gz"] {
+        let archive = root_path.join(format!("archive.{extension}"));
+        let out = root_path.join(format!("out-{extension}"));
+        ouch!("c", &dir, &archive);
+        ouch!("d", "--confined", &archive, "-d", &out);
+        assert_eq!(fs::read_to_string(out.join("dir/sub/file.txt")).unwrap(), "contents");
+        assert_eq!(fs::read_link(out.join("dir/link")).unwrap(), Path::new("sub/file.txt"));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn confined_extracts_every_kind_of_entry() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let archive = root_path.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(4);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, "dir/file.txt", &b"file"[..]).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Link);
+    header.set_size(0);
+    builder
+        .append_link(&mut header, "dir/hard.txt", "dir/file.txt")
+        .unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Continuous);
+    header.set_size(10);
+    header.set_mode(0o644);
+    builder
+        .append_data(&mut header, "dir/continuous.txt", &b"continuous"[..])
+        .unwrap();
+
+    // Sparse file of 10 bytes, with a hole of 6 bytes before the 4 stored ones
+    let mut header = tar::Header::new_gnu();
+    header.set_path("dir/sparse.bin").unwrap();
+    header.set_entry_type(tar::EntryType::GNUSparse);
+    header.set_size(4);
+    header.set_mode(0o644);
+    let gnu = header.as_gnu_mut().unwrap();
+    gnu.sparse[0].offset.copy_from_slice(b"00000000006\0");
+    gnu.sparse[0].numbytes.copy_from_slice(b"00000000004\0");
+    gnu.set_real_size(10);
+    header.set_cksum();
+    builder.append(&header, &b"data"[..]).unwrap();
+    builder.finish().unwrap();
+
+    let out = root_path.join("out");
+    ouch!("d", "--confined", &archive, "-d", &out);
+    assert_eq!(fs::read(out.join("dir/hard.txt")).unwrap(), b"file");
+    assert_eq!(fs::read(out.join("dir/continuous.txt")).unwrap(), b"continuous");
+    assert_eq!(fs::read(out.join("dir/sparse.bin")).unwrap(), b"\0\0\0\0\0\0data");
+
+    // Files colliding once stripped are handled like the conflicts of other extractions
+    let archive = root_path.join("colliding.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    for (path, content) in [("dir/first", b"first"), ("dir/other", b"other")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, &content[..]).unwrap();
+    }
+    builder.finish().unwrap();
+
+    for (answer, expected) in [("--no", b"first"), ("--yes", b"other")] {
+        let out = root_path.join(format!("out{answer}"));
+        crate::utils::cargo_bin()
+            .args(["d", "--confined", answer, "--strip-trailing-components", "1"])
+            .arg(&archive)
+            .arg("-d")
+            .arg(&out)
+            .assert()
+            .success();
+        assert_eq!(&fs::read(out.join("dir")).unwrap(), expected);
+    }
+}
+
+#[test]
+fn stdin_name_gives_the_formats_of_stdin() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    crate::utils::cargo_bin()
+        .current_dir(root_path)
+        .args(["c", "-", "--stdin-name", "data.tar.gz"])
+        .write_stdin("piped data")
+        .assert()
+        .success();
+
+    // Compressed with the tar and gzip chain, into an entry named without the extensions
+    let archive = root_path.join("data.tar.gz");
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(&archive).unwrap()));
+    let entries: Vec<_> = tar
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap().path().unwrap().into_owned())
+        .collect();
+    assert_eq!(entries, [Path::new("data")]);
+
+    let out = root_path.join("out");
+    crate::utils::cargo_bin()
+        .args(["d", "-", "--stdin-name", "data.tar.gz", "-d"])
+        .arg(&out)
+        .write_stdin(fs::read(&archive).unwrap())
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(out.join("data")).unwrap(), "piped data");
+
+    // Names without a known format are rejected
+    crate::utils::cargo_bin()
+        .args(["d", "-", "--stdin-name", "data.txt"])
+        .write_stdin(fs::read(&archive).unwrap())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn repair_zip_with_truncated_central_directory() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let archive = root_path.join("archive.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    let options = zip::write::FileOptions::default();
+    writer.add_directory("dir/", options).unwrap();
+    for (name, contents) in [("dir/a.txt", "first"), ("b.txt", "second")] {
+        writer.start_file(name, options).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    // Cut in the middle of the central directory, the local entries before it are intact
+    let mut data = fs::read(&archive).unwrap();
+    let central_directory = memmem::find(&data, b"PK\x01\x02").unwrap();
+    data.truncate(central_directory + 20);
+    fs::write(&archive, data).unwrap();
+    assert!(zip::ZipArchive::new(fs::File::open(&archive).unwrap()).is_err());
+
+    let output = crate::utils::cargo_bin()
+        .arg("repair")
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"Recovered 3 entries").is_some());
+
+    let repaired = root_path.join("archive.repaired.zip");
+    let output = crate::utils::cargo_bin()
+        .arg("list")
+        .arg(&repaired)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let listed = output.stdout.to_str().unwrap();
+    for name in ["dir", "dir/a.txt", "b.txt"] {
+        assert!(listed.lines().any(|line| line.trim().trim_end_matches('/') == name));
+    }
+
+    let out = root_path.join("out");
+    ouch!("d", &repaired, "-d", &out);
+    assert_eq!(fs::read_to_string(out.join("dir/a.txt")).unwrap(), "first");
+    assert_eq!(fs::read_to_string(out.join("b.txt")).unwrap(), "second");
+}
+
+#[test]
+fn compress_zstd_with_frame_size() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let mut contents = vec![0; 1000 * 1000];
+    SmallRng::seed_from_u64(0).fill(contents.as_mut_slice());
+    let input = root_path.join("input");
+    fs::write(&input, &contents).unwrap();
+
+    let archive = root_path.join("input.zst");
+    ouch!("c", &input, &archive, "--frame-size", "256KiB");
+
+    // Frames of 256 KiB of input, then what's left
+    let compressed = fs::read(&archive).unwrap();
+    let mut frames = compressed.as_slice();
+    let mut sizes = vec![];
+    while !frames.is_empty() {
+        let len = zstd::zstd_safe::find_frame_compressed_size(frames).unwrap();
+        sizes.push(zstd::decode_all(&frames[..len]).unwrap().len());
+        frames = &frames[len..];
+    }
+    assert_eq!(sizes, [262144, 262144, 262144, 213568]);
+
+    let out = root_path.join("out");
+    ouch!("d", &archive, "-d", &out);
+    assert_eq!(fs::read(out.join("input")).unwrap(), contents);
+}
+
+#[test]
+fn decompress_zip_with_mixed_compression_methods() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let entries = [
+        ("stored.txt", zip::CompressionMethod::STORE),
+        ("deflated.txt", zip::CompressionMethod::DEFLATE),
+        ("bzip2.txt", zip::CompressionMethod::BZIP2),
+        ("zstd.txt", zip::CompressionMethod::STORE),
+        ("lzma.txt", zip::CompressionMethod::STORE),
+    ];
+    let contents = |name: &str| name.repeat(100);
+
+    let archive = root_path.join("archive.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for (name, method) in entries {
+        let options = zip::write::FileOptions::default().compression_method(method);
+        writer.start_file(name, options).unwrap();
+        let data = match name {
+            "zstd.txt" => zstd::encode_all(contents(name).as_bytes(), 3).unwrap(),
+            _ => contents(name).into_bytes(),
+        };
+        writer.write_all(&data).unwrap();
+    }
+    writer.finish().unwrap();
+
+    // The writer can't compress these, so they're stored and their headers patched afterwards
+    let mut data = fs::read(&archive).unwrap();
+    let mut patch_headers = |name: &str, method: u16| {
+        let contents = contents(name);
+        let mut crc = flate2::Crc::new();
+        crc.update(contents.as_bytes());
+
+        // Offsets of the method, CRC32 and uncompressed size, then of the name
+        for (signature, offsets, name_offset) in [(b"PK\x03\x04", [8, 14, 22], 30), (b"PK\x01\x02", [10, 16, 24], 46)] {
+            let header = memmem::find_iter(&data, signature)
+                .find(|&start| data[start + name_offset..].starts_with(name.as_bytes()))
+                .unwrap();
+            data[header + offsets[0]..][..2].copy_from_slice(&method.to_le_bytes());
+            data[header + offsets[1]..][..4].copy_from_slice(&crc.sum().to_le_bytes());
+            data[header + offsets[2]..][..4].copy_from_slice(&(contents.len() as u32).to_le_bytes());
+        }
+    };
+    patch_headers("zstd.txt", 93);
+    patch_headers("lzma.txt", 14);
+    fs::write(&archive, data).unwrap();
+
+    let out = root_path.join("out");
+    let output = crate::utils::cargo_bin()
+        .args(["--yes", "d"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(&out)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"Skipping \"lzma.txt\", it's compressed with LZMA").is_some());
+
+    for (name, _) in &entries[..4] {
+        assert_eq!(fs::read_to_string(out.join(name)).unwrap(), contents(name));
+    }
+    assert!(!out.join("lzma.txt").exists());
+}
+
+#[test]
+fn invalid_progress_template_lists_tokens() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let input = root_path.join("input");
+    fs::write(&input, "contents").unwrap();
+
+    let output = crate::utils::cargo_bin()
+        .arg("--progress-template")
+        .arg("{percentage} done")
+        .arg("c")
+        .arg(&input)
+        .arg(root_path.join("input.gz"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = output.stderr.to_str().unwrap();
+    assert!(stderr.contains("Unknown token {percentage}"));
+    assert!(stderr.contains("Supported tokens are: {spinner}, {file}, {files}, {percent}"));
+    assert!(!root_path.join("input.gz").exists());
+
+    ouch!(
+        "--progress-template",
+        "{percent}",
+        "c",
+        &input,
+        root_path.join("input.gz")
+    );
+    assert!(root_path.join("input.gz").exists());
+}
+
+#[test]
+fn compress_with_breadth_first_traversal() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    for subdir in ["a/deep", "b/deep"] {
+        fs::create_dir_all(dir.join(subdir)).unwrap();
+        fs::write(dir.join(subdir).join("file.txt"), "contents").unwrap();
+    }
+
+    // Depth of each entry of the archive, in the order they were written
+    let entry_depths = |traversal: &str| {
+        let archive = root_path.join(format!("{traversal}.tar"));
+        ouch!("c", &dir, &archive, "--traversal", traversal);
+        let mut archive = tar::Archive::new(fs::File::open(&archive).unwrap());
+        archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().components().count())
+            .collect::<Vec<_>>()
+    };
+
+    // Siblings are in no particular order, but levels are
+    assert_eq!(entry_depths("bfs"), [1, 2, 2, 3, 3, 4, 4]);
+    let dfs = entry_depths("dfs");
+    assert_eq!(dfs.len(), 7);
+    assert!(dfs.windows(2).any(|pair| pair[1] < pair[0]));
+}
+
+#[test]
+fn compress_nothing_unless_allowed_empty() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let archive = root_path.join("archive.tar");
+
+    // Like the output of a `find -print0` that matched nothing
+    let output = crate::utils::cargo_bin()
+        .args(["compress", "--read0"])
+        .arg(&archive)
+        .write_stdin("")
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"No files to compress").is_some());
+    assert!(memmem::find(&output.stderr, b"--allow-empty").is_some());
+    assert!(!archive.exists());
+
+    crate::utils::cargo_bin()
+        .args(["compress", "--read0", "--allow-empty"])
+        .arg(&archive)
+        .write_stdin("")
+        .assert()
+        .success();
+    let mut archive = tar::Archive::new(fs::File::open(&archive).unwrap());
+    assert_eq!(archive.entries().unwrap().count(), 0);
+
+    // Only archives can be empty
+    crate::utils::cargo_bin()
+        .args(["compress", "--read0", "--allow-empty"])
+        .arg(root_path.join("file.gz"))
+        .write_stdin("")
+        .assert()
+        .failure();
+
+    // Directories whose entries are all excluded are just as empty
+    let dir = root_path.join("logs");
+    fs::create_dir_all(dir.join("old")).unwrap();
+    fs::write(dir.join("old/first.log"), "log").unwrap();
+    fs::write(dir.join("second.log"), "log").unwrap();
+    fs::write(root_path.join("exclude"), "*.log\nold\n").unwrap();
+    let archive = root_path.join("logs.tar");
+    let output = crate::utils::cargo_bin()
+        .args(["compress", "--yes", "--exclude-from"])
+        .arg(root_path.join("exclude"))
+        .arg(&dir)
+        .arg(&archive)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"No files to compress").is_some());
+    assert!(!archive.exists());
+
+    crate::utils::cargo_bin()
+        .args(["compress", "--yes", "--allow-empty", "--exclude-from"])
+        .arg(root_path.join("exclude"))
+        .arg(&dir)
+        .arg(&archive)
+        .assert()
+        .success();
+}
+
+#[test]
+fn compress_with_exclude_from_files() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("target/debug")).unwrap();
+    fs::create_dir_all(dir.join("src")).unwrap();
+    for file in [
+        "src/main.rs",
+        "src/debug.log",
+        "build.log",
+        "target/debug/app",
+        "notes.tmp",
+        "README",
+    ] {
+        fs::write(dir.join(file), "contents").unwrap();
+    }
+
+    let logs = root_path.join("logs.exclude");
+    fs::write(&logs, "# Logs and build artifacts\n*.log\n\ntarget\n").unwrap();
+    let tmp = root_path.join("tmp.exclude");
+    fs::write(&tmp, "  *.tmp  \n").unwrap();
+
+    let archive_paths = |archive: &Path| {
+        let mut archive = tar::Archive::new(fs::File::open(archive).unwrap());
+        let mut paths = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    };
+
+    let archive = root_path.join("archive.tar");
+    ouch!("c", &dir, &archive, "--exclude-from", &logs, "--exclude-from", &tmp);
+    let expected: Vec<PathBuf> = ["dir", "dir/README", "dir/src", "dir/src/main.rs"]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(archive_paths(&archive), expected);
+
+    // Along with the globs of the preset
+    let config = root_path.join("config.json");
+    fs::write(&config, r#"{ "presets": { "no-readme": { "exclude": ["README"] } } }"#).unwrap();
+    let archive = root_path.join("preset.tar");
+    crate::utils::cargo_bin()
+        .env("OUCH_CONFIG", &config)
+        .args(["compress", "--yes", "--preset", "no-readme", "--exclude-from"])
+        .arg(&logs)
+        .arg(&dir)
+        .arg(&archive)
+        .assert()
+        .success();
+    let expected: Vec<PathBuf> = ["dir", "dir/notes.tmp", "dir/src", "dir/src/main.rs"]
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    assert_eq!(archive_paths(&archive), expected);
+
+    let invalid = root_path.join("invalid.exclude");
+    fs::write(&invalid, "*.log\n[abc\n").unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["compress", "--exclude-from"])
+        .arg(&invalid)
+        .arg(&dir)
+        .arg(root_path.join("invalid.tar"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"Invalid pattern at line 2").is_some());
+}
+
+#[test]
+fn tar_pax_times_are_restored_with_precision() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // A record is prefixed with its own length, which counts its digits
+    let pax_record = |key: &str, value: &str| {
+        let body = format!(" {key}={value}\n");
+        let mut length = body.len() + 1;
+        while length.to_string().len() + body.len() != length {
+            length += 1;
+        }
+        format!("{length}{body}")
+    };
+    let records = pax_record("mtime", "1700000000.123456789") + &pax_record("atime", "1600000000.5");
+
+    let archive = root_path.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XHeader);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "PaxHeaders/file.txt", records.as_bytes())
+        .unwrap();
+    let mut header = tar::Header::new_ustar();
+    header.set_size(8);
+    header.set_mode(0o644);
+    header.set_mtime(1700000000);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, "file.txt", "contents".as_bytes())
+        .unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    // The times the filesystem would keep, as it may be coarser than nanoseconds
+    let probe = root_path.join("probe");
+    let epoch = std::time::SystemTime::UNIX_EPOCH;
+    let (mtime, atime) = (
+        epoch + std::time::Duration::new(1700000000, 123456789),
+        epoch + std::time::Duration::new(1600000000, 500000000),
+    );
+    let probe_file = fs::File::create(&probe).unwrap();
+    probe_file
+        .file()
+        .set_times(std::fs::FileTimes::new().set_modified(mtime).set_accessed(atime))
+        .unwrap();
+    let probe = fs::metadata(&probe).unwrap();
+
+    let out = root_path.join("out");
+    ouch!("d", &archive, "-d", &out, "--preserve-atime");
+    let extracted = fs::metadata(out.join("file.txt")).unwrap();
+    assert_eq!(extracted.modified().unwrap(), probe.modified().unwrap());
+    assert_eq!(extracted.accessed().unwrap(), probe.accessed().unwrap());
+
+    // Without --preserve-atime, only the modification time is restored
+    let out = root_path.join("out-without-atime");
+    ouch!("d", &archive, "-d", &out);
+    let extracted = fs::metadata(out.join("file.txt")).unwrap();
+    assert_eq!(extracted.modified().unwrap(), probe.modified().unwrap());
+    assert_ne!(extracted.accessed().unwrap(), probe.accessed().unwrap());
+
+    #[cfg(unix)]
+    {
+        let out = root_path.join("out-confined");
+        fs::create_dir(&out).unwrap();
+        ouch!("d", &archive, "-d", &out, "--preserve-atime", "--confined");
+        let extracted = fs::metadata(out.join("file.txt")).unwrap();
+        assert_eq!(extracted.modified().unwrap(), probe.modified().unwrap());
+        assert_eq!(extracted.accessed().unwrap(), probe.accessed().unwrap());
+    }
+}
+
+/// Writes a tar with a regular file entry at `../victim.txt`, modified at 1000 seconds after the
+/// epoch and owned by the ids 1234
+fn tar_with_parent_dir_entry(archive: &Path) {
+    let mut builder = tar::Builder::new(fs::File::create(archive).unwrap());
+    let mut header = tar::Header::new_ustar();
+    let name = b"../victim.txt";
+    // Write the name directly, `Header::set_path` refuses `..`
+    header.as_old_mut().name[..name.len()].copy_from_slice(name);
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(4);
+    header.set_mtime(1000);
+    header.set_uid(1234);
+    header.set_gid(1234);
+    header.set_cksum();
+    builder.append(&header, &b"evil"[..]).unwrap();
+    builder.finish().unwrap();
+}
+
+#[test]
+fn tar_times_arent_set_outside_of_the_output_directory() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let archive = root_path.join("archive.tar");
+    tar_with_parent_dir_entry(&archive);
+
+    let out = root_path.join("out");
+    let victim = out.join("victim.txt");
+    fs::create_dir(&out).unwrap();
+    fs::write(&victim, "victim").unwrap();
+    let mtime = fs::metadata(&victim).unwrap().modified().unwrap();
+
+    ouch!("d", &archive, "-d", out.join("sub"));
+    assert_eq!(fs::read(&victim).unwrap(), b"victim");
+    assert_eq!(fs::metadata(&victim).unwrap().modified().unwrap(), mtime);
+
+    // The entry is skipped when there is nothing to escape to as well
+    fs::remove_file(&victim).unwrap();
+    let output = ouch!("d", &archive, "-d", out.join("other"));
+    assert!(memmem::find(&[output.stdout, output.stderr].concat(), b"victim.txt").is_none());
+    assert!(!victim.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn tar_owners_arent_set_outside_of_the_output_directory() {
+    use std::os::unix::fs::MetadataExt;
+
+    // Owners are only changed by root
+    if unsafe { libc::geteuid() } != 0 {
+        return;
+    }
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+    let archive = root_path.join("archive.tar");
+    tar_with_parent_dir_entry(&archive);
+
+    let out = root_path.join("out");
+    let victim = out.join("victim.txt");
+    fs::create_dir(&out).unwrap();
+    fs::write(&victim, "victim").unwrap();
+    let owner = fs::metadata(&victim).unwrap().uid();
+
+    ouch!("d", &archive, "-d", out.join("sub"), "--owner-map", "1234:4321");
+    assert_eq!(fs::metadata(&victim).unwrap().uid(), owner);
+
+    // Nor through a symlink entry
+    let archive = root_path.join("symlink.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    let mut header = tar::Header::new_ustar();
+    let name = b"../link";
+    header.as_old_mut().name[..name.len()].copy_from_slice(name);
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_link_name("victim.txt").unwrap();
+    header.set_mode(0o777);
+    header.set_size(0);
+    header.set_mtime(1000);
+    header.set_uid(1234);
+    header.set_gid(1234);
+    header.set_cksum();
+    builder.append(&header, std::io::empty()).unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    ouch!("d", &archive, "-d", out.join("sub"), "--owner-map", "1234:4321");
+    assert!(fs::symlink_metadata(out.join("link")).is_err());
+    assert_eq!(fs::metadata(&victim).unwrap().uid(), owner);
+}
+
+#[test]
+fn decompress_verifies_sibling_checksum_files() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let file = root_path.join("file.txt");
+    fs::write(&file, "contents").unwrap();
+    let archive = root_path.join("file.txt.gz");
+    ouch!("c", &file, &archive);
+    fs::remove_file(&file).unwrap();
+
+    let checksum_file = root_path.join("file.txt.gz.sha256");
+    fs::write(&checksum_file, format!("{}  file.txt.gz\n", "0".repeat(64))).unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["decompress", "--yes"])
+        .arg(&archive)
+        .current_dir(root_path)
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"Checksum mismatch").is_some());
+    assert!(!file.exists());
+
+    // Skipped on request
+    crate::utils::cargo_bin()
+        .args(["decompress", "--yes", "--no-verify"])
+        .arg(&archive)
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&file).unwrap(), b"contents");
+    fs::remove_file(&file).unwrap();
+
+    let digest = sha2::Sha256::digest(fs::read(&archive).unwrap());
+    let digest: String = digest.iter().map(|byte| format!("{byte:02x}")).collect();
+    fs::write(&checksum_file, format!("{digest}  file.txt.gz\n")).unwrap();
+    let output = crate::utils::cargo_bin()
+        .args(["decompress", "--yes"])
+        .arg(&archive)
+        .current_dir(root_path)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"WARNING").is_none());
+    assert_eq!(fs::read(&file).unwrap(), b"contents");
+    fs::remove_file(&file).unwrap();
+    fs::remove_file(&checksum_file).unwrap();
+
+    // BLAKE3 checksums are verified too
+    let checksum_file = root_path.join("file.txt.gz.blake3");
+    fs::write(&checksum_file, format!("{}  file.txt.gz\n", "0".repeat(64))).unwrap();
+    crate::utils::cargo_bin()
+        .args(["decompress", "--yes"])
+        .arg(&archive)
+        .current_dir(root_path)
+        .assert()
+        .failure();
+    assert!(!file.exists());
+
+    let digest = blake3::hash(&fs::read(&archive).unwrap());
+    fs::write(&checksum_file, format!("{}  file.txt.gz\n", digest.to_hex())).unwrap();
+    crate::utils::cargo_bin()
+        .args(["decompress", "--yes"])
+        .arg(&archive)
+        .current_dir(root_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read(&file).unwrap(), b"contents");
+}
+
+#[test]
+fn compress_with_converted_line_endings() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("notes.txt"), "first\r\nsecond\r\n").unwrap();
+    let binary = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\r\n".to_vec();
+    fs::write(dir.join("image.png"), &binary).unwrap();
+
+    let archive = root_path.join("archive.tar");
+    ouch!("c", &dir, &archive, "--eol", "lf");
+
+    let mut archive = tar::Archive::new(fs::File::open(&archive).unwrap());
+    let mut contents: Vec<_> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.header().entry_type().is_file())
+        .map(|mut entry| {
+            let mut content = vec![];
+            entry.read_to_end(&mut content).unwrap();
+            (entry.path().unwrap().into_owned(), content)
+        })
+        .collect();
+    contents.sort();
+    assert_eq!(
+        contents,
+        [
+            (PathBuf::from("dir/image.png"), binary),
+            (PathBuf::from("dir/notes.txt"), b"first\nsecond\n".to_vec()),
+        ]
+    );
+
+    // Back to CRLF, for single file formats too
+    let compressed = root_path.join("notes.txt.gz");
+    ouch!("c", dir.join("notes.txt"), &compressed, "--eol", "crlf");
+    let mut content = vec![];
+    flate2::read::GzDecoder::new(fs::File::open(&compressed).unwrap())
+        .read_to_end(&mut content)
+        .unwrap();
+    assert_eq!(content, b"first\r\nsecond\r\n");
+}
+
+#[test]
+fn list_duplicate_entry_paths() {
+    let temp_dir = tempdir().unwrap();
+    let archive = temp_dir.path().join("archive.tar");
+
+    // Like the result of appending to an archive with `tar -r`
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    for (path, content) in [
+        ("a.txt", "old"),
+        ("b.txt", "only"),
+        ("./c.txt", "old"),
+        ("a.txt", "new"),
+        ("c.txt", "new"),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, content.as_bytes()).unwrap();
+    }
+    builder.finish().unwrap();
+    drop(builder);
+
+    let output = crate::utils::cargo_bin()
+        .args(["list", "--list-duplicates"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let report = stdout.split_once("Duplicate paths:\n").unwrap().1;
+    assert_eq!(report, "a.txt (2 entries)\nc.txt (2 entries)\n");
+
+    let file = temp_dir.path().join("file.txt");
+    fs::write(&file, "contents").unwrap();
+    let archive = temp_dir.path().join("unique.tar");
+    ouch!("c", &file, &archive);
+    let output = crate::utils::cargo_bin()
+        .args(["list", "--list-duplicates"])
+        .arg(&archive)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stdout, b"No duplicate paths").is_some());
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_compression_leaves_no_output() {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt, process::Stdio, thread, time::Duration};
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Compression blocks on reading the FIFO once the output is created, until it's interrupted
+    let input = root_path.join("input");
+    let c_input = CString::new(input.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_input.as_ptr(), 0o644) }, 0);
+
+    for (format, signal) in [("gz", libc::SIGINT), ("zst", libc::SIGTERM)] {
+        let output = root_path.join(format!("output.{format}"));
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_ouch"))
+            .args(["compress", "--quiet"])
+            .arg(&input)
+            .arg(&output)
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let mut fifo = fs::OpenOptions::new().write(true).open(&input).unwrap();
+        fifo.write_all(&[b'a'; 1 << 20]).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(output.exists());
+
+        unsafe { libc::kill(child.id() as libc::pid_t, signal) };
+        let status = child.wait().unwrap();
+        assert!(!status.success());
+        assert!(!output.exists(), "{format} output left behind");
+    }
+}
+
+#[test]
+fn decompress_with_fixed_mtime() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let archive = root_path.join("archive.tar");
+    let mut builder = tar::Builder::new(fs::File::create(&archive).unwrap());
+    for (path, mtime) in [("dir/", 1_000), ("dir/old.txt", 2_000), ("dir/new.txt", 1_700_000_000)] {
+        let mut header = tar::Header::new_gnu();
+        if path.ends_with('/') {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+        } else {
+            header.set_mode(0o644);
+        }
+        header.set_size(0);
+        header.set_mtime(mtime);
+        builder.append_data(&mut header, path, std::io::empty()).unwrap();
+    }
+    builder.finish().unwrap();
+    drop(builder);
+
+    let output = root_path.join("output");
+    ouch!("d", &archive, "--dir", &output, "--set-mtime", "@1234567890");
+
+    let expected = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_234_567_890);
+    for path in ["dir", "dir/old.txt", "dir/new.txt"] {
+        let mtime = fs::metadata(output.join(path)).unwrap().modified().unwrap();
+        assert_eq!(mtime, expected, "{path}");
+    }
+
+    // Files already extracted by an interrupted run would be rewritten
+    let output = crate::utils::cargo_bin()
+        .args(["d", "--yes", "--resume", "--set-mtime", "@1234567890"])
+        .arg(&archive)
+        .arg("--dir")
+        .arg(root_path.join("output"))
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    assert!(memmem::find(&output.stderr, b"cannot be used with").is_some());
+}
+
+#[test]
+fn list_with_cache_of_entries() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("first.txt"), "contents").unwrap();
+    let archive = root_path.join("archive.tar");
+    ouch!("c", &dir, &archive);
+
+    let list = || {
+        let output = crate::utils::cargo_bin()
+            .args(["list", "--cache"])
+            .arg(&archive)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert!(list().contains("dir/first.txt"));
+    let cache = root_path.join("archive.tar.ouchidx");
+    assert!(cache.exists());
+
+    // Listings read the cache while it's up to date
+    let tampered = fs::read_to_string(&cache).unwrap().replace("first.txt", "cached.txt");
+    fs::write(&cache, tampered).unwrap();
+    assert!(list().contains("dir/cached.txt"));
+
+    // A changed archive makes it stale, so it's regenerated
+    fs::write(dir.join("second.txt"), "more contents").unwrap();
+    ouch!("c", &dir, &archive);
+    let listing = list();
+    assert!(listing.contains("dir/first.txt") && listing.contains("dir/second.txt"));
+    assert!(!listing.contains("cached.txt"));
+    assert!(fs::read_to_string(&cache).unwrap().contains("second.txt"));
+
+    // Ignored with --no-cache
+    fs::write(&cache, "damaged").unwrap();
+    crate::utils::cargo_bin()
+        .args(["list", "--cache", "--no-cache"])
+        .arg(&archive)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&cache).unwrap(), "damaged");
+}
+
+#[cfg(unix)]
+#[test]
+fn decompress_into_fifo_and_stdout() {
+    use std::{
+        ffi::CString,
+        os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+        thread,
+    };
+
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let contents: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+    let file = root_path.join("file.bin");
+    fs::write(&file, &contents).unwrap();
+    let compressed = root_path.join("file.bin.zst");
+    ouch!("c", &file, &compressed);
+
+    let fifo = root_path.join("fifo");
+    let c_fifo = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(c_fifo.as_ptr(), 0o644) }, 0);
+
+    // Larger than the buffer of the pipe, so it's streamed while read
+    let reader = {
+        let fifo = fifo.clone();
+        thread::spawn(move || fs::read(fifo).unwrap())
+    };
+    ouch!("d", &compressed, "-o", &fifo);
+    assert_eq!(reader.join().unwrap(), contents);
+    assert!(fs::metadata(&fifo).unwrap().file_type().is_fifo());
+
+    // The standard output of the test is a pipe too
+    let output = crate::utils::cargo_bin()
+        .args(["decompress", "--quiet", "--yes"])
+        .arg(&compressed)
+        .args(["-o", "/dev/stdout"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert_eq!(output.stdout, contents);
+}
+
+#[test]
+fn compress_below_min_ratio() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let mut random = vec![0; 100_000];
+    SmallRng::seed_from_u64(0).fill(random.as_mut_slice());
+    let incompressible = root_path.join("random.bin");
+    fs::write(&incompressible, &random).unwrap();
+    let compressible = root_path.join("zeros.bin");
+    fs::write(&compressible, vec![0; 100_000]).unwrap();
+
+    let output = root_path.join("random.bin.zst");
+    crate::utils::cargo_bin()
+        .args(["compress", "--yes", "--min-ratio", "1.5"])
+        .arg(&incompressible)
+        .arg(&output)
+        .assert()
+        .code(ouch::EXIT_POOR_RATIO);
+    assert!(!output.exists());
+
+    let output = root_path.join("zeros.bin.zst");
+    ouch!("c", &compressible, &output, "--min-ratio", "1.5");
+    assert!(output.exists());
+
+    // Archives are stored without their compression formats
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::copy(&incompressible, dir.join("random.bin")).unwrap();
+    let output = root_path.join("archive.tar.gz");
+    ouch!(
+        "c",
+        &dir,
+        &output,
+        "--min-ratio",
+        "1.5",
+        "--min-ratio-fallback",
+        "store"
+    );
+    assert!(!output.exists());
+    let mut archive = tar::Archive::new(fs::File::open(root_path.join("archive.tar")).unwrap());
+    let mut entry = archive
+        .entries()
+        .unwrap()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.path().unwrap() == Path::new("dir/random.bin"))
+        .unwrap();
+    let mut content = vec![];
+    entry.read_to_end(&mut content).unwrap();
+    assert_eq!(content, random);
+
+    // And single files as a copy of their input
+    let out_dir = root_path.join("out");
+    fs::create_dir(&out_dir).unwrap();
+    ouch!(
+        "c",
+        &incompressible,
+        out_dir.join("random.bin.gz"),
+        "--min-ratio",
+        "1.5",
+        "--min-ratio-fallback",
+        "store"
+    );
+    assert!(!out_dir.join("random.bin.gz").exists());
+    assert_eq!(fs::read(out_dir.join("random.bin")).unwrap(), random);
+
+    // Which are the outputs of --stats-json, the input itself if the copy would be written on it
+    let stats_path = root_path.join("stats.json");
+    let stored_outputs = |output: &Path| {
+        ouch!(
+            "c",
+            &incompressible,
+            output,
+            "--min-ratio",
+            "1.5",
+            "--min-ratio-fallback",
+            "store",
+            "--stats-json",
+            &stats_path
+        );
+        let stats: serde_json::Value = serde_json::from_slice(&fs::read(&stats_path).unwrap()).unwrap();
+        stats["outputs"].clone()
+    };
+    let stored = out_dir.join("random.bin");
+    fs::remove_file(&stored).unwrap();
+    assert_eq!(
+        stored_outputs(&out_dir.join("random.bin.gz")),
+        serde_json::json!([stored.to_str().unwrap()])
+    );
+    assert_eq!(
+        stored_outputs(&root_path.join("random.bin.gz")),
+        serde_json::json!([incompressible.to_str().unwrap()])
+    );
+    assert_eq!(fs::read(&incompressible).unwrap(), random);
+}
+
+#[test]
+fn decompress_split_sevenz_volumes() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Incompressible, so the entry spans the boundary between volumes
+    let mut random = vec![0; 200_000];
+    SmallRng::seed_from_u64(0).fill(random.as_mut_slice());
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("random.bin"), &random).unwrap();
+    let archive = root_path.join("archive.7z");
+    ouch!("c", &dir, &archive);
+
+    let split = |count: usize| {
+        let data = fs::read(&archive).unwrap();
+        let chunk_size = data.len().div_ceil(count);
+        for (i, chunk) in data.chunks(chunk_size).enumerate() {
+            fs::write(root_path.join(format!("archive.7z.{:03}", i + 1)), chunk).unwrap();
+        }
+    };
+    split(2);
+    let first_volume = root_path.join("archive.7z.001");
+
+    let output = crate::utils::cargo_bin()
+        .args(["list", "--yes"])
+        .arg(&first_volume)
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    assert!(String::from_utf8(output.stdout).unwrap().contains("random.bin"));
+
+    let out_dir = root_path.join("out");
+    ouch!("d", &first_volume, "-d", &out_dir);
+    assert_eq!(fs::read(out_dir.join("dir/random.bin")).unwrap(), random);
+
+    let missing_volume_error = |volume: &str| {
+        let output = crate::utils::cargo_bin()
+            .args(["decompress", "--yes"])
+            .arg(&first_volume)
+            .arg("-d")
+            .arg(root_path.join("failed"))
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        assert!(String::from_utf8(output.stderr).unwrap().contains(volume));
+    };
+
+    // Both gaps and volumes missing at the end are named
+    split(3);
+    fs::remove_file(root_path.join("archive.7z.002")).unwrap();
+    missing_volume_error("archive.7z.002");
+
+    split(3);
+    fs::remove_file(root_path.join("archive.7z.003")).unwrap();
+    missing_volume_error("archive.7z.003");
+}
+
+#[test]
+fn entry_names_use_forward_slashes() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("a").join("b")).unwrap();
+    fs::write(dir.join("a").join("b").join("file"), "content").unwrap();
+
+    // Names are the same whatever the platform the archives are created on
+    let zip = root_path.join("archive.zip");
+    ouch!("c", &dir, &zip);
+    let archive = zip::ZipArchive::new(fs::File::open(&zip).unwrap()).unwrap();
+    assert!(archive.file_names().any(|name| name == "dir/a/b/file"));
+    assert!(archive.file_names().all(|name| !name.contains('\\')));
+
+    let sevenz = root_path.join("archive.7z");
+    ouch!("c", &dir, &sevenz);
+    let mut names = vec![];
+    sevenz_rust2::decompress_file_with_extract_fn(&sevenz, root_path, |entry, _, _| {
+        names.push(entry.name().to_owned());
+        Ok(true)
+    })
+    .unwrap();
+    assert!(names.iter().any(|name| name == "dir/a/b/file"));
+    assert!(names.iter().all(|name| !name.contains('\\')));
+
+    // And turned back into paths of the platform on extraction
+    for archive in [&zip, &sevenz] {
+        let out_dir = root_path.join("out");
+        ouch!("d", archive, "-d", &out_dir);
+        assert_eq!(
+            fs::read_to_string(out_dir.join("dir").join("a").join("b").join("file")).unwrap(),
+            "content"
+        );
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
+
+#[test]
+fn inputs_are_kept_unless_removed() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let file = root_path.join("file.txt");
+    fs::write(&file, "content").unwrap();
+    let compressed = root_path.join("file.txt.gz");
+
+    ouch!("c", &file, &compressed);
+    assert!(file.exists() && compressed.exists());
+    ouch!("d", &compressed, "-d", root_path.join("out"));
+    assert!(compressed.exists());
+
+    fs::remove_file(&compressed).unwrap();
+    ouch!("c", &file, &compressed, "--remove");
+    assert!(!file.exists() && compressed.exists());
+    ouch!("d", &compressed, "-d", root_path.join("removed"), "--remove");
+    assert!(!compressed.exists());
+    assert_eq!(
+        fs::read_to_string(root_path.join("removed/file.txt")).unwrap(),
+        "content"
+    );
+
+    // Inputs holding the output aren't removed along with it
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("file"), "content").unwrap();
+    let inner = root_path.join("inner");
+    fs::create_dir(&inner).unwrap();
+    ouch!("c", &dir, &inner, dir.join("archive.tar"), "--remove");
+    assert!(dir.join("archive.tar").exists());
+    assert!(!inner.exists());
+
+    // Nor inputs kept as the output by --min-ratio-fallback store, unlike the stored copies
+    let mut random = vec![0; 10_000];
+    SmallRng::seed_from_u64(0).fill(random.as_mut_slice());
+    let data = root_path.join("data.bin");
+    fs::write(&data, &random).unwrap();
+    let store = |output: PathBuf| {
+        ouch!(
+            "c",
+            &data,
+            &output,
+            "--min-ratio",
+            "2",
+            "--min-ratio-fallback",
+            "store",
+            "--remove"
+        );
+        assert!(!output.exists());
+    };
+    store(root_path.join("data.bin.gz"));
+    assert_eq!(fs::read(&data).unwrap(), random);
+    store(root_path.join("out/data.bin.gz"));
+    assert!(!data.exists());
+    assert_eq!(fs::read(root_path.join("out/data.bin")).unwrap(), random);
+}
+
+#[test]
+fn encrypted_sevenz_needs_password() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let file = root_path.join("secret.txt");
+    fs::write(&file, "content").unwrap();
+    let archive = root_path.join("archive.7z");
+    sevenz_rust2::compress_encrypted(&file, fs::File::create(&archive).unwrap(), "password".into()).unwrap();
+
+    let out_dir = root_path.join("out");
+    for args in [&["list"][..], &["decompress", "-d", out_dir.to_str().unwrap()]] {
+        let output = crate::utils::cargo_bin()
+            .args(args)
+            .arg(&archive)
+            .arg("--yes")
+            .assert()
+            .failure()
+            .get_output()
+            .clone();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("This 7z archive is encrypted"), "{stderr}");
+        assert!(stderr.contains("--password"), "{stderr}");
+    }
+
+    ouch!("d", &archive, "-d", &out_dir, "--password", "password");
+    assert_eq!(fs::read_to_string(out_dir.join("secret.txt")).unwrap(), "content");
+}
+
+#[test]
+fn list_slice_with_offset_and_limit() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    for i in 0..10 {
+        fs::write(dir.join(format!("file{i}")), "content").unwrap();
+    }
+
+    let list = |archive: &Path, args: &[&str]| -> Vec<String> {
+        let output = crate::utils::cargo_bin()
+            .args(["list", "--yes"])
+            .arg(archive)
+            .args(args)
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        // The first line names the archive
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(ToOwned::to_owned)
+            .collect()
+    };
+
+    for format in ["tar.gz", "zip"] {
+        let archive = root_path.join(format!("archive.{format}"));
+        ouch!("c", &dir, &archive);
+
+        let all = list(&archive, &[]);
+        assert_eq!(all.len(), 11);
+        assert_eq!(list(&archive, &["--offset", "3", "--limit", "4"]), all[3..7]);
+        assert_eq!(list(&archive, &["--offset", "8"]), all[8..]);
+        assert_eq!(list(&archive, &["--limit", "2"]), all[..2]);
+        assert_eq!(list(&archive, &["--offset", "20"]).len(), 0);
+        assert_eq!(list(&archive, &["--limit", "0"]).len(), 0);
+    }
+}
+
+#[test]
+fn absolute_and_remote_looking_entries_are_refused() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    // Set in the header directly, as the tar crate refuses absolute paths
+    let tar_with_entry = |name: &str| {
+        let mut header = tar::Header::new_gnu();
+        header.as_gnu_mut().unwrap().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(7);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(vec![]);
+        builder.append(&header, &b"content"[..]).unwrap();
+        builder.into_inner().unwrap()
+    };
+    let zip_with_entry = |name: &str| {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        writer.start_file(name, zip::write::FileOptions::default()).unwrap();
+        writer.write_all(b"content").unwrap();
+        writer.finish().unwrap().into_inner()
+    };
+
+    for (name, relative) in [("/abs/file", "abs/file"), ("host:file", "host:file")] {
+        for (format, data) in [("tar", tar_with_entry(name)), ("zip", zip_with_entry(name))] {
+            let archive = root_path.join(format!("archive.{format}"));
+            fs::write(&archive, data).unwrap();
+
+            let out_dir = root_path.join(format!("out-{format}"));
+            let output = crate::utils::cargo_bin()
+                .args(["decompress", "--yes"])
+                .arg(&archive)
+                .arg("-d")
+                .arg(&out_dir)
+                .assert()
+                .failure()
+                .get_output()
+                .clone();
+            let stderr = String::from_utf8(output.stderr).unwrap();
+            assert!(stderr.contains("Refusing to extract the entry"), "{stderr}");
+            assert!(stderr.contains(name), "{stderr}");
+
+            ouch!("d", &archive, "-d", &out_dir, "--force-local");
+            assert_eq!(fs::read_to_string(out_dir.join(relative)).unwrap(), "content");
+            fs::remove_dir_all(&out_dir).unwrap();
+        }
+    }
+}
+
+#[test]
+fn compression_stats_as_json() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("a"), "a".repeat(10_000)).unwrap();
+    fs::write(dir.join("b"), "b".repeat(500)).unwrap();
+
+    let archive = root_path.join("archive.tar.gz");
+    let stats_path = root_path.join("stats.json");
+    ouch!("c", &dir, &archive, "--stats-json", &stats_path, "--level", "6");
+    let stats: serde_json::Value = serde_json::from_slice(&fs::read(&stats_path).unwrap()).unwrap();
+
+    // The keys documented on `CompressionStats`
+    let keys: Vec<_> = stats.as_object().unwrap().keys().map(String::as_str).sorted().collect();
+    assert_eq!(
+        keys,
+        [
+            "duration_secs",
+            "entries",
+            "formats",
+            "input_size",
+            "output_size",
+            "outputs",
+            "parameters",
+            "ratio",
+            "throughput"
+        ]
+    );
+    let output_size = fs::metadata(&archive).unwrap().len();
+    assert_eq!(stats["input_size"], 10_500);
+    assert_eq!(stats["output_size"], output_size);
+    assert_eq!(stats["ratio"].as_f64().unwrap(), 10_500.0 / output_size as f64);
+    assert_eq!(stats["entries"], 3);
+    assert_eq!(stats["formats"], serde_json::json!(["tar", "gz"]));
+    assert_eq!(stats["outputs"], serde_json::json!([archive]));
+    assert_eq!(stats["parameters"], serde_json::json!({ "level": 6 }));
+    assert!(stats["duration_secs"].as_f64().unwrap() >= 0.0);
+    assert!(stats["throughput"].as_f64().unwrap() >= 0.0);
+
+    // Written to stdout with "-"
+    let output = crate::utils::cargo_bin()
+        .args(["compress", "--yes"])
+        .arg(dir.join("a"))
+        .arg(root_path.join("a.zst"))
+        .args(["--stats-json", "-"])
+        .assert()
+        .success()
+        .get_output()
+        .clone();
+    let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(stats["input_size"], 10_000);
+    assert_eq!(stats["entries"], 1);
+
+    // Outputs whose path isn't UTF-8 are written lossily
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::ffi::OsStrExt;
+
+        let archive = root_path.join(std::ffi::OsStr::from_bytes(b"invalid-\xFF.zst"));
+        let output = crate::utils::cargo_bin()
+            .args(["compress", "--yes"])
+            .arg(dir.join("a"))
+            .arg(&archive)
+            .args(["--stats-json", "-"])
+            .assert()
+            .success()
+            .get_output()
+            .clone();
+        let stats: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let lossy = archive.to_string_lossy();
+        assert_eq!(stats["outputs"], serde_json::json!([lossy]));
+    }
+}
+
+#[test]
+fn decompress_with_renamed_root() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let project = root_path.join("project-1.2.0");
+    fs::create_dir_all(project.join("src")).unwrap();
+    fs::write(project.join("src/main.rs"), "fn main() {}").unwrap();
+    let other = root_path.join("other");
+    fs::write(&other, "content").unwrap();
+
+    for format in ["tar.gz", "zip", "7z"] {
+        let archive = root_path.join(format!("project.{format}"));
+        ouch!("c", &project, &archive);
+        let out_dir = root_path.join(format!("out-{format}"));
+        ouch!("d", &archive, "-d", &out_dir, "--rename-root", "project");
+        assert_eq!(
+            fs::read_to_string(out_dir.join("project/src/main.rs")).unwrap(),
+            "fn main() {}"
+        );
+        assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 1);
+    }
+
+    // Archives with several roots have none to rename
+    let archive = root_path.join("multiple.tar");
+    ouch!("c", &project, &other, &archive);
+    let out_dir = root_path.join("out-multiple");
+    let output = crate::utils::cargo_bin()
+        .args(["decompress", "--yes"])
+        .arg(&archive)
+        .arg("-d")
+        .arg(&out_dir)
+        .args(["--rename-root", "project"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("It has 2 entries at the top level"), "{stderr}");
+    assert_eq!(fs::read_dir(&out_dir).unwrap().count(), 0);
+}
+
+#[test]
+fn compress_verified_against_source() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let dir = root_path.join("dir");
+    fs::create_dir_all(dir.join("empty")).unwrap();
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    let mut random = vec![0; 100_000];
+    SmallRng::seed_from_u64(0).fill(random.as_mut_slice());
+    fs::write(dir.join("sub/random.bin"), &random).unwrap();
+    fs::write(dir.join("text"), "content").unwrap();
+
+    for format in ["tar.gz", "zip", "7z"] {
+        let archive = root_path.join(format!("archive.{format}"));
+        ouch!("c", &dir, &archive, "--verify-against-source=content");
+        ouch!("c", &dir, &archive, "--name", "renamed", "--verify-against-source");
+    }
+    ouch!(
+        "c",
+        &dir,
+        root_path.join("volumes.tar"),
+        "--split-by-entry",
+        "60KB",
+        "--verify-against-source=content"
+    );
+    ouch!(
+        "c",
+        dir.join("text"),
+        root_path.join("text.zst"),
+        "--verify-against-source=content"
+    );
+}
+
+#[test]
+fn decompress_start_at_offset() {
+    let temp_dir = tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    let gzip_member = |data: &[u8]| {
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    };
+    let blocks: [&[u8]; 3] = [b"first block\n", b"second block\n", b"third block\n"];
+    let members: Vec<_> = blocks.iter().map(|block| gzip_member(block)).collect();
+    let content = blocks.concat();
+    let archive = root_path.join("blocked.txt.gz");
+    fs::write(&archive, members.concat()).unwrap();
+
+    // Without an index, the skipped bytes are decompressed and discarded
+    ouch!("d", &archive, "-d", root_path.join("sequential"), "--start-at", "5");
+    assert_eq!(
+        fs::read(root_path.join("sequential/blocked.txt")).unwrap(),
+        content[5..]
+    );
+    ouch!("d", &archive, "-d", root_path.join("past-end"), "--start-at", "1000");
+    assert!(fs::read(root_path.join("past-end/blocked.txt")).unwrap().is_empty());
+
+    // With an index, decompression starts at the member holding the offset, so a broken first
+    // member isn't even read
+    #[cfg(feature = "gzi")]
+    {
+        let mut index = 2u64.to_le_bytes().to_vec();
+        let (mut compressed, mut decompressed) = (0, 0);
+        for (member, block) in members.iter().zip(blocks).take(2) {
+            compressed += member.len() as u64;
+            decompressed += block.len() as u64;
+            index.extend(compressed.to_le_bytes());
+            index.extend(decompressed.to_le_bytes());
+        }
+        fs::write(root_path.join("blocked.txt.gz.gzi"), index).unwrap();
+        let broken = [vec![0; members[0].len()], members[1].clone(), members[2].clone()].concat();
+        fs::write(&archive, broken).unwrap();
+
+        let offset = blocks[0].len() + 3;
+        ouch!(
+            "d",
+            &archive,
+            "-d",
+            root_path.join("indexed"),
+            "--start-at",
+            offset.to_string()
+        );
+        assert_eq!(
+            fs::read(root_path.join("indexed/blocked.txt")).unwrap(),
+            content[offset..]
+        );
+        crate::utils::cargo_bin()
+            .args(["d", "--yes"])
+            .arg(&archive)
+            .arg("-d")
+            .arg(root_path.join("unindexed"))
+            .assert()
+            .failure();
+    }
+}
@@ -70,6 +70,16 @@ fn ui_test_err_decompress_missing_extension() {
     ui!(format!("{snapshot}-3"), run_ouch("ouch decompress b.unknown", dir));
 }
 
+#[test]
+fn ui_test_err_decompress_plain_text_file() {
+    let (_dropper, dir) = testdir().unwrap();
+
+    std::fs::write(dir.join("notes.txt"), "Not an archive, just some notes.\n").unwrap();
+
+    let snapshot = concat_snapshot_filename_rar_feature("ui_test_err_decompress_plain_text_file");
+    ui!(snapshot, run_ouch("ouch decompress notes.txt", dir));
+}
+
 #[test]
 fn ui_test_err_missing_files() {
     let (_dropper, dir) = testdir().unwrap();